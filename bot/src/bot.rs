@@ -2,6 +2,7 @@ use abstract_client::{AbstractClient, AccountSource, Environment};
 use carrot_app::{
     msg::{
         AppExecuteMsg, AppQueryMsg, CompoundStatus, CompoundStatusResponse, ExecuteMsg, QueryMsg,
+        RebalanceStatus, RebalanceStatusResponse,
     },
     AppInterface,
 };
@@ -295,6 +296,20 @@ impl Bot {
                     self.metrics.autocompounded_error_count.with(&label).inc();
                 }
             }
+            match rebalance_instance(&self.daemon, (id, addr)) {
+                // Successful rebalances, started grace periods are not counted
+                Ok(rebalanced) => self
+                    .metrics
+                    .rebalanced_count
+                    .with(&label)
+                    .inc_by(rebalanced),
+                Err(err) => {
+                    log!(
+                        Level::Error,
+                        "error ocurred during rebalance for {contract} carrot-app: {err:?}"
+                    );
+                }
+            }
         }
     }
 }
@@ -329,6 +344,46 @@ fn autocompound_instance(
     Ok(resp.status)
 }
 
+fn rebalance_instance(daemon: &Daemon, instance: (&str, &Addr)) -> anyhow::Result<u64> {
+    let (id, address) = instance;
+    let app = AppInterface::new(id, daemon.clone());
+    app.set_address(address);
+    use carrot_app::AppQueryMsgFns;
+    let resp: RebalanceStatusResponse = app.rebalance_status()?;
+    if !resp.permissionless {
+        return Ok(0);
+    }
+
+    // Out of range position either starts the grace period or gets rebalanced
+    let mut rebalanced = 0;
+    for position in resp.positions {
+        match position.status {
+            RebalanceStatus::OutOfRange {} | RebalanceStatus::Ready {} => {}
+            // Nothing to do until the grace period is over
+            RebalanceStatus::InRange {} | RebalanceStatus::GracePeriod(_) => continue,
+        }
+        let response = daemon.execute(
+            &ExecuteMsg::from(AppExecuteMsg::Rebalance {
+                position: Some(position.name),
+                max_spread: None,
+                belief_price0: None,
+                belief_price1: None,
+            }),
+            &[],
+            address,
+        )?;
+        // Only (re)started the grace period otherwise
+        if response
+            .event_attr_values("wasm-abstract", "out_of_range_since")
+            .is_empty()
+        {
+            rebalanced += 1;
+        }
+    }
+
+    Ok(rebalanced)
+}
+
 mod utils {
     use abstract_app::std::version_control::ModulesListResponse;
     use cosmos_sdk_proto::{
@@ -10,6 +10,7 @@ pub struct Metrics {
     pub autocompounded_count: IntCounterVec,
     pub autocompounded_not_ready_count: IntCounterVec,
     pub autocompounded_error_count: IntCounterVec,
+    pub rebalanced_count: IntCounterVec,
     pub contract_instances_to_autocompound: IntGauge,
     // balance of every instance
     pub contract_balance: IntGaugeVec,
@@ -51,6 +52,14 @@ impl Metrics {
             &["contract_version"],
         )
         .unwrap();
+        let rebalanced_count = IntCounterVec::new(
+            Opts::new(
+                "carrot_app_bot_rebalanced_count",
+                "Number of times out of range positions have been rebalanced",
+            ),
+            &["contract_version"],
+        )
+        .unwrap();
         let contract_instances_to_autocompound = IntGauge::new(
             "carrot_app_bot_contract_instances_to_autocompound",
             "Number of instances that are eligible to be compounded",
@@ -77,6 +86,9 @@ impl Metrics {
         registry
             .register(Box::new(autocompounded_error_count.clone()))
             .unwrap();
+        registry
+            .register(Box::new(rebalanced_count.clone()))
+            .unwrap();
         registry
             .register(Box::new(contract_instances_to_autocompound.clone()))
             .unwrap();
@@ -89,6 +101,7 @@ impl Metrics {
             autocompounded_count,
             autocompounded_not_ready_count,
             autocompounded_error_count,
+            rebalanced_count,
             contract_instances_to_autocompound,
             contract_balance,
         }
@@ -12,7 +12,7 @@ use cw_orch_osmosis_test_tube::osmosis_test_tube::osmosis_std::types::cosmos::au
 use dotenv::dotenv;
 
 use carrot_app::{
-    msg::{AppInstantiateMsg, CreatePositionMessage},
+    msg::{AppInstantiateMsg, CreatePositionMessage, PositionRange},
     state::AutocompoundRewardsConfig,
 };
 
@@ -23,8 +23,6 @@ pub struct CarrotAppInitData {
     pub funds: Vec<Coin>,
     pub denom0: String,
     pub denom1: String,
-    pub asset0: Coin,
-    pub asset1: Coin,
     pub swap_asset: AssetEntry,
 }
 
@@ -49,7 +47,7 @@ fn main() -> anyhow::Result<()> {
         amount: Uint128::new(6_000),
     }];
 
-    let app_data = usdc_usdc_ax::app_data(funds, 100_000_000_000_000, 100_000_000_000_000);
+    let app_data = usdc_usdc_ax::app_data(funds);
 
     // Give all authzs and create subaccount with app in single tx
     let mut msgs = utils::give_authorizations_msgs(&client, savings_app_addr, &app_data)?;
@@ -64,15 +62,27 @@ fn main() -> anyhow::Result<()> {
             min_gas_balance: Uint128::new(1000000),
             max_gas_balance: Uint128::new(3000000),
         },
+        rebalance_grace_period_seconds: None,
+        twap_config: None,
+        incentives_swap_allowlist: None,
+        autocompound_mode: None,
+        compound_ratio: None,
+        payout: None,
+        performance_fee: None,
+        autocompound_max_spread: None,
         create_position: Some(CreatePositionMessage {
-            lower_tick: app_data.lower_tick,
-            upper_tick: app_data.upper_tick,
+            name: None,
+            weight: None,
+            range: PositionRange::Ticks {
+                lower_tick: app_data.lower_tick,
+                upper_tick: app_data.upper_tick,
+            },
             funds: app_data.funds,
-            asset0: app_data.asset0,
-            asset1: app_data.asset1,
             max_spread: None,
             belief_price0: None,
             belief_price1: None,
+            token_min_amount0: None,
+            token_min_amount1: None,
         }),
     };
     let create_sub_account_message = utils::create_account_message(&client, init_msg)?;
@@ -85,7 +95,7 @@ fn main() -> anyhow::Result<()> {
 
 mod usdt_usdc {
     use abstract_app::objects::AssetEntry;
-    use cosmwasm_std::{Coin, Uint128};
+    use cosmwasm_std::Coin;
 
     use crate::{usdc_usdc_ax::USDC_NOBLE_ASSET, CarrotAppInitData};
 
@@ -97,11 +107,7 @@ mod usdt_usdc {
     // USDC
     pub const TOKEN1: &str = "ibc/498A0751C798A0D9A389AA3691123DADA57DAA4FE165D5C75894505B876BA6E4";
 
-    pub fn app_data(
-        funds: Vec<Coin>,
-        asset0_amount: u128,
-        asset1_amount: u128,
-    ) -> CarrotAppInitData {
+    pub fn app_data(funds: Vec<Coin>) -> CarrotAppInitData {
         CarrotAppInitData {
             pool_id: POOL_ID,
             lower_tick: LOWER_TICK,
@@ -109,14 +115,6 @@ mod usdt_usdc {
             funds,
             denom0: TOKEN0.to_owned(),
             denom1: TOKEN1.to_owned(),
-            asset0: Coin {
-                denom: TOKEN0.to_owned(),
-                amount: Uint128::new(asset0_amount),
-            },
-            asset1: Coin {
-                denom: TOKEN1.to_owned(),
-                amount: Uint128::new(asset1_amount),
-            },
             swap_asset: AssetEntry::new(USDC_NOBLE_ASSET),
         }
     }
@@ -124,7 +122,7 @@ mod usdt_usdc {
 
 mod usdc_usdc_ax {
     use abstract_app::objects::AssetEntry;
-    use cosmwasm_std::{Coin, Uint128};
+    use cosmwasm_std::Coin;
 
     use crate::CarrotAppInitData;
 
@@ -137,11 +135,7 @@ mod usdc_usdc_ax {
     const LOWER_TICK: i64 = -3700;
     const UPPER_TICK: i64 = 300;
 
-    pub fn app_data(
-        funds: Vec<Coin>,
-        asset0_amount: u128,
-        asset1_amount: u128,
-    ) -> CarrotAppInitData {
+    pub fn app_data(funds: Vec<Coin>) -> CarrotAppInitData {
         CarrotAppInitData {
             pool_id: USDC_AXL_POOL_ID,
             lower_tick: LOWER_TICK,
@@ -149,14 +143,6 @@ mod usdc_usdc_ax {
             funds,
             denom0: USDC_NOBLE.to_owned(),
             denom1: USDC_AXL.to_owned(),
-            asset0: Coin {
-                denom: USDC_NOBLE.to_owned(),
-                amount: Uint128::new(asset0_amount),
-            },
-            asset1: Coin {
-                denom: USDC_AXL.to_owned(),
-                amount: Uint128::new(asset1_amount),
-            },
             swap_asset: AssetEntry::new(USDC_NOBLE_ASSET),
         }
     }
@@ -0,0 +1,568 @@
+//! Concentrated liquidity math, mirroring the Osmosis implementation
+//! See https://docs.osmosis.zone/osmosis-core/modules/concentrated-liquidity#geometric-tick-spacing-with-additive-ranges
+
+use std::str::FromStr;
+
+use cosmwasm_std::{ensure, Decimal256, StdResult, Uint128, Uint256};
+
+use crate::{contract::AppResult, error::AppError};
+
+/// Smallest tick supported by Osmosis, maps to a price of 10^-12
+pub const MIN_TICK: i64 = -108_000_000;
+/// Biggest tick supported by Osmosis, maps to a price of 10^38
+pub const MAX_TICK: i64 = 342_000_000;
+
+/// Exponent of the additive increment between two ticks at a price of one
+const EXPONENT_AT_PRICE_ONE: i64 = -6;
+/// Amount of ticks it takes for the price to grow by a power of ten
+const GEOMETRIC_EXPONENT_INCREMENT_DISTANCE_IN_TICKS: i64 = 9_000_000;
+
+/// Returns 10^exponent, only valid for exponents in the [-18, 38] range
+fn pow10(exponent: i64) -> Decimal256 {
+    let power = Uint256::from(10u128).pow(exponent.unsigned_abs() as u32);
+    if exponent >= 0 {
+        Decimal256::from_ratio(power, 1u128)
+    } else {
+        Decimal256::from_ratio(1u128, power)
+    }
+}
+
+/// Converts a tick index into the price of token0 in token1
+pub fn tick_to_price(tick: i64) -> AppResult<Decimal256> {
+    ensure!(
+        (MIN_TICK..=MAX_TICK).contains(&tick),
+        AppError::TickOutOfBounds { tick }
+    );
+    if tick == 0 {
+        return Ok(Decimal256::one());
+    }
+
+    // Every 9_000_000 ticks the price grows by a power of ten,
+    // in between the price grows additively by a fixed increment
+    let geometric_exponent_delta = tick / GEOMETRIC_EXPONENT_INCREMENT_DISTANCE_IN_TICKS;
+    let mut exponent_at_current_tick = EXPONENT_AT_PRICE_ONE + geometric_exponent_delta;
+    if tick < 0 {
+        // The increment is 10 times smaller below the power of ten we are in
+        exponent_at_current_tick -= 1;
+    }
+    let num_additive_ticks =
+        tick - geometric_exponent_delta * GEOMETRIC_EXPONENT_INCREMENT_DISTANCE_IN_TICKS;
+
+    let base_price = pow10(geometric_exponent_delta);
+    if num_additive_ticks == 0 {
+        return Ok(base_price);
+    }
+    let additive_price = pow10(exponent_at_current_tick)
+        * Decimal256::from_ratio(num_additive_ticks.unsigned_abs(), 1u128);
+    let price = if num_additive_ticks > 0 {
+        base_price + additive_price
+    } else {
+        base_price - additive_price
+    };
+    Ok(price)
+}
+
+/// Converts the price of token0 in token1 into the biggest tick with a price lower or equal to it
+pub fn price_to_tick(price: Decimal256) -> AppResult<i64> {
+    ensure!(
+        price >= tick_to_price(MIN_TICK)? && price <= tick_to_price(MAX_TICK)?,
+        AppError::PriceOutOfBounds { price }
+    );
+    if price == Decimal256::one() {
+        return Ok(0);
+    }
+
+    // Find the power of ten the price is in
+    let mut geometric_exponent_delta: i64 = 0;
+    if price > Decimal256::one() {
+        while pow10(geometric_exponent_delta + 1) <= price {
+            geometric_exponent_delta += 1;
+        }
+    } else {
+        while price <= pow10(geometric_exponent_delta - 1) {
+            geometric_exponent_delta -= 1;
+        }
+    }
+    let base_price = pow10(geometric_exponent_delta);
+    let base_tick = geometric_exponent_delta * GEOMETRIC_EXPONENT_INCREMENT_DISTANCE_IN_TICKS;
+    if price == base_price {
+        return Ok(base_tick);
+    }
+
+    // Count the additive increments from the power of ten, rounding towards the lower price
+    let tick = if price > base_price {
+        let additive_increment = pow10(EXPONENT_AT_PRICE_ONE + geometric_exponent_delta);
+        let num_additive_ticks = ((price - base_price) / additive_increment).to_uint_floor();
+        base_tick + Uint128::try_from(num_additive_ticks)?.u128() as i64
+    } else {
+        let additive_increment = pow10(EXPONENT_AT_PRICE_ONE + geometric_exponent_delta - 1);
+        let num_additive_ticks = ((base_price - price) / additive_increment).to_uint_ceil();
+        base_tick - Uint128::try_from(num_additive_ticks)?.u128() as i64
+    };
+    Ok(tick)
+}
+
+/// Rounds the tick down to the closest multiple of the `tick_spacing`
+pub fn align_tick_down(tick: i64, tick_spacing: u64) -> i64 {
+    tick.div_euclid(tick_spacing as i64) * tick_spacing as i64
+}
+
+/// Rounds the tick up to the closest multiple of the `tick_spacing`
+pub fn align_tick_up(tick: i64, tick_spacing: u64) -> i64 {
+    let aligned = align_tick_down(tick, tick_spacing);
+    if aligned == tick {
+        aligned
+    } else {
+        aligned + tick_spacing as i64
+    }
+}
+
+/// Converts a tick index into the square root of the price of token0 in token1
+pub fn tick_to_sqrt_price(tick: i64) -> AppResult<Decimal256> {
+    Ok(tick_to_price(tick)?.sqrt())
+}
+
+/// Parses a decimal returned by Osmosis.
+/// Sqrt prices are stored with 36 decimals, everything past the 18th decimal gets truncated
+pub fn parse_osmosis_decimal(value: &str) -> StdResult<Decimal256> {
+    match value.split_once('.') {
+        Some((whole, fractional)) if fractional.len() > 18 => {
+            Decimal256::from_str(&format!("{whole}.{}", &fractional[..18]))
+        }
+        _ => Decimal256::from_str(value),
+    }
+}
+
+/// Liquidity provided by `amount0` of token0 between two sqrt prices
+fn liquidity0(amount0: Decimal256, sqrt_lower: Decimal256, sqrt_upper: Decimal256) -> Decimal256 {
+    amount0 * sqrt_lower * sqrt_upper / (sqrt_upper - sqrt_lower)
+}
+
+/// Liquidity provided by `amount1` of token1 between two sqrt prices
+fn liquidity1(amount1: Decimal256, sqrt_lower: Decimal256, sqrt_upper: Decimal256) -> Decimal256 {
+    amount1 / (sqrt_upper - sqrt_lower)
+}
+
+/// Amount of token0 held by `liquidity` between two sqrt prices
+fn amount0_delta(
+    liquidity: Decimal256,
+    sqrt_lower: Decimal256,
+    sqrt_upper: Decimal256,
+) -> Decimal256 {
+    liquidity * (sqrt_upper - sqrt_lower) / (sqrt_upper * sqrt_lower)
+}
+
+/// Amount of token1 held by `liquidity` between two sqrt prices
+fn amount1_delta(
+    liquidity: Decimal256,
+    sqrt_lower: Decimal256,
+    sqrt_upper: Decimal256,
+) -> Decimal256 {
+    liquidity * (sqrt_upper - sqrt_lower)
+}
+
+/// Square roots of the prices of the `[lower_tick, upper_tick)` range
+fn range_sqrt_prices(lower_tick: i64, upper_tick: i64) -> AppResult<(Decimal256, Decimal256)> {
+    ensure!(
+        lower_tick < upper_tick,
+        AppError::InvalidTickRange {
+            lower_tick,
+            upper_tick
+        }
+    );
+    Ok((
+        tick_to_sqrt_price(lower_tick)?,
+        tick_to_sqrt_price(upper_tick)?,
+    ))
+}
+
+/// Biggest liquidity that can be provided with `amount0` and `amount1`
+/// in the `[lower_tick, upper_tick)` range while the pool is at `sqrt_price`
+pub fn liquidity_for_amounts(
+    sqrt_price: Decimal256,
+    lower_tick: i64,
+    upper_tick: i64,
+    amount0: Decimal256,
+    amount1: Decimal256,
+) -> AppResult<Decimal256> {
+    let (sqrt_lower, sqrt_upper) = range_sqrt_prices(lower_tick, upper_tick)?;
+
+    // Below the range the position only holds token0, above the range only token1
+    let liquidity = if sqrt_price <= sqrt_lower {
+        liquidity0(amount0, sqrt_lower, sqrt_upper)
+    } else if sqrt_price < sqrt_upper {
+        liquidity0(amount0, sqrt_price, sqrt_upper).min(liquidity1(amount1, sqrt_lower, sqrt_price))
+    } else {
+        liquidity1(amount1, sqrt_lower, sqrt_upper)
+    };
+    Ok(liquidity)
+}
+
+/// Amounts of token0 and token1 held by `liquidity` in the `[lower_tick, upper_tick)` range
+/// while the pool is at `sqrt_price`
+pub fn amounts_for_liquidity(
+    sqrt_price: Decimal256,
+    lower_tick: i64,
+    upper_tick: i64,
+    liquidity: Decimal256,
+) -> AppResult<(Decimal256, Decimal256)> {
+    let (sqrt_lower, sqrt_upper) = range_sqrt_prices(lower_tick, upper_tick)?;
+
+    // Below the range the position only holds token0, above the range only token1
+    let sqrt_price = sqrt_price.clamp(sqrt_lower, sqrt_upper);
+
+    let amount0 = amount0_delta(liquidity, sqrt_price, sqrt_upper);
+    let amount1 = amount1_delta(liquidity, sqrt_lower, sqrt_price);
+    Ok((amount0, amount1))
+}
+
+/// Returns a range of the same width as `[lower_tick, upper_tick)`, centered on `current_tick`.
+/// Ticks of the new range are aligned to the `tick_spacing` of the pool.
+/// Near the ends of the supported ticks, the range gets shifted back between them
+pub fn recenter_range(
+    current_tick: i64,
+    lower_tick: i64,
+    upper_tick: i64,
+    tick_spacing: u64,
+) -> (i64, i64) {
+    let min_tick = align_tick_up(MIN_TICK, tick_spacing);
+    let max_tick = align_tick_down(MAX_TICK, tick_spacing);
+    let width = upper_tick - lower_tick;
+    let new_lower_tick = align_tick_down(current_tick - width / 2, tick_spacing)
+        .min(max_tick - width)
+        .max(min_tick);
+    (new_lower_tick, (new_lower_tick + width).min(max_tick))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ticks and prices from the Osmosis tick conversion tests
+    const TICK_PRICE_VECTORS: [(i64, &str); 10] = [
+        (MIN_TICK, "0.000000000001"),
+        (-20_594_000, "0.007406"),
+        (-9_000_000, "0.1"),
+        (-1, "0.9999999"),
+        (0, "1"),
+        (1, "1.000001"),
+        (9_000_000, "10"),
+        (36_650_010, "16500.1"),
+        (38_035_000, "30350"),
+        (MAX_TICK, "100000000000000000000000000000000000000"),
+    ];
+
+    // Deterministic pseudo-random numbers, linear congruential generator from Knuth's MMIX
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            self.0 >> 11
+        }
+
+        fn in_range(&mut self, range: std::ops::RangeInclusive<i64>) -> i64 {
+            let len = (range.end() - range.start() + 1) as u64;
+            range.start() + (self.next() % len) as i64
+        }
+    }
+
+    // Ticks spread over the whole range, the stride is prime so all the additive offsets get covered
+    fn sample_ticks() -> impl Iterator<Item = i64> {
+        (MIN_TICK..=MAX_TICK).step_by(999_983).chain([
+            MIN_TICK + 1,
+            -1,
+            0,
+            1,
+            MAX_TICK - 1,
+            MAX_TICK,
+        ])
+    }
+
+    fn dec(value: &str) -> Decimal256 {
+        Decimal256::from_str(value).unwrap()
+    }
+
+    fn assert_close(result: Decimal256, expected: Decimal256, tolerance: &str) {
+        assert!(
+            result.abs_diff(expected) <= dec(tolerance),
+            "{result} is not close enough to {expected}"
+        );
+    }
+
+    #[test]
+    fn tick_to_price_vectors() {
+        for (tick, price) in TICK_PRICE_VECTORS {
+            assert_eq!(tick_to_price(tick).unwrap(), dec(price), "tick {tick}");
+        }
+    }
+
+    #[test]
+    fn price_to_tick_vectors() {
+        for (tick, price) in TICK_PRICE_VECTORS {
+            assert_eq!(price_to_tick(dec(price)).unwrap(), tick, "price {price}");
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        assert_eq!(
+            tick_to_price(MIN_TICK - 1).unwrap_err(),
+            AppError::TickOutOfBounds { tick: MIN_TICK - 1 }
+        );
+        assert_eq!(
+            tick_to_price(MAX_TICK + 1).unwrap_err(),
+            AppError::TickOutOfBounds { tick: MAX_TICK + 1 }
+        );
+        let price = dec("0.0000000000009");
+        assert_eq!(
+            price_to_tick(price).unwrap_err(),
+            AppError::PriceOutOfBounds { price }
+        );
+    }
+
+    #[test]
+    fn tick_price_roundtrip() {
+        for tick in sample_ticks() {
+            let price = tick_to_price(tick).unwrap();
+            assert_eq!(price_to_tick(price).unwrap(), tick, "tick {tick}");
+        }
+    }
+
+    #[test]
+    fn tick_price_roundtrip_around_powers_of_ten() {
+        // Every tick around the points where the price increment changes
+        let boundaries =
+            (MIN_TICK..=MAX_TICK).step_by(GEOMETRIC_EXPONENT_INCREMENT_DISTANCE_IN_TICKS as usize);
+        for boundary in boundaries {
+            let ticks = (boundary - 500).max(MIN_TICK)..=(boundary + 500).min(MAX_TICK);
+            for tick in ticks {
+                let price = tick_to_price(tick).unwrap();
+                assert_eq!(price_to_tick(price).unwrap(), tick, "tick {tick}");
+            }
+        }
+    }
+
+    #[test]
+    fn tick_price_roundtrip_random() {
+        let mut rng = Lcg(42);
+        for _ in 0..20_000 {
+            let tick = rng.in_range(MIN_TICK..=MAX_TICK);
+            let price = tick_to_price(tick).unwrap();
+            assert_eq!(price_to_tick(price).unwrap(), tick, "tick {tick}");
+        }
+    }
+
+    #[test]
+    fn price_between_ticks_rounds_down() {
+        for tick in sample_ticks().filter(|tick| *tick < MAX_TICK) {
+            let price = tick_to_price(tick).unwrap();
+            let next_price = tick_to_price(tick + 1).unwrap();
+            assert!(price < next_price, "tick {tick}");
+
+            let atomics_between = (next_price - price).atomics() / Uint256::from(2u128);
+            let price_between = price + Decimal256::new(atomics_between.max(Uint256::one()));
+            if price_between < next_price {
+                assert_eq!(price_to_tick(price_between).unwrap(), tick, "tick {tick}");
+            }
+        }
+    }
+
+    #[test]
+    fn tick_alignment() {
+        assert_eq!(align_tick_down(150, 100), 100);
+        assert_eq!(align_tick_up(150, 100), 200);
+        assert_eq!(align_tick_down(-150, 100), -200);
+        assert_eq!(align_tick_up(-150, 100), -100);
+        assert_eq!(align_tick_down(-200, 100), -200);
+        assert_eq!(align_tick_up(-200, 100), -200);
+    }
+
+    #[test]
+    fn sqrt_price_vectors() {
+        assert_eq!(tick_to_sqrt_price(0).unwrap(), Decimal256::one());
+        assert_eq!(tick_to_sqrt_price(18_000_000).unwrap(), dec("10"));
+        assert_eq!(tick_to_sqrt_price(-18_000_000).unwrap(), dec("0.1"));
+        assert_close(
+            tick_to_sqrt_price(9_000_000).unwrap(),
+            dec("3.162277660168379332"),
+            "0.000000000000000001",
+        );
+    }
+
+    #[test]
+    fn parse_sqrt_price() {
+        assert_eq!(
+            parse_osmosis_decimal("1.000000499999875000062499960937527343").unwrap(),
+            dec("1.000000499999875000")
+        );
+        assert_eq!(parse_osmosis_decimal("70.5").unwrap(), dec("70.5"));
+    }
+
+    // Liquidity and amounts from the Osmosis concentrated liquidity math tests,
+    // price range of 4545-5500 with the current price at 5000
+    const SQRT_PRICE_4545: &str = "67.416615162732695594";
+    const SQRT_PRICE_5000: &str = "70.710678118654752440";
+    const SQRT_PRICE_5500: &str = "74.161984870956629487";
+
+    #[test]
+    fn liquidity_vectors() {
+        assert_close(
+            liquidity0(dec("1000000"), dec(SQRT_PRICE_5000), dec(SQRT_PRICE_5500)),
+            dec("1519437308.014768571720923239"),
+            "0.000000001",
+        );
+        assert_close(
+            liquidity1(
+                dec("5000000000"),
+                dec(SQRT_PRICE_4545),
+                dec(SQRT_PRICE_5000),
+            ),
+            dec("1517882343.751510418088349649"),
+            "0.000000001",
+        );
+    }
+
+    #[test]
+    fn amount_vectors() {
+        let liquidity = dec("1517882343.751510418088349649");
+        assert_close(
+            amount0_delta(liquidity, dec(SQRT_PRICE_5000), dec(SQRT_PRICE_5500)),
+            dec("998976.618347426388356629"),
+            "0.000000001",
+        );
+        assert_close(
+            amount1_delta(liquidity, dec(SQRT_PRICE_4545), dec(SQRT_PRICE_5000)),
+            dec("5000000000"),
+            "0.000000001",
+        );
+    }
+
+    #[test]
+    fn amounts_outside_of_range() {
+        let (lower_tick, upper_tick) = (-1000, 1000);
+        let liquidity = dec("1000000");
+
+        // Below the range only token0
+        let below = tick_to_sqrt_price(-2000).unwrap();
+        let (amount0, amount1) =
+            amounts_for_liquidity(below, lower_tick, upper_tick, liquidity).unwrap();
+        assert!(!amount0.is_zero());
+        assert!(amount1.is_zero());
+
+        // Above the range only token1
+        let above = tick_to_sqrt_price(2000).unwrap();
+        let (amount0, amount1) =
+            amounts_for_liquidity(above, lower_tick, upper_tick, liquidity).unwrap();
+        assert!(amount0.is_zero());
+        assert!(!amount1.is_zero());
+
+        assert_eq!(
+            amounts_for_liquidity(below, upper_tick, lower_tick, liquidity).unwrap_err(),
+            AppError::InvalidTickRange {
+                lower_tick: upper_tick,
+                upper_tick: lower_tick
+            }
+        );
+    }
+
+    #[test]
+    fn liquidity_amounts_roundtrip() {
+        let amount0 = dec("1000000");
+        let amount1 = dec("2500000");
+        for (lower_tick, upper_tick) in [(-100_000, 10_000), (-1000, 1000), (-37000, 1000)] {
+            for current_tick in [-200_000, -50_000, -500, 0, 500, 5000, 50_000] {
+                let sqrt_price = tick_to_sqrt_price(current_tick).unwrap();
+                let liquidity =
+                    liquidity_for_amounts(sqrt_price, lower_tick, upper_tick, amount0, amount1)
+                        .unwrap();
+                let (used0, used1) =
+                    amounts_for_liquidity(sqrt_price, lower_tick, upper_tick, liquidity).unwrap();
+
+                // Never uses more than provided, one of the amounts gets used entirely
+                let tolerance = dec("0.000001");
+                assert!(used0 <= amount0 + tolerance, "{current_tick}");
+                assert!(used1 <= amount1 + tolerance, "{current_tick}");
+                assert!(
+                    used0.abs_diff(amount0) <= tolerance || used1.abs_diff(amount1) <= tolerance,
+                    "{current_tick}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn liquidity_amounts_roundtrip_random() {
+        let mut rng = Lcg(7);
+        for _ in 0..5_000 {
+            let lower_tick = rng.in_range(-30_000_000..=30_000_000);
+            let upper_tick = lower_tick + rng.in_range(1..=5_000_000);
+            let width = upper_tick - lower_tick;
+            let current_tick = rng.in_range(lower_tick - width..=upper_tick + width);
+            let amount0 =
+                Decimal256::from_ratio(rng.in_range(1_000_000..=1_000_000_000_000) as u128, 1u128);
+            let amount1 =
+                Decimal256::from_ratio(rng.in_range(1_000_000..=1_000_000_000_000) as u128, 1u128);
+
+            let sqrt_price = tick_to_sqrt_price(current_tick).unwrap();
+            let liquidity =
+                liquidity_for_amounts(sqrt_price, lower_tick, upper_tick, amount0, amount1)
+                    .unwrap();
+            let (used0, used1) =
+                amounts_for_liquidity(sqrt_price, lower_tick, upper_tick, liquidity).unwrap();
+
+            // Never uses more than provided, one of the amounts gets used entirely
+            let case = format!("[{lower_tick}, {upper_tick}) at {current_tick}");
+            let tolerance0 = amount0 * dec("0.000000001");
+            let tolerance1 = amount1 * dec("0.000000001");
+            assert!(used0 <= amount0 + tolerance0, "{case}");
+            assert!(used1 <= amount1 + tolerance1, "{case}");
+            assert!(
+                used0.abs_diff(amount0) <= tolerance0 || used1.abs_diff(amount1) <= tolerance1,
+                "{case}"
+            );
+        }
+    }
+
+    #[test]
+    fn recenter_range_stays_in_bounds() {
+        // Centered on the current tick, aligned to the tick spacing
+        assert_eq!(recenter_range(5_050, -1000, 1000, 100), (4_000, 6_000));
+        assert_eq!(recenter_range(-5_050, -1000, 1000, 100), (-6_100, -4_100));
+
+        // Shifted back between the supported ticks
+        assert_eq!(
+            recenter_range(MAX_TICK - 10, -1000, 1000, 100),
+            (MAX_TICK - 2000, MAX_TICK)
+        );
+        assert_eq!(
+            recenter_range(MIN_TICK + 10, -1000, 1000, 100),
+            (MIN_TICK, MIN_TICK + 2000)
+        );
+        assert_eq!(
+            recenter_range(MIN_TICK + 10, -1050, 950, 300),
+            (
+                align_tick_up(MIN_TICK, 300),
+                align_tick_up(MIN_TICK, 300) + 2000
+            )
+        );
+
+        let mut rng = Lcg(3);
+        for _ in 0..10_000 {
+            let tick_spacing = [1, 10, 100, 1000][rng.in_range(0..=3) as usize];
+            let lower_tick = align_tick_down(rng.in_range(MIN_TICK..=MAX_TICK - 1), tick_spacing);
+            let upper_tick = align_tick_down(rng.in_range(lower_tick + 1..=MAX_TICK), tick_spacing)
+                .max(lower_tick + tick_spacing as i64);
+            let current_tick = rng.in_range(MIN_TICK..=MAX_TICK);
+            let (new_lower_tick, new_upper_tick) =
+                recenter_range(current_tick, lower_tick, upper_tick, tick_spacing);
+            assert!(MIN_TICK <= new_lower_tick && new_upper_tick <= MAX_TICK);
+            assert_eq!(new_lower_tick % tick_spacing as i64, 0);
+            assert_eq!(new_upper_tick % tick_spacing as i64, 0);
+            assert_eq!(new_upper_tick - new_lower_tick, upper_tick - lower_tick);
+        }
+    }
+}
@@ -7,8 +7,10 @@ use crate::{
     handlers,
     msg::{AppExecuteMsg, AppInstantiateMsg, AppMigrateMsg, AppQueryMsg},
     replies::{
-        add_to_position_reply, create_position_reply, withdraw_to_asset_reply, ADD_TO_POSITION_ID,
-        CREATE_POSITION_ID, WITHDRAW_TO_ASSET_ID,
+        add_to_position_reply, create_position_reply, migrate_pool_reply, rebalance_reply,
+        withdraw_swaps_reply, withdraw_to_asset_reply, withdraw_to_recipient_reply, zap_reply,
+        ADD_TO_POSITION_ID, CREATE_POSITION_ID, MIGRATE_POOL_ID, REBALANCE_ID, WITHDRAW_SWAPS_ID,
+        WITHDRAW_TO_ASSET_ID, WITHDRAW_TO_RECIPIENT_ID, ZAP_ID,
     },
 };
 
@@ -39,6 +41,11 @@ const APP: App = App::new(APP_ID, APP_VERSION, None)
         (CREATE_POSITION_ID, create_position_reply),
         (ADD_TO_POSITION_ID, add_to_position_reply),
         (WITHDRAW_TO_ASSET_ID, withdraw_to_asset_reply),
+        (REBALANCE_ID, rebalance_reply),
+        (WITHDRAW_SWAPS_ID, withdraw_swaps_reply),
+        (WITHDRAW_TO_RECIPIENT_ID, withdraw_to_recipient_reply),
+        (MIGRATE_POOL_ID, migrate_pool_reply),
+        (ZAP_ID, zap_reply),
     ])
     .with_dependencies(&[DEX_DEPENDENCY]);
 
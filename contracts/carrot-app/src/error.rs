@@ -1,7 +1,12 @@
 use abstract_app::sdk::AbstractSdkError;
 use abstract_app::AppError as AbstractAppError;
-use abstract_app::{objects::ans_host::AnsHostError, std::AbstractError};
-use cosmwasm_std::{Coin, ConversionOverflowError, Decimal, StdError};
+use abstract_app::{
+    objects::{ans_host::AnsHostError, AssetEntry},
+    std::AbstractError,
+};
+use cosmwasm_std::{
+    Coin, ConversionOverflowError, Decimal, Decimal256, StdError, Uint128, Uint256, Uint64,
+};
 use cw_asset::{AssetError, AssetInfo};
 use cw_controllers::AdminError;
 use cw_utils::ParseReplyError;
@@ -45,6 +50,9 @@ pub enum AppError {
     #[error("Unauthorized")]
     Unauthorized {},
 
+    #[error("App is paused")]
+    Paused {},
+
     #[error("Wrong denom deposited, expected exactly {expected}, got {got:?}")]
     DepositError { expected: AssetInfo, got: Vec<Coin> },
 
@@ -63,12 +71,36 @@ pub enum AppError {
     #[error("No rewards for autocompound")]
     NoRewards {},
 
+    #[error("Autocompound is on cooldown for {seconds_left} more seconds")]
+    AutocompoundCooldown { seconds_left: Uint64 },
+
     #[error("Reward configuration error: {0}")]
     RewardConfigError(String),
 
+    #[error("TWAP configuration error: {0}")]
+    TwapConfigError(String),
+
+    #[error("Payout configuration error: {0}")]
+    PayoutConfigError(String),
+
+    #[error("Performance fee error: {0}")]
+    PerformanceFeeError(String),
+
+    #[error("Rebalance configuration error: {0}")]
+    RebalanceConfigError(String),
+
+    #[error("Autocompound max spread has to be below 1, got {max_spread}")]
+    InvalidAutocompoundMaxSpread { max_spread: Decimal },
+
+    #[error("Pool price {price} deviates too much from its TWAP {twap}")]
+    TwapDeviation { price: Decimal, twap: Decimal },
+
     #[error("Position already exists. Please withdraw all funds before creating a new position")]
     PositionExists {},
 
+    #[error("Position {position_id} can't be imported: {reason}")]
+    ImportPositionError { position_id: u64, reason: String },
+
     #[error("Operation exceeds max spread limit, price: {price}")]
     MaxSpreadAssertion { price: Decimal },
 
@@ -77,4 +109,59 @@ pub enum AppError {
 
     #[error("Not enough liquidity for withdraw to asset")]
     NotEnoughForWithdrawTo {},
+
+    #[error("Received {received}, less than the minimum of {min_receive}")]
+    MinReceive {
+        received: Uint128,
+        min_receive: Uint128,
+    },
+
+    #[error("Tick {tick} is outside of the supported tick range")]
+    TickOutOfBounds { tick: i64 },
+
+    #[error("Invalid tick range, lower tick {lower_tick} has to be below upper tick {upper_tick}")]
+    InvalidTickRange { lower_tick: i64, upper_tick: i64 },
+
+    #[error("Price {price} is outside of the supported price range")]
+    PriceOutOfBounds { price: Decimal256 },
+
+    #[error("Spot price percentage has to be between 0 and 1, got {percentage}")]
+    InvalidSpotPercentage { percentage: Decimal },
+
+    #[error("Position is in range, no rebalance needed")]
+    PositionInRange {},
+
+    #[error("Position can be rebalanced by anyone in {seconds_left} seconds")]
+    RebalanceGracePeriod { seconds_left: Uint64 },
+
+    #[error("Position weight has to be bigger than zero")]
+    ZeroWeight {},
+
+    #[error("Withdraw amount is specific to a position, please provide the position name")]
+    PositionNameRequired {},
+
+    #[error("Withdraw percentage has to be between 1 and 10000 bps, got {bps}")]
+    InvalidWithdrawPercentage { bps: u16 },
+
+    #[error("Withdraw value has to be bigger than zero")]
+    ZeroWithdrawValue {},
+
+    #[error("Withdraw value {value} exceeds the position value {position_value}")]
+    WithdrawValueTooHigh {
+        value: Uint128,
+        position_value: Uint256,
+    },
+
+    #[error("App already uses pool {pool_id}")]
+    SamePool { pool_id: u64 },
+
+    #[error("{asset} is not an asset of the pool")]
+    NotPoolAsset { asset: AssetEntry },
+
+    #[error("Liquidity provision doesn't meet the minimum amounts, token0: {token_min_amount0}, token1: {token_min_amount1}: {reason}")]
+    LiquiditySlippage {
+        token_min_amount0: Uint128,
+        token_min_amount1: Uint128,
+        reason: String,
+    },
 }
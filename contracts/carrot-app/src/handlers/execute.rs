@@ -1,17 +1,35 @@
-use super::swap_helpers::{swap_msg, swap_to_enter_position};
+use super::instantiate::{check_config, resolve_pool_config};
+use super::swap_helpers::{
+    allowlisted_incentives, assert_swap_twap, ratio_for_range, route_swap_msgs, route_to_asset,
+    swap_msg, swap_route, swap_to_enter_position, zap_msgs,
+};
 use crate::{
+    cl_math::{parse_osmosis_decimal, price_to_tick, recenter_range},
     contract::{App, AppResult, OSMOSIS},
     error::AppError,
-    helpers::{get_balance, get_user, nonpayable},
-    msg::{AppExecuteMsg, CreatePositionMessage, ExecuteMsg, SwapToAsset},
-    replies::{ADD_TO_POSITION_ID, CREATE_POSITION_ID, WITHDRAW_TO_ASSET_ID},
+    helpers::{
+        forward_to_recipient, get_balance, get_user, nonpayable, pool_funds, pool_value,
+        query_pool, query_twap, range_to_ticks,
+    },
+    msg::{
+        AppExecuteMsg, CompoundStatus, CreatePositionMessage, ExecuteMsg, OptionalUpdate,
+        RebalanceStatus, SwapToAsset, WithdrawAmount, MAX_BPS,
+    },
+    replies::{
+        ADD_TO_POSITION_ID, CREATE_POSITION_ID, MIGRATE_POOL_ID, REBALANCE_ID, WITHDRAW_SWAPS_ID,
+        WITHDRAW_TO_ASSET_ID, WITHDRAW_TO_RECIPIENT_ID,
+    },
     state::{
-        assert_contract, AutocompoundRewardsConfig, CarrotPosition, Config, CONFIG,
-        TEMP_WITHDRAW_TO_ASSET,
+        add_compounded, add_principal, assert_contract, push_compound_event, push_pending_position,
+        reduce_principal, AutocompoundMode, AutocompoundRewardsConfig, CarrotPosition,
+        CompoundEvent, Config, MigratePoolParams, MigratedPosition, PayoutConfig, PendingPosition,
+        PerformanceFee, RebalanceParams, TwapConfig, WithdrawToAsset, WithdrawToRecipient,
+        ZapTarget, CONFIG, DEFAULT_POSITION, DEFAULT_WEIGHT, LAST_COMPOUND, TEMP_MIGRATE_POOL,
+        TEMP_REBALANCE, TEMP_WITHDRAW_RECIPIENT, TEMP_WITHDRAW_TO_ASSET,
     },
 };
 use abstract_app::{
-    objects::AnsAsset,
+    objects::{AnsAsset, AssetEntry},
     sdk::{
         features::{AbstractNameService, AbstractResponse},
         AuthZ, AuthZInterface, Resolve,
@@ -19,15 +37,15 @@ use abstract_app::{
 };
 use abstract_dex_adapter::DexInterface;
 use cosmwasm_std::{
-    to_json_binary, Addr, Coin, CosmosMsg, Decimal, Decimal256, Deps, DepsMut, Env, MessageInfo,
-    SubMsg, Uint128, Uint256, Uint64, WasmMsg,
+    ensure, to_json_binary, Addr, Coin, CosmosMsg, Decimal, Decimal256, Deps, DepsMut, Env,
+    MessageInfo, StdError, StdResult, SubMsg, Uint128, Uint256, Uint64, WasmMsg,
 };
-use cw_asset::Asset;
+use cw_asset::{Asset, AssetInfo};
 use osmosis_std::{
     try_proto_to_cosmwasm_coins,
     types::osmosis::concentratedliquidity::v1beta1::{
-        MsgAddToPosition, MsgCollectIncentives, MsgCollectSpreadRewards, MsgCreatePosition,
-        MsgWithdrawPosition,
+        ConcentratedliquidityQuerier, MsgAddToPosition, MsgCollectIncentives,
+        MsgCollectSpreadRewards, MsgCreatePosition, MsgWithdrawPosition, Pool,
     },
 };
 use std::str::FromStr;
@@ -45,11 +63,28 @@ pub fn execute_handler(
         AppExecuteMsg::UpdateConfig {
             autocompound_cooldown_seconds,
             autocompound_rewards_config,
+            rebalance_grace_period_seconds,
+            twap_config,
+            incentives_swap_allowlist,
+            autocompound_mode,
+            compound_ratio,
+            payout,
+            performance_fee,
+            autocompound_max_spread,
         } => update_config(
             deps,
+            info,
             app,
             autocompound_cooldown_seconds,
             autocompound_rewards_config,
+            rebalance_grace_period_seconds,
+            twap_config,
+            incentives_swap_allowlist,
+            autocompound_mode,
+            compound_ratio,
+            payout,
+            performance_fee,
+            autocompound_max_spread,
         ),
         AppExecuteMsg::CreatePosition(create_position_msg) => {
             create_position(deps, env, info, app, create_position_msg)
@@ -59,6 +94,8 @@ pub fn execute_handler(
             max_spread,
             belief_price0,
             belief_price1,
+            token_min_amount0,
+            token_min_amount1,
         } => deposit(
             deps,
             env,
@@ -67,21 +104,79 @@ pub fn execute_handler(
             max_spread,
             belief_price0,
             belief_price1,
+            token_min_amount0,
+            token_min_amount1,
             app,
         ),
-        AppExecuteMsg::Withdraw { amount, swap_to } => {
-            withdraw(deps, env, info, amount, swap_to, app)
-        }
+        AppExecuteMsg::Withdraw {
+            amount,
+            swap_to,
+            position,
+            recipient,
+        } => withdraw(deps, env, info, amount, swap_to, position, recipient, app),
         AppExecuteMsg::Autocompound {} => autocompound(deps, env, info, app),
+        AppExecuteMsg::Harvest { swap_to } => harvest(deps, env, info, swap_to, app),
+        AppExecuteMsg::Rebalance {
+            position,
+            max_spread,
+            belief_price0,
+            belief_price1,
+        } => rebalance(
+            deps,
+            env,
+            info,
+            position,
+            max_spread,
+            belief_price0,
+            belief_price1,
+            app,
+        ),
+        AppExecuteMsg::Reconcile {} => reconcile(deps, env, info, app),
+        AppExecuteMsg::ImportPosition { position_id } => {
+            import_position(deps, env, info, app, position_id)
+        }
+        AppExecuteMsg::MigratePool {
+            new_pool_id,
+            range,
+            max_spread,
+            token_min_amount0,
+            token_min_amount1,
+        } => migrate_pool(
+            deps,
+            env,
+            info,
+            app,
+            new_pool_id,
+            MigratedPosition {
+                range,
+                max_spread,
+                token_min_amount0,
+                token_min_amount1,
+            },
+        ),
+        AppExecuteMsg::Pause {} => set_paused(deps, info, app, true),
+        AppExecuteMsg::Unpause {} => set_paused(deps, info, app, false),
+        AppExecuteMsg::EmergencyExit {} => emergency_exit(deps, env, info, app),
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_config(
     deps: DepsMut,
+    info: MessageInfo,
     app: App,
     autocompound_cooldown_seconds: Option<Uint64>,
     autocompound_rewards_config: Option<AutocompoundRewardsConfig>,
+    rebalance_grace_period_seconds: Option<OptionalUpdate<Uint64>>,
+    twap_config: Option<OptionalUpdate<TwapConfig>>,
+    incentives_swap_allowlist: Option<Vec<AssetEntry>>,
+    autocompound_mode: Option<AutocompoundMode>,
+    compound_ratio: Option<Decimal>,
+    payout: Option<OptionalUpdate<PayoutConfig>>,
+    performance_fee: Option<OptionalUpdate<PerformanceFee>>,
+    autocompound_max_spread: Option<Decimal>,
 ) -> AppResult {
+    app.admin.assert_admin(deps.as_ref(), &info.sender)?;
     let mut config = CONFIG.load(deps.storage)?;
 
     if let Some(new_rewards_config) = autocompound_rewards_config {
@@ -112,6 +207,52 @@ fn update_config(
     if let Some(new_autocompound_cooldown) = autocompound_cooldown_seconds {
         config.autocompound_cooldown_seconds = new_autocompound_cooldown;
     }
+    if let Some(rebalance_grace_period_update) = rebalance_grace_period_seconds {
+        config.rebalance_grace_period_seconds = rebalance_grace_period_update.into_option();
+        config.check_rebalance()?;
+    }
+    if let Some(twap_config_update) = twap_config {
+        if let OptionalUpdate::Set(new_twap_config) = &twap_config_update {
+            new_twap_config.check()?;
+        }
+        config.twap_config = twap_config_update.into_option();
+    }
+    if let Some(new_incentives_swap_allowlist) = incentives_swap_allowlist {
+        config.incentives_swap_allowlist = new_incentives_swap_allowlist;
+        // Check incentives can be swapped into the pool assets
+        let ans_host = app.ans_host(deps.as_ref())?;
+        for incentive in &config.incentives_swap_allowlist {
+            swap_route(deps.as_ref(), &ans_host, &config, incentive)?;
+        }
+    }
+    if let Some(new_autocompound_mode) = autocompound_mode {
+        new_autocompound_mode.check(deps.as_ref(), &app.ans_host(deps.as_ref())?)?;
+        config.autocompound_mode = new_autocompound_mode;
+    }
+    if compound_ratio.is_some() || payout.is_some() {
+        if let Some(new_compound_ratio) = compound_ratio {
+            config.compound_ratio = new_compound_ratio;
+        }
+        if let Some(payout_update) = payout {
+            config.payout = payout_update.into_option();
+        }
+        config.check_payout(deps.as_ref(), &app.ans_host(deps.as_ref())?)?;
+    }
+    if let Some(performance_fee_update) = performance_fee {
+        if let OptionalUpdate::Set(new_performance_fee) = &performance_fee_update {
+            new_performance_fee.check(deps.as_ref())?;
+        }
+        config.performance_fee = performance_fee_update.into_option();
+    }
+    if let Some(new_autocompound_max_spread) = autocompound_max_spread {
+        ensure!(
+            new_autocompound_max_spread < Decimal::one(),
+            AppError::InvalidAutocompoundMaxSpread {
+                max_spread: new_autocompound_max_spread
+            }
+        );
+        config.autocompound_max_spread = new_autocompound_max_spread;
+    }
 
     CONFIG.save(deps.storage, &config)?;
     Ok(app.response("update_config"))
@@ -129,13 +270,22 @@ fn create_position(
     create_position_msg: CreatePositionMessage,
 ) -> AppResult {
     app.admin.assert_admin(deps.as_ref(), &info.sender)?;
-    // Check if there is already saved position
-    if CarrotPosition::may_load(deps.as_ref())?.is_some() {
+    let config = CONFIG.load(deps.storage)?;
+    ensure!(!config.paused, AppError::Paused {});
+    // Check if there is already saved position with this name
+    let name = create_position_msg
+        .name
+        .as_deref()
+        .unwrap_or(DEFAULT_POSITION);
+    if CarrotPosition::may_load(deps.as_ref(), name)?.is_some() {
         return Err(AppError::PositionExists {});
     }
+    // Only the pool assets go into the position
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    add_principal(deps.storage, &pool_funds(&pool, &create_position_msg.funds))?;
 
     let (swap_messages, create_position_msg) =
-        _create_position(deps.as_ref(), &env, &app, create_position_msg)?;
+        _create_position(deps, &env, &app, create_position_msg)?;
 
     Ok(app
         .response("create_position")
@@ -145,160 +295,686 @@ fn create_position(
 
 #[allow(clippy::too_many_arguments)]
 fn deposit(
-    deps: DepsMut,
+    mut deps: DepsMut,
     env: Env,
     info: MessageInfo,
     funds: Vec<Coin>,
     max_spread: Option<Decimal>,
     belief_price0: Option<Decimal>,
     belief_price1: Option<Decimal>,
+    token_min_amount0: Option<Uint128>,
+    token_min_amount1: Option<Uint128>,
     app: App,
 ) -> AppResult {
     // Only the admin (manager contracts or account owner) + the smart contract can deposit
     app.admin
         .assert_admin(deps.as_ref(), &info.sender)
         .or(assert_contract(&info, &env))?;
+    let config = CONFIG.load(deps.storage)?;
+    ensure!(!config.paused, AppError::Paused {});
 
-    let carrot_position = CarrotPosition::load(deps.as_ref())?;
-
-    let asset0: Coin = carrot_position.position.asset0.unwrap().try_into()?;
-    let asset1: Coin = carrot_position.position.asset1.unwrap().try_into()?;
-
-    // When depositing, we start by adapting the available funds to the expected pool funds ratio
-    // We do so by computing the swap information
+    let carrot_positions = CarrotPosition::load_all(deps.as_ref())?;
+    if carrot_positions.is_empty() {
+        return Err(AppError::NoPosition {});
+    }
+    // Positions seen back in range restart the grace period the next time they go out of range
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    for carrot_position in carrot_positions.iter() {
+        carrot_position.track_range(deps.storage, &env, pool.current_tick)?;
+    }
 
-    let (swap_msgs, assets_for_position) = swap_to_enter_position(
-        deps.as_ref(),
+    // Funds that are not pool assets get swapped into the pool assets first,
+    // the deposit continues with the received amounts in the reply of the swaps
+    let zap_msgs = zap_msgs(
+        deps.branch(),
         &env,
-        funds,
         &app,
-        asset0,
-        asset1,
+        &config,
+        funds.clone(),
         max_spread,
-        belief_price0,
-        belief_price1,
+        ZapTarget::Deposit {
+            max_spread,
+            belief_price0,
+            belief_price1,
+            token_min_amount0,
+            token_min_amount1,
+            principal: info.sender != env.contract.address,
+        },
     )?;
+    if !zap_msgs.is_empty() {
+        return Ok(app.response("deposit").add_submessages(zap_msgs));
+    }
 
     let user = get_user(deps.as_ref(), &app)?;
+    let authz = app.auth_z(deps.as_ref(), Some(user.clone()))?;
 
-    let deposit_msg = app.auth_z(deps.as_ref(), Some(user.clone()))?.execute(
-        &env.contract.address,
-        MsgAddToPosition {
-            position_id: carrot_position.id,
-            sender: user.to_string(),
-            amount0: assets_for_position.asset0.amount.to_string(),
-            amount1: assets_for_position.asset1.amount.to_string(),
-            token_min_amount0: "0".to_string(),
-            token_min_amount1: "0".to_string(),
-        },
-    );
+    // Deposits of the contract itself are compounded rewards, accounted by the autocompound
+    if info.sender != env.contract.address {
+        add_principal(deps.storage, &funds)?;
+    }
 
-    Ok(app
-        .response("deposit")
-        .add_messages(swap_msgs)
-        .add_submessage(SubMsg::reply_on_success(deposit_msg, ADD_TO_POSITION_ID)))
+    // Explicit minimum amounts get split the same way as the funds
+    let token_min_amounts0 =
+        token_min_amount0.map(|amount| split_amount(amount, &carrot_positions));
+    let token_min_amounts1 =
+        token_min_amount1.map(|amount| split_amount(amount, &carrot_positions));
+
+    let mut response = app.response("deposit");
+    for (index, (position_funds, carrot_position)) in split_funds(funds, &carrot_positions)
+        .into_iter()
+        .zip(carrot_positions)
+        .enumerate()
+    {
+        if position_funds.iter().all(|coin| coin.amount.is_zero()) {
+            continue;
+        }
+        let asset0: Coin = carrot_position.position.asset0.unwrap().try_into()?;
+        let asset1: Coin = carrot_position.position.asset1.unwrap().try_into()?;
+
+        // When depositing, we start by adapting the available funds to the expected pool funds ratio
+        // We do so by computing the swap information
+
+        let (swap_msgs, assets_for_position) = swap_to_enter_position(
+            deps.as_ref(),
+            &env,
+            position_funds,
+            &app,
+            asset0,
+            asset1,
+            max_spread,
+            belief_price0,
+            belief_price1,
+        )?;
+        let (token_min_amount0, token_min_amount1) = assets_for_position.min_amounts(
+            max_spread,
+            token_min_amounts0.as_ref().map(|amounts| amounts[index]),
+            token_min_amounts1.as_ref().map(|amounts| amounts[index]),
+        )?;
+
+        let deposit_msg = authz.execute(
+            &env.contract.address,
+            MsgAddToPosition {
+                position_id: carrot_position.id,
+                sender: user.to_string(),
+                amount0: assets_for_position.asset0.amount.to_string(),
+                amount1: assets_for_position.asset1.amount.to_string(),
+                token_min_amount0: token_min_amount0.to_string(),
+                token_min_amount1: token_min_amount1.to_string(),
+            },
+        );
+        // Position id changes after adding to the position, reply needs to know which one it is
+        push_pending_position(
+            deps.storage,
+            PendingPosition {
+                name: carrot_position.name,
+                weight: carrot_position.weight,
+                token_min_amount0,
+                token_min_amount1,
+            },
+        )?;
+
+        response = response
+            .add_messages(swap_msgs)
+            .add_submessage(SubMsg::reply_always(deposit_msg, ADD_TO_POSITION_ID));
+    }
+
+    Ok(response)
+}
+
+/// Splits the amount between positions according to their weights,
+/// rounding leftovers go to the first position
+fn split_amount(amount: Uint128, carrot_positions: &[CarrotPosition]) -> Vec<Uint128> {
+    let total_weight: u64 = carrot_positions
+        .iter()
+        .map(|carrot_position| carrot_position.weight)
+        .sum();
+
+    let mut shares: Vec<Uint128> = carrot_positions
+        .iter()
+        .skip(1)
+        .map(|carrot_position| amount.multiply_ratio(carrot_position.weight, total_weight))
+        .collect();
+    let leftover = amount - shares.iter().sum::<Uint128>();
+    shares.insert(0, leftover);
+    shares
+}
+
+/// Splits the funds between positions according to their weights,
+/// rounding leftovers go to the first position
+pub(crate) fn split_funds(funds: Vec<Coin>, carrot_positions: &[CarrotPosition]) -> Vec<Vec<Coin>> {
+    let mut shares = vec![vec![]; carrot_positions.len()];
+    for coin in funds {
+        for (share, amount) in shares
+            .iter_mut()
+            .zip(split_amount(coin.amount, carrot_positions))
+        {
+            share.push(Coin {
+                denom: coin.denom.clone(),
+                amount,
+            });
+        }
+    }
+    shares
 }
 
+#[allow(clippy::too_many_arguments)]
 fn withdraw(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    amount: Option<Uint256>,
+    amount: Option<WithdrawAmount>,
     swap_to: Option<SwapToAsset>,
+    position: Option<String>,
+    recipient: Option<String>,
     app: App,
 ) -> AppResult {
     // Only the authorized addresses (admin ?) can withdraw
     app.admin.assert_admin(deps.as_ref(), &info.sender)?;
 
-    let carrot_position = CarrotPosition::load(deps.as_ref())?;
+    let carrot_positions = positions_to_withdraw(deps.as_ref(), amount.as_ref(), position)?;
+
+    // Withdraws reduce the principal by their share of the value of all the positions
+    let config = CONFIG.load(deps.storage)?;
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    let mut total_funds = vec![];
+    for carrot_position in CarrotPosition::load_all(deps.as_ref())? {
+        // Positions seen back in range restart the grace period the next time they go out of range
+        carrot_position.track_range(deps.storage, &env, pool.current_tick)?;
+        total_funds.extend(_withdraw_amounts(None, &carrot_position)?.2);
+    }
+    let total_value = pool_value(&pool, &total_funds, &pool.token1)?;
+    let mut withdrawn_funds = vec![];
+
     // Get app's user and set up authz.
     let user = get_user(deps.as_ref(), &app)?;
     let authz = app.auth_z(deps.as_ref(), Some(user.clone()))?;
 
-    // Collect all rewards/incentives if they exist
-    let (collect_rewards_msgs, rewards) =
-        _inner_claim_rewards(&env, carrot_position.clone(), user.clone(), authz.clone())?;
+    let recipient = recipient
+        .map(|recipient| deps.api.addr_validate(&recipient))
+        .transpose()?;
+
+    // Resolve to_asset if provided
+    if let Some(swap_to) = &swap_to {
+        let balance_before =
+            get_balance(swap_to.to_asset.clone(), deps.as_ref(), user.clone(), &app)?;
+        let swap_recipient = swap_to
+            .recipient
+            .as_ref()
+            .map(|recipient| deps.api.addr_validate(recipient))
+            .transpose()?
+            .or(recipient.clone());
+        TEMP_WITHDRAW_TO_ASSET.save(
+            deps.storage,
+            &WithdrawToAsset {
+                swap_to: swap_to.clone(),
+                pending_withdraws: carrot_positions.len() as u64,
+                balance_before,
+                recipient: swap_recipient,
+            },
+        )?;
+    } else if let Some(recipient) = &recipient {
+        TEMP_WITHDRAW_RECIPIENT.save(
+            deps.storage,
+            &WithdrawToRecipient {
+                recipient: recipient.clone(),
+                pending_withdraws: carrot_positions.len() as u64,
+            },
+        )?;
+    }
+
+    let mut app_response = app.response("withdraw");
+    for carrot_position in carrot_positions {
+        // Collect all rewards/incentives if they exist
+        let (collect_rewards_msgs, rewards, _) = _inner_claim_rewards(
+            &env,
+            std::slice::from_ref(&carrot_position),
+            user.clone(),
+            authz.clone(),
+        )?;
 
-    // Withdraw funds
-    let (withdraw_msg, withdraw_amount, total_amount, _withdrawn_funds) =
-        _inner_withdraw(&env, amount, carrot_position, user, authz)?;
+        // Withdraw funds
+        let name = carrot_position.name.clone();
+        let position_funds = carrot_position.funds()?;
+        let liquidity_amount =
+            withdraw_liquidity(deps.as_ref(), amount.as_ref(), &carrot_position)?;
+        let (withdraw_msg, withdraw_amount, total_amount, position_withdrawn_funds) =
+            _inner_withdraw(
+                &env,
+                liquidity_amount,
+                carrot_position,
+                user.clone(),
+                authz.clone(),
+            )?;
+        // Stale positions are valued from what's left in them
+        let remaining_funds = position_funds
+            .into_iter()
+            .zip(&position_withdrawn_funds)
+            .map(|(coin, withdrawn)| Coin {
+                amount: coin.amount.saturating_sub(withdrawn.amount),
+                denom: coin.denom,
+            })
+            .collect();
+        CarrotPosition::save_funds(deps.storage, &name, remaining_funds)?;
+        withdrawn_funds.extend(position_withdrawn_funds);
+
+        let partial_withdraw = withdraw_amount != total_amount;
+
+        app_response = app_response
+            .add_attribute("position", name)
+            .add_attribute("withdraw_amount", withdraw_amount)
+            .add_attribute("total_amount", total_amount);
+
+        app_response = if swap_to.is_some() {
+            app_response
+                .add_submessage(SubMsg::reply_on_success(withdraw_msg, WITHDRAW_TO_ASSET_ID))
+        } else if recipient.is_some() {
+            // Reply forwards the exact withdrawn amounts
+            app_response.add_submessage(SubMsg::reply_on_success(
+                withdraw_msg,
+                WITHDRAW_TO_RECIPIENT_ID,
+            ))
+        } else {
+            app_response.add_message(withdraw_msg)
+        };
+        // Add the collect_rewards_msgs only if there are rewards AND if we are doing a partial withdraw
+        // Context: While partial position withdraws on osmosis keep the rewards unclaimed, full withdraws automatically withdraw rewards
+        if !rewards.is_empty() && partial_withdraw {
+            app_response = app_response.add_messages(collect_rewards_msgs);
+        }
+    }
 
-    let partial_withdraw = withdraw_amount != total_amount;
+    if !total_value.is_zero() {
+        let withdrawn_share = Decimal::from_ratio(
+            pool_value(&pool, &withdrawn_funds, &pool.token1)?,
+            total_value,
+        )
+        .min(Decimal::one());
+        reduce_principal(deps.storage, withdrawn_share)?;
+    }
 
-    let mut app_response = app
-        .response("withdraw")
-        .add_attribute("withdraw_amount", withdraw_amount)
-        .add_attribute("total_amount", total_amount);
+    Ok(app_response)
+}
 
-    // Resolve to_asset if provided
-    app_response = if let Some(swap_to) = swap_to {
-        TEMP_WITHDRAW_TO_ASSET.save(deps.storage, &swap_to)?;
-        app_response.add_submessage(SubMsg::reply_on_success(withdraw_msg, WITHDRAW_TO_ASSET_ID))
-    } else {
-        app_response.add_message(withdraw_msg)
+/// Removes the positions that got withdrawn outside of the app and
+/// adopts the untracked positions of the user in the pool in their place
+fn reconcile(mut deps: DepsMut, env: Env, info: MessageInfo, app: App) -> AppResult {
+    app.admin.assert_admin(deps.as_ref(), &info.sender)?;
+    let config = CONFIG.load(deps.storage)?;
+
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    let stale_positions = CarrotPosition::load_stale(deps.as_ref())?;
+    let mut stale_value = Uint128::zero();
+    for (_, _, funds) in &stale_positions {
+        stale_value += pool_value(&pool, funds, &pool.token1)?;
+    }
+    let mut valid_value = Uint128::zero();
+    for carrot_position in CarrotPosition::load_all(deps.as_ref())? {
+        valid_value += pool_value(&pool, &carrot_position.funds()?, &pool.token1)?;
+    }
+    for (name, _, _) in &stale_positions {
+        CarrotPosition::remove(deps.storage, name);
+    }
+    // Funds of the stale positions left the app,
+    // their share of the principal is estimated from their last known value
+    if !stale_value.is_zero() {
+        reduce_principal(
+            deps.storage,
+            Decimal::from_ratio(stale_value, stale_value + valid_value),
+        )?;
+    }
+
+    // Positions of the user in the pool that the app doesn't know about
+    let user = get_user(deps.as_ref(), &app)?;
+    let tracked_ids = CarrotPosition::load_stored_ids(deps.storage)?;
+    let untracked_positions: Vec<(u64, Vec<Coin>)> =
+        ConcentratedliquidityQuerier::new(&deps.querier)
+            .user_positions(user.to_string(), config.pool_config.pool_id, None)?
+            .positions
+            .into_iter()
+            .filter_map(|breakdown| {
+                let position_id = breakdown.position?.position_id;
+                let funds = try_proto_to_cosmwasm_coins(
+                    breakdown.asset0.into_iter().chain(breakdown.asset1),
+                );
+                Some(funds.map(|funds| (position_id, funds)))
+            })
+            .filter(|untracked| {
+                untracked
+                    .as_ref()
+                    .map_or(true, |(position_id, _)| !tracked_ids.contains(position_id))
+            })
+            .collect::<StdResult<_>>()?;
+
+    let mut response = app.response("reconcile");
+    for ((name, weight, _), (position_id, funds)) in
+        stale_positions.iter().zip(&untracked_positions)
+    {
+        CarrotPosition::save_position(deps.branch(), &env.block.time, name, *position_id, *weight)?;
+        // Funds of adopted positions count as deposited, the same as imported positions
+        add_principal(deps.storage, funds)?;
+        response = response.add_attribute("adopted", format!("{name}:{position_id}"));
+    }
+    for (name, _, _) in stale_positions.into_iter().skip(untracked_positions.len()) {
+        response = response.add_attribute("removed", name);
+    }
+    Ok(response)
+}
+
+/// Starts tracking a position the user created in the pool outside of the app
+fn import_position(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    app: App,
+    position_id: u64,
+) -> AppResult {
+    app.admin.assert_admin(deps.as_ref(), &info.sender)?;
+    let config = CONFIG.load(deps.storage)?;
+    ensure!(!config.paused, AppError::Paused {});
+    if CarrotPosition::may_load(deps.as_ref(), DEFAULT_POSITION)?.is_some() {
+        return Err(AppError::PositionExists {});
+    }
+    let import_error = |reason: &str| AppError::ImportPositionError {
+        position_id,
+        reason: reason.to_owned(),
     };
-    // Add the collect_rewards_msgs only if there are rewards AND if we are doing a partial withdraw
-    // Context: While partial position withdraws on osmosis keep the rewards unclaimed, full withdraws automatically withdraw rewards
-    if !rewards.is_empty() && partial_withdraw {
-        app_response = app_response.add_messages(collect_rewards_msgs);
+    // The same position under two names would get deposited into twice
+    ensure!(
+        !CarrotPosition::load_stored_ids(deps.storage)?.contains(&position_id),
+        import_error("position is already tracked")
+    );
+
+    let breakdown = ConcentratedliquidityQuerier::new(&deps.querier)
+        .position_by_id(position_id)
+        .map_err(|_| import_error("position not found"))?
+        .position
+        .ok_or_else(|| import_error("position not found"))?;
+    let position = breakdown
+        .position
+        .ok_or_else(|| import_error("position not found"))?;
+    let user = get_user(deps.as_ref(), &app)?;
+    ensure!(
+        position.address == user.as_str(),
+        import_error("position is not owned by the account owner")
+    );
+    ensure!(
+        position.pool_id == config.pool_config.pool_id,
+        import_error("position is not in the pool of the app")
+    );
+
+    // The funds of the position count as deposited
+    let funds = try_proto_to_cosmwasm_coins(breakdown.asset0.into_iter().chain(breakdown.asset1))?;
+    add_principal(deps.storage, &funds)?;
+    CarrotPosition::save_position(
+        deps,
+        &env.block.time,
+        DEFAULT_POSITION,
+        position_id,
+        DEFAULT_WEIGHT,
+    )?;
+
+    Ok(app
+        .response("import_position")
+        .add_attribute("position_id", position_id.to_string()))
+}
+
+/// Withdraws all the positions, their funds go into a position in the new pool on reply
+fn migrate_pool(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    app: App,
+    new_pool_id: u64,
+    position: MigratedPosition,
+) -> AppResult {
+    app.admin.assert_admin(deps.as_ref(), &info.sender)?;
+    let config = CONFIG.load(deps.storage)?;
+    ensure!(!config.paused, AppError::Paused {});
+    ensure!(
+        new_pool_id != config.pool_config.pool_id,
+        AppError::SamePool {
+            pool_id: new_pool_id
+        }
+    );
+    let carrot_positions = CarrotPosition::load_all(deps.as_ref())?;
+    if carrot_positions.is_empty() {
+        return Err(AppError::NoPosition {});
     }
 
-    Ok(app_response)
+    // The new pool gets resolved and checked the same way as on instantiate
+    let ans = app.name_service(deps.as_ref());
+    let (pool_config, dex_name) = resolve_pool_config(deps.as_ref(), &app, new_pool_id)?;
+    let new_config = Config {
+        pool_config: pool_config.clone(),
+        ..config.clone()
+    };
+    check_config(deps.as_ref(), ans.host(), &new_config, &dex_name)?;
+    range_to_ticks(&query_pool(&deps.querier, new_pool_id)?, &position.range)?;
+    // Assets of the current pool have to be swappable into the new pool assets
+    for asset in [&config.pool_config.asset0, &config.pool_config.asset1] {
+        if asset != &pool_config.asset0 && asset != &pool_config.asset1 {
+            swap_route(deps.as_ref(), ans.host(), &new_config, asset)?;
+        }
+    }
+
+    let user = get_user(deps.as_ref(), &app)?;
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    let balances_before = [pool.token0, pool.token1]
+        .into_iter()
+        .map(|denom| deps.querier.query_balance(&user, denom))
+        .collect::<StdResult<Vec<Coin>>>()?;
+    TEMP_MIGRATE_POOL.save(
+        deps.storage,
+        &MigratePoolParams {
+            pool_config,
+            position,
+            balances_before,
+        },
+    )?;
+
+    // Withdraw everything, the reply comes after the last withdraw
+    let authz = app.auth_z(deps.as_ref(), Some(user.clone()))?;
+    let mut withdraw_msgs = carrot_positions
+        .into_iter()
+        .map(|carrot_position| {
+            let (msg, _, _, _) =
+                _inner_withdraw(&env, None, carrot_position, user.clone(), authz.clone())?;
+            Ok(msg)
+        })
+        .collect::<AppResult<Vec<CosmosMsg>>>()?;
+    let last_withdraw_msg = withdraw_msgs.pop().unwrap();
+
+    Ok(app
+        .response("migrate_pool")
+        .add_attribute("new_pool_id", new_pool_id.to_string())
+        .add_messages(withdraw_msgs)
+        .add_submessage(SubMsg::reply_on_success(last_withdraw_msg, MIGRATE_POOL_ID)))
+}
+
+fn set_paused(deps: DepsMut, info: MessageInfo, app: App, paused: bool) -> AppResult {
+    app.admin.assert_admin(deps.as_ref(), &info.sender)?;
+    CONFIG.update(deps.storage, |mut config| -> AppResult<_> {
+        config.paused = paused;
+        Ok(config)
+    })?;
+
+    Ok(app
+        .response("set_paused")
+        .add_attribute("paused", paused.to_string()))
+}
+
+/// Withdraws all the positions without swaps, ANS or dex adapter.
+/// The app gets paused, so nothing goes back into the pool until the admin unpauses it
+fn emergency_exit(deps: DepsMut, env: Env, info: MessageInfo, app: App) -> AppResult {
+    app.admin.assert_admin(deps.as_ref(), &info.sender)?;
+    let carrot_positions = CarrotPosition::load_all(deps.as_ref())?;
+    if carrot_positions.is_empty() {
+        return Err(AppError::NoPosition {});
+    }
+
+    let user = get_user(deps.as_ref(), &app)?;
+    let authz = app.auth_z(deps.as_ref(), Some(user.clone()))?;
+    let mut response = app.response("emergency_exit");
+    for carrot_position in carrot_positions {
+        CarrotPosition::remove(deps.storage, &carrot_position.name);
+        response = response.add_attribute("position", carrot_position.name.clone());
+        let (withdraw_msg, _, _, _) =
+            _inner_withdraw(&env, None, carrot_position, user.clone(), authz.clone())?;
+        response = response.add_message(withdraw_msg);
+    }
+    reduce_principal(deps.storage, Decimal::one())?;
+    CONFIG.update(deps.storage, |mut config| -> AppResult<_> {
+        config.paused = true;
+        Ok(config)
+    })?;
+
+    Ok(response)
+}
+
+/// Loads the positions targeted by a withdraw, all of them when `position` is omitted
+pub(crate) fn positions_to_withdraw(
+    deps: Deps,
+    amount: Option<&WithdrawAmount>,
+    position: Option<String>,
+) -> AppResult<Vec<CarrotPosition>> {
+    let carrot_positions = match position {
+        Some(name) => vec![CarrotPosition::load(deps, &name)?],
+        None => CarrotPosition::load_all(deps)?,
+    };
+    if carrot_positions.is_empty() {
+        return Err(AppError::NoPosition {});
+    }
+    // Liquidity amounts and values of different positions are not interchangeable
+    ensure!(
+        !amount.is_some_and(WithdrawAmount::is_position_specific) || carrot_positions.len() == 1,
+        AppError::PositionNameRequired {}
+    );
+    Ok(carrot_positions)
 }
 
 /// Auto-compound the position with earned fees and incentives.
-fn autocompound(deps: DepsMut, env: Env, info: MessageInfo, app: App) -> AppResult {
+fn autocompound(mut deps: DepsMut, env: Env, info: MessageInfo, app: App) -> AppResult {
     // Everyone can autocompound
     let config = CONFIG.load(deps.storage)?;
+    ensure!(!config.paused, AppError::Paused {});
 
-    let (compound_status, maybe_carrot_position) = CarrotPosition::compound_status(
+    let (compound_status, carrot_positions) = CarrotPosition::compound_status(
         deps.as_ref(),
         &env,
         config.autocompound_cooldown_seconds.u64(),
     )?;
 
-    // Check if osmosis returned position
-    let carrot_position = maybe_carrot_position.ok_or(AppError::NoPosition {})?;
+    // Check if osmosis returned positions
+    if carrot_positions.is_empty() {
+        return Err(AppError::NoPosition {});
+    }
+    // Non-admin can't autocompound before the end of the cooldown, the executor reward is paid on every call
+    let is_admin = app.admin.is_admin(deps.as_ref(), &info.sender)?;
+    if let CompoundStatus::Cooldown(seconds_left) = compound_status {
+        ensure!(is_admin, AppError::AutocompoundCooldown { seconds_left });
+    }
 
-    let mut rewards = cosmwasm_std::Coins::default();
-    let mut collect_rewards_msgs = vec![];
+    // Keep track of the time positions are out of range for the permissionless rebalance
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    for carrot_position in carrot_positions.iter() {
+        carrot_position.track_range(deps.storage, &env, pool.current_tick)?;
+    }
+
+    // Rewards get recorded in the compound history
+    let mut spread_rewards = cosmwasm_std::Coins::default();
+    let mut incentives = cosmwasm_std::Coins::default();
+    for carrot_position in carrot_positions.iter() {
+        let position = &carrot_position.position;
+        for coin in try_proto_to_cosmwasm_coins(position.claimable_spread_rewards.clone())? {
+            spread_rewards.add(coin)?;
+        }
+        for coin in try_proto_to_cosmwasm_coins(position.claimable_incentives.clone())? {
+            incentives.add(coin)?;
+        }
+    }
+    let mut compound_event = CompoundEvent {
+        id: 0,
+        timestamp: env.block.time,
+        position_ids: carrot_positions.iter().map(|p| p.id).collect(),
+        spread_rewards: spread_rewards.into(),
+        incentives: incentives.into(),
+        executor: info.sender.clone(),
+        executor_reward: None,
+    };
 
-    // Get app's user and set up authz.
     let user = get_user(deps.as_ref(), &app)?;
-    let authz = app.auth_z(deps.as_ref(), Some(user.clone()))?;
+    let mut response = match config.autocompound_mode.clone() {
+        AutocompoundMode::Compound {} => {
+            let (compound_msgs, performance_fee) =
+                _inner_compound(deps.branch(), &env, &app, &config, &carrot_positions)?;
+            app.response("auto-compound")
+                .add_attribute("compound_ratio", config.compound_ratio.to_string())
+                .add_attribute("performance_fee", performance_fee.to_string())
+                .add_submessages(compound_msgs)
+        }
+        AutocompoundMode::Harvest { swap_to } => {
+            let (harvest_msgs, settle_msg) = _inner_harvest(
+                deps.branch(),
+                &env,
+                &app,
+                &config,
+                &carrot_positions,
+                swap_to,
+            )?;
+            app.response("auto-compound")
+                .add_attribute("mode", "harvest")
+                .add_messages(harvest_msgs)
+                .add_submessages(settle_msg)
+        }
+    };
 
-    // If there are external incentives, claim them.
-    if !carrot_position.position.claimable_incentives.is_empty() {
-        let asset0_denom = carrot_position.position.asset0.unwrap().denom;
-        let asset1_denom = carrot_position.position.asset1.unwrap().denom;
+    // Cooldown starts on every autocompound, positions are not always saved again
+    LAST_COMPOUND.save(deps.storage, &env.block.time)?;
 
-        for coin in try_proto_to_cosmwasm_coins(carrot_position.position.claimable_incentives)? {
-            if coin.denom == asset0_denom || coin.denom == asset1_denom {
-                rewards.add(coin)?;
-            }
-        }
-        collect_rewards_msgs.push(authz.execute(
-            &env.contract.address,
-            MsgCollectIncentives {
-                position_ids: vec![carrot_position.id],
-                sender: user.to_string(),
-            },
+    // If called by non-admin and reward cooldown has ended, send rewards to the contract caller.
+    if !is_admin && compound_status.is_ready() {
+        compound_event.executor_reward = Some(AnsAsset::new(
+            config.autocompound_rewards_config.gas_asset.clone(),
+            config.autocompound_rewards_config.reward,
         ));
+        let executor_reward_messages = autocompound_executor_rewards(
+            deps.as_ref(),
+            &env,
+            info.sender.into_string(),
+            &app,
+            user,
+            config,
+        )?;
+
+        response = response.add_messages(executor_reward_messages);
     }
+    push_compound_event(deps.storage, compound_event)?;
 
-    // If there is income from swap fees, claim them.
-    if !carrot_position.position.claimable_spread_rewards.is_empty() {
-        for coin in try_proto_to_cosmwasm_coins(carrot_position.position.claimable_spread_rewards)?
-        {
-            rewards.add(coin)?;
-        }
-        collect_rewards_msgs.push(authz.execute(
-            &env.contract.address,
-            MsgCollectSpreadRewards {
-                position_ids: vec![carrot_position.id],
-                sender: user.to_string(),
-            },
-        ))
+    Ok(response)
+}
+
+/// Collects the rewards of all positions and deposits them back into the positions,
+/// the share of the rewards that is not compounded goes to the payout.
+/// Returns the messages and the performance fee taken from the rewards
+fn _inner_compound(
+    mut deps: DepsMut,
+    env: &Env,
+    app: &App,
+    config: &Config,
+    carrot_positions: &[CarrotPosition],
+) -> AppResult<(Vec<SubMsg>, cosmwasm_std::Coins)> {
+    // Get app's user and set up authz.
+    let user = get_user(deps.as_ref(), app)?;
+    let authz = app.auth_z(deps.as_ref(), Some(user.clone()))?;
+
+    // Claim rewards of all the positions
+    let (collect_rewards_msgs, mut rewards, other_incentives) =
+        _inner_claim_rewards(env, carrot_positions, user, authz)?;
+
+    // Allowlisted incentive tokens get swapped into the pool assets by the deposit,
+    // the other ones stay in the wallet
+    for coin in allowlisted_incentives(deps.as_ref(), app, config, &other_incentives.to_vec())? {
+        rewards.add(coin)?;
     }
 
     // If there are no rewards, we can't do anything
@@ -306,25 +982,377 @@ fn autocompound(deps: DepsMut, env: Env, info: MessageInfo, app: App) -> AppResu
         return Err(crate::error::AppError::NoRewards {});
     }
 
+    let mut compound_msgs: Vec<SubMsg> =
+        collect_rewards_msgs.into_iter().map(SubMsg::new).collect();
+
+    // Skim the performance fee before compounding
+    let mut performance_fee_amounts = cosmwasm_std::Coins::default();
+    if let Some(performance_fee) = &config.performance_fee {
+        let mut remaining_rewards = cosmwasm_std::Coins::default();
+        for coin in rewards.iter() {
+            let fee_amount = coin.amount.mul_floor(performance_fee.fee);
+            performance_fee_amounts.add(Coin {
+                denom: coin.denom.clone(),
+                amount: fee_amount,
+            })?;
+            remaining_rewards.add(Coin {
+                denom: coin.denom.clone(),
+                amount: coin.amount - fee_amount,
+            })?;
+        }
+        rewards = remaining_rewards;
+
+        let fee_recipient = deps.api.addr_validate(&performance_fee.recipient)?;
+        let fee_assets = performance_fee_amounts
+            .iter()
+            .map(|coin| Asset::native(&coin.denom, coin.amount))
+            .collect();
+        let fee_msgs = forward_to_recipient(deps.as_ref(), env, app, fee_assets, &fee_recipient)?;
+        compound_msgs.extend(fee_msgs.into_iter().map(SubMsg::new));
+    }
+
+    // Split the rewards between the positions and the payout
+    let mut compound_rewards = cosmwasm_std::Coins::default();
+    let mut payout_rewards = cosmwasm_std::Coins::default();
+    for coin in rewards.iter() {
+        let compound_amount = coin.amount.mul_floor(config.compound_ratio);
+        compound_rewards.add(Coin {
+            denom: coin.denom.clone(),
+            amount: compound_amount,
+        })?;
+        payout_rewards.add(Coin {
+            denom: coin.denom.clone(),
+            amount: coin.amount - compound_amount,
+        })?;
+    }
+    if let Some(payout) = &config.payout {
+        compound_msgs.extend(payout_msgs(
+            deps.branch(),
+            env,
+            app,
+            config,
+            payout,
+            &rewards,
+            payout_rewards,
+        )?);
+    }
+
     // Finally we deposit of all rewarded tokens into the position
-    let msg_deposit = CosmosMsg::Wasm(WasmMsg::Execute {
-        contract_addr: env.contract.address.to_string(),
-        msg: to_json_binary(&ExecuteMsg::Module(AppExecuteMsg::Deposit {
-            funds: rewards.into(),
-            max_spread: None,
-            belief_price0: None,
-            belief_price1: None,
-        }))?,
-        funds: vec![],
-    });
+    if !compound_rewards.is_empty() {
+        // Swapped incentives are accounted by the deposit once the received amounts are known
+        let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+        add_compounded(deps.storage, &pool_funds(&pool, &compound_rewards.to_vec()))?;
+        let msg_deposit = CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            msg: to_json_binary(&ExecuteMsg::Module(AppExecuteMsg::Deposit {
+                funds: compound_rewards.into(),
+                max_spread: Some(config.autocompound_max_spread),
+                belief_price0: None,
+                belief_price1: None,
+                token_min_amount0: None,
+                token_min_amount1: None,
+            }))?,
+            funds: vec![],
+        });
+        compound_msgs.push(SubMsg::new(msg_deposit));
+    }
+    Ok((compound_msgs, performance_fee_amounts))
+}
 
-    let mut response = app
-        .response("auto-compound")
-        .add_messages(collect_rewards_msgs)
-        .add_message(msg_deposit);
+/// Sends the payout share of the rewards to the payout address, swapped into the payout asset when configured.
+/// The swapped amount is only known once the swaps are done, it gets forwarded by the reply of the last swap
+fn payout_msgs(
+    deps: DepsMut,
+    env: &Env,
+    app: &App,
+    config: &Config,
+    payout: &PayoutConfig,
+    rewards: &cosmwasm_std::Coins,
+    payout_rewards: cosmwasm_std::Coins,
+) -> AppResult<Vec<SubMsg>> {
+    let recipient = deps.api.addr_validate(&payout.address)?;
+    let ans = app.name_service(deps.as_ref());
+    let max_spread = Some(payout.max_spread.unwrap_or(config.autocompound_max_spread));
+
+    let mut direct_payout = vec![];
+    let mut swap_msgs = vec![];
+    for coin in payout_rewards.iter() {
+        let route = payout.swap_to.as_ref().and_then(|to_asset| {
+            let asset = ans.query(&AssetInfo::native(&coin.denom)).ok()?;
+            if &asset == to_asset {
+                return None;
+            }
+            let route = route_to_asset(deps.as_ref(), ans.host(), config, &asset, to_asset).ok()?;
+            Some((asset, route))
+        });
+        match route {
+            Some((asset, route)) => {
+                let (msgs, _) = route_swap_msgs(
+                    deps.as_ref(),
+                    env,
+                    app,
+                    AnsAsset::new(asset, coin.amount),
+                    &route,
+                    max_spread,
+                )?;
+                swap_msgs.extend(msgs);
+            }
+            None => direct_payout.push(Asset::native(&coin.denom, coin.amount)),
+        }
+    }
 
-    // If called by non-admin and reward cooldown has ended, send rewards to the contract caller.
-    if !app.admin.is_admin(deps.as_ref(), &info.sender)? && compound_status.is_ready() {
+    let mut payout_msgs: Vec<SubMsg> =
+        forward_to_recipient(deps.as_ref(), env, app, direct_payout, &recipient)?
+            .into_iter()
+            .map(SubMsg::new)
+            .collect();
+    let (Some(to_asset), Some(last_swap_msg)) = (&payout.swap_to, swap_msgs.pop()) else {
+        return Ok(payout_msgs);
+    };
+
+    // Balance of the payout asset once the rewards are collected, the fee and the direct payout are sent
+    let user = get_user(deps.as_ref(), app)?;
+    let to_asset_denom = match to_asset.resolve(&deps.querier, ans.host())? {
+        AssetInfo::Native(denom) => denom,
+        _ => return Err(AppError::WrongAssetInfo {}),
+    };
+    let balance_before = get_balance(to_asset.clone(), deps.as_ref(), user, app)?
+        + rewards.amount_of(&to_asset_denom)
+        - payout_rewards.amount_of(&to_asset_denom);
+    TEMP_WITHDRAW_TO_ASSET.save(
+        deps.storage,
+        &WithdrawToAsset {
+            swap_to: SwapToAsset {
+                to_asset: to_asset.clone(),
+                max_spread,
+                min_receive: None,
+                recipient: Some(payout.address.clone()),
+            },
+            pending_withdraws: 0,
+            balance_before,
+            recipient: Some(recipient),
+        },
+    )?;
+    payout_msgs.extend(swap_msgs.into_iter().map(SubMsg::new));
+    payout_msgs.push(SubMsg::reply_on_success(last_swap_msg, WITHDRAW_SWAPS_ID));
+    Ok(payout_msgs)
+}
+
+/// Collects the rewards of all positions into the wallet without compounding them
+fn harvest(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    swap_to: Option<SwapToAsset>,
+    app: App,
+) -> AppResult {
+    app.admin.assert_admin(deps.as_ref(), &info.sender)?;
+    let config = CONFIG.load(deps.storage)?;
+
+    let carrot_positions = CarrotPosition::load_all(deps.as_ref())?;
+    if carrot_positions.is_empty() {
+        return Err(AppError::NoPosition {});
+    }
+
+    let (harvest_msgs, settle_msg) =
+        _inner_harvest(deps, &env, &app, &config, &carrot_positions, swap_to)?;
+    Ok(app
+        .response("harvest")
+        .add_messages(harvest_msgs)
+        .add_submessages(settle_msg))
+}
+
+/// Collects the rewards of the positions and swaps them into `swap_to` if provided.
+/// Rewards that can't be swapped into `swap_to` stay in the wallet.
+/// Returns the messages and the last one as a submessage when the received amount has to be
+/// checked against `min_receive` or forwarded to the recipient
+fn _inner_harvest(
+    deps: DepsMut,
+    env: &Env,
+    app: &App,
+    config: &Config,
+    carrot_positions: &[CarrotPosition],
+    swap_to: Option<SwapToAsset>,
+) -> AppResult<(Vec<CosmosMsg>, Option<SubMsg>)> {
+    let user = get_user(deps.as_ref(), app)?;
+    let authz = app.auth_z(deps.as_ref(), Some(user.clone()))?;
+
+    let (mut harvest_msgs, mut rewards, other_incentives) =
+        _inner_claim_rewards(env, carrot_positions, user.clone(), authz)?;
+    for coin in other_incentives {
+        rewards.add(coin)?;
+    }
+    if rewards.is_empty() {
+        return Err(AppError::NoRewards {});
+    }
+    let Some(swap_to) = swap_to else {
+        return Ok((harvest_msgs, None));
+    };
+
+    let max_spread = Some(swap_to.max_spread.unwrap_or(config.autocompound_max_spread));
+    let ans = app.name_service(deps.as_ref());
+    for coin in rewards {
+        let Ok(asset) = ans.query(&AssetInfo::native(&coin.denom)) else {
+            continue;
+        };
+        if asset == swap_to.to_asset {
+            continue;
+        }
+        let Ok(route) =
+            route_to_asset(deps.as_ref(), ans.host(), config, &asset, &swap_to.to_asset)
+        else {
+            continue;
+        };
+        let (swap_msgs, _) = route_swap_msgs(
+            deps.as_ref(),
+            env,
+            app,
+            AnsAsset::new(asset, coin.amount),
+            &route,
+            max_spread,
+        )?;
+        harvest_msgs.extend(swap_msgs);
+    }
+
+    if swap_to.min_receive.is_none() && swap_to.recipient.is_none() {
+        return Ok((harvest_msgs, None));
+    }
+    // Received amount gets settled once the last message is executed
+    let balance_before = get_balance(swap_to.to_asset.clone(), deps.as_ref(), user, app)?;
+    let recipient = swap_to
+        .recipient
+        .as_ref()
+        .map(|recipient| deps.api.addr_validate(recipient))
+        .transpose()?;
+    TEMP_WITHDRAW_TO_ASSET.save(
+        deps.storage,
+        &WithdrawToAsset {
+            swap_to,
+            pending_withdraws: 0,
+            balance_before,
+            recipient,
+        },
+    )?;
+    let last_msg = harvest_msgs.pop().unwrap();
+    Ok((
+        harvest_msgs,
+        Some(SubMsg::reply_on_success(last_msg, WITHDRAW_SWAPS_ID)),
+    ))
+}
+
+/// Re-center an out of range position on the current price.
+/// This operation happens in multiple steps:
+/// 1. Withdraw the whole position
+/// 2. In the reply, swap the withdrawn funds to the ratio of the new range and create the new position
+///
+/// Anyone can rebalance once the position is out of range for the configured grace period,
+/// the executor gets rewarded the same way as for the autocompound.
+fn rebalance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    position: Option<String>,
+    max_spread: Option<Decimal>,
+    belief_price0: Option<Decimal>,
+    belief_price1: Option<Decimal>,
+    app: App,
+) -> AppResult {
+    let config = CONFIG.load(deps.storage)?;
+    ensure!(!config.paused, AppError::Paused {});
+    let name = position.unwrap_or_else(|| DEFAULT_POSITION.to_owned());
+    let carrot_position = CarrotPosition::load(deps.as_ref(), &name)?;
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+
+    // A position in range is earning, nothing to do
+    if carrot_position.is_in_range(pool.current_tick) {
+        return Err(AppError::PositionInRange {});
+    }
+
+    let is_admin = app.admin.is_admin(deps.as_ref(), &info.sender)?;
+    // Non-admin can't pick slippage parameters
+    let (max_spread, belief_price0, belief_price1) = if is_admin {
+        (max_spread, belief_price0, belief_price1)
+    } else {
+        let grace_period = config
+            .rebalance_grace_period_seconds
+            .ok_or(AppError::Unauthorized {})?;
+        let grace_period_started = match carrot_position.rebalance_status(
+            deps.storage,
+            &env,
+            pool.current_tick,
+            grace_period.u64(),
+        )? {
+            RebalanceStatus::Ready {} => {
+                // Spot price can be pushed out of range and could have been back in range
+                // since the grace period started
+                let stayed_out = stayed_out_of_range(
+                    deps.as_ref(),
+                    &env,
+                    &pool,
+                    &carrot_position,
+                    grace_period,
+                )?;
+                if !stayed_out {
+                    carrot_position.restart_grace_period(deps.storage, &env)?;
+                }
+                !stayed_out
+            }
+            RebalanceStatus::GracePeriod(seconds_left) => {
+                return Err(AppError::RebalanceGracePeriod { seconds_left })
+            }
+            _ => {
+                // Start the grace period
+                carrot_position.track_range(deps.storage, &env, pool.current_tick)?;
+                true
+            }
+        };
+        if grace_period_started {
+            return Ok(app
+                .response("rebalance")
+                .add_attribute("position", name)
+                .add_attribute("out_of_range_since", env.block.time.to_string()));
+        }
+        (Some(config.autocompound_max_spread), None, None)
+    };
+    carrot_position.clear_out_of_range(deps.storage);
+
+    let position_details = carrot_position.position.position.clone().unwrap();
+    let (lower_tick, upper_tick) = recenter_range(
+        pool.current_tick,
+        position_details.lower_tick,
+        position_details.upper_tick,
+        pool.tick_spacing,
+    );
+    TEMP_REBALANCE.save(
+        deps.storage,
+        &RebalanceParams {
+            name: name.clone(),
+            weight: carrot_position.weight,
+            lower_tick,
+            upper_tick,
+            max_spread,
+            belief_price0,
+            belief_price1,
+        },
+    )?;
+
+    // Get app's user and set up authz.
+    let user = get_user(deps.as_ref(), &app)?;
+    let authz = app.auth_z(deps.as_ref(), Some(user.clone()))?;
+
+    // Withdraw everything, full withdraws collect the rewards as well
+    let (withdraw_msg, _, _, _) =
+        _inner_withdraw(&env, None, carrot_position, user.clone(), authz)?;
+
+    let mut response = app
+        .response("rebalance")
+        .add_attribute("position", name)
+        .add_attribute("lower_tick", lower_tick.to_string())
+        .add_attribute("upper_tick", upper_tick.to_string())
+        .add_submessage(SubMsg::reply_on_success(withdraw_msg, REBALANCE_ID));
+
+    // If called by non-admin, send rewards to the contract caller.
+    if !is_admin {
         let executor_reward_messages = autocompound_executor_rewards(
             deps.as_ref(),
             &env,
@@ -340,49 +1368,98 @@ fn autocompound(deps: DepsMut, env: Env, info: MessageInfo, app: App) -> AppResu
     Ok(response)
 }
 
+/// Whether the TWAP of the pool over the grace period is out of the range of the position,
+/// on the same side as the current price
+fn stayed_out_of_range(
+    deps: Deps,
+    env: &Env,
+    pool: &Pool,
+    carrot_position: &CarrotPosition,
+    grace_period: Uint64,
+) -> AppResult<bool> {
+    let twap = query_twap(
+        &deps.querier,
+        env,
+        pool.id,
+        pool.token0.clone(),
+        pool.token1.clone(),
+        grace_period.u64(),
+    )?;
+    // TWAP is the price of token1 in token0, ticks follow the price of token0 in token1
+    let twap_price = twap
+        .inv()
+        .ok_or(AppError::Std(StdError::generic_err("Pool TWAP is zero")))?;
+    let twap_tick = price_to_tick(twap_price.into())?;
+    let lower_tick = carrot_position
+        .position
+        .position
+        .as_ref()
+        .unwrap()
+        .lower_tick;
+    Ok(!carrot_position.is_in_range(twap_tick)
+        && (twap_tick < lower_tick) == (pool.current_tick < lower_tick))
+}
+
+/// Collects the rewards of the positions.
+/// Returns the collect messages, the rewards in pool assets and the incentives in other tokens
 fn _inner_claim_rewards(
     env: &Env,
-    carrot_position: CarrotPosition,
+    carrot_positions: &[CarrotPosition],
     user: Addr,
     authz: AuthZ,
-) -> AppResult<(Vec<CosmosMsg>, cosmwasm_std::Coins)> {
+) -> AppResult<(Vec<CosmosMsg>, cosmwasm_std::Coins, cosmwasm_std::Coins)> {
     let mut rewards = cosmwasm_std::Coins::default();
-    let mut collect_rewards_msgs = vec![];
-
-    // If there are external incentives, claim them.
-    if !carrot_position.position.claimable_incentives.is_empty() {
-        let asset0_denom = carrot_position.position.asset0.unwrap().denom;
-        let asset1_denom = carrot_position.position.asset1.unwrap().denom;
+    let mut other_incentives = cosmwasm_std::Coins::default();
+    let mut incentives_position_ids = vec![];
+    let mut spread_rewards_position_ids = vec![];
+
+    for carrot_position in carrot_positions {
+        let position = &carrot_position.position;
+
+        // If there are external incentives, claim them.
+        if !position.claimable_incentives.is_empty() {
+            let asset0_denom = &position.asset0.as_ref().unwrap().denom;
+            let asset1_denom = &position.asset1.as_ref().unwrap().denom;
+
+            for coin in try_proto_to_cosmwasm_coins(position.claimable_incentives.clone())? {
+                if &coin.denom == asset0_denom || &coin.denom == asset1_denom {
+                    rewards.add(coin)?;
+                } else {
+                    other_incentives.add(coin)?;
+                }
+            }
+            incentives_position_ids.push(carrot_position.id);
+        }
 
-        for coin in try_proto_to_cosmwasm_coins(carrot_position.position.claimable_incentives)? {
-            if coin.denom == asset0_denom || coin.denom == asset1_denom {
+        // If there is income from swap fees, claim them.
+        if !position.claimable_spread_rewards.is_empty() {
+            for coin in try_proto_to_cosmwasm_coins(position.claimable_spread_rewards.clone())? {
                 rewards.add(coin)?;
             }
+            spread_rewards_position_ids.push(carrot_position.id);
         }
+    }
+
+    let mut collect_rewards_msgs = vec![];
+    if !incentives_position_ids.is_empty() {
         collect_rewards_msgs.push(authz.execute(
             &env.contract.address,
             MsgCollectIncentives {
-                position_ids: vec![carrot_position.id],
+                position_ids: incentives_position_ids,
                 sender: user.to_string(),
             },
         ));
     }
-
-    // If there is income from swap fees, claim them.
-    if !carrot_position.position.claimable_spread_rewards.is_empty() {
-        for coin in try_proto_to_cosmwasm_coins(carrot_position.position.claimable_spread_rewards)?
-        {
-            rewards.add(coin)?;
-        }
+    if !spread_rewards_position_ids.is_empty() {
         collect_rewards_msgs.push(authz.execute(
             &env.contract.address,
             MsgCollectSpreadRewards {
-                position_ids: vec![carrot_position.id],
+                position_ids: spread_rewards_position_ids,
                 sender: user.to_string(),
             },
         ))
     }
-    Ok((collect_rewards_msgs, rewards))
+    Ok((collect_rewards_msgs, rewards, other_incentives))
 }
 
 fn _inner_withdraw(
@@ -392,15 +1469,8 @@ fn _inner_withdraw(
     user: Addr,
     authz: AuthZ,
 ) -> AppResult<(CosmosMsg, Uint256, Uint256, [Coin; 2])> {
-    let position_details = carrot_position.position.position.unwrap();
-    let total_liquidity: Decimal256 = position_details.liquidity.parse()?;
-    let total_liquidity_atomics: Uint256 = total_liquidity.atomics();
-
-    let liquidity_amount = if let Some(amount) = amount {
-        amount
-    } else {
-        total_liquidity_atomics
-    };
+    let (liquidity_amount, total_liquidity_atomics, withdrawn_funds) =
+        _withdraw_amounts(amount, &carrot_position)?;
 
     // We need to execute withdraw on the user's behalf
     let msg = authz.execute(
@@ -412,19 +1482,104 @@ fn _inner_withdraw(
         },
     );
 
-    let asset0_osmosis = carrot_position.position.asset0.unwrap();
-    let asset1_osmosis = carrot_position.position.asset1.unwrap();
+    Ok((
+        msg,
+        liquidity_amount,
+        total_liquidity_atomics,
+        withdrawn_funds,
+    ))
+}
+
+/// Translates the withdraw amount into the liquidity amount of the position,
+/// `None` withdraws everything
+pub(crate) fn withdraw_liquidity(
+    deps: Deps,
+    amount: Option<&WithdrawAmount>,
+    carrot_position: &CarrotPosition,
+) -> AppResult<Option<Uint256>> {
+    let Some(amount) = amount else {
+        return Ok(None);
+    };
+    let position_details = carrot_position.position.position.as_ref().unwrap();
+    let total_liquidity = position_details.liquidity.parse::<Decimal256>()?.atomics();
+
+    let liquidity_amount = match amount {
+        WithdrawAmount::Liquidity(liquidity_amount) => *liquidity_amount,
+        WithdrawAmount::Percentage { bps } => {
+            ensure!(
+                *bps > 0 && *bps <= MAX_BPS,
+                AppError::InvalidWithdrawPercentage { bps: *bps }
+            );
+            total_liquidity.multiply_ratio(*bps, MAX_BPS)
+        }
+        WithdrawAmount::Value { asset, amount } => {
+            ensure!(!amount.is_zero(), AppError::ZeroWithdrawValue {});
+            let config = CONFIG.load(deps.storage)?;
+            let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+            let sqrt_price = parse_osmosis_decimal(&pool.current_sqrt_price)?;
+            // Price of token0 in token1
+            let price = sqrt_price * sqrt_price;
+
+            let amount0 = Decimal256::from_ratio(
+                Uint256::from_str(&carrot_position.position.asset0.as_ref().unwrap().amount)?,
+                1u128,
+            );
+            let amount1 = Decimal256::from_ratio(
+                Uint256::from_str(&carrot_position.position.asset1.as_ref().unwrap().amount)?,
+                1u128,
+            );
+            let position_value = if *asset == config.pool_config.asset0 {
+                amount0 + amount1 / price
+            } else if *asset == config.pool_config.asset1 {
+                amount1 + amount0 * price
+            } else {
+                return Err(AppError::NotPoolAsset {
+                    asset: asset.clone(),
+                });
+            }
+            .to_uint_floor();
+
+            ensure!(
+                Uint256::from(*amount) <= position_value,
+                AppError::WithdrawValueTooHigh {
+                    value: *amount,
+                    position_value,
+                }
+            );
+            total_liquidity.multiply_ratio(*amount, position_value)
+        }
+    };
+    Ok(Some(liquidity_amount))
+}
+
+/// Liquidity to withdraw, total liquidity of the position and the funds withdrawing it returns
+pub(crate) fn _withdraw_amounts(
+    amount: Option<Uint256>,
+    carrot_position: &CarrotPosition,
+) -> AppResult<(Uint256, Uint256, [Coin; 2])> {
+    let position_details = carrot_position.position.position.as_ref().unwrap();
+    let total_liquidity: Decimal256 = position_details.liquidity.parse()?;
+    let total_liquidity_atomics: Uint256 = total_liquidity.atomics();
+
+    let liquidity_amount = if let Some(amount) = amount {
+        amount
+    } else {
+        total_liquidity_atomics
+    };
+
+    let asset0_osmosis = carrot_position.position.asset0.as_ref().unwrap();
+    let asset1_osmosis = carrot_position.position.asset1.as_ref().unwrap();
 
     let withdrawn_funds = [
         Coin {
-            denom: asset0_osmosis.denom,
+            denom: asset0_osmosis.denom.clone(),
             amount: Uint128::try_from(
                 Uint256::from_str(&asset0_osmosis.amount)? * liquidity_amount
                     / total_liquidity_atomics,
             )?,
         },
         Coin {
-            denom: asset1_osmosis.denom,
+            denom: asset1_osmosis.denom.clone(),
             amount: Uint128::try_from(
                 Uint256::from_str(&asset1_osmosis.amount)? * liquidity_amount
                     / total_liquidity_atomics,
@@ -432,31 +1587,25 @@ fn _inner_withdraw(
         },
     ];
 
-    Ok((
-        msg,
-        liquidity_amount,
-        total_liquidity_atomics,
-        withdrawn_funds,
-    ))
+    Ok((liquidity_amount, total_liquidity_atomics, withdrawn_funds))
 }
 
 /// This function creates a position for the user,
-/// 1. Swap the indicated funds to match the asset0/asset1 ratio and deposit as much as possible in the pool for the given parameters
+/// 1. Swap the indicated funds to match the token ratio of the range and deposit as much as possible in the pool for the given parameters
 /// 2. Create a new position
 /// 3. Store position id from create position response
 ///
-/// * `lower_tick` - Concentrated liquidity pool parameter
-/// * `upper_tick` - Concentrated liquidity pool parameter
+/// * `name` - Name of the position, defaults to [`DEFAULT_POSITION`]
+/// * `weight` - Share of the deposits going into this position, defaults to [`DEFAULT_WEIGHT`]
+/// * `range` - Ticks or prices of the position, converted to the ticks of the pool
 /// * `funds` -  Funds that will be deposited from the user wallet directly into the pool. DO NOT SEND FUNDS TO THIS ENDPOINT
-/// * `asset0` - The target amount of asset0.denom that the user will deposit inside the pool
-/// * `asset1` - The target amount of asset1.denom that the user will deposit inside the pool
 ///
-/// asset0 and asset1 are only used in a ratio to each other. They are there to make sure that the deposited funds will ALL land inside the pool.
-/// We don't use an asset ratio because either one of the amounts can be zero
+/// The token ratio gets computed from the ticks and the current sqrt price of the pool,
+/// this makes sure that the deposited funds will ALL land inside the pool.
 /// See https://docs.osmosis.zone/osmosis-core/modules/concentrated-liquidity for more details
 ///
 pub(crate) fn _create_position(
-    deps: Deps,
+    deps: DepsMut,
     env: &Env,
     app: &App,
     create_position_msg: CreatePositionMessage,
@@ -464,19 +1613,27 @@ pub(crate) fn _create_position(
     let config = CONFIG.load(deps.storage)?;
 
     let CreatePositionMessage {
-        lower_tick,
-        upper_tick,
+        name,
+        weight,
+        range,
         funds,
-        asset0,
-        asset1,
         max_spread,
         belief_price0,
         belief_price1,
+        token_min_amount0,
+        token_min_amount1,
     } = create_position_msg;
 
+    let weight = weight.unwrap_or(DEFAULT_WEIGHT);
+    ensure!(weight > 0, AppError::ZeroWeight {});
+
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    let (lower_tick, upper_tick) = range_to_ticks(&pool, &range)?;
+    let (asset0, asset1) = ratio_for_range(&pool, lower_tick, upper_tick)?;
+
     // 1. Swap the assets
     let (swap_msgs, assets_for_position) = swap_to_enter_position(
-        deps,
+        deps.as_ref(),
         env,
         funds,
         app,
@@ -486,10 +1643,12 @@ pub(crate) fn _create_position(
         belief_price0,
         belief_price1,
     )?;
-    let sender = get_user(deps, app)?;
+    let (token_min_amount0, token_min_amount1) =
+        assets_for_position.min_amounts(max_spread, token_min_amount0, token_min_amount1)?;
+    let sender = get_user(deps.as_ref(), app)?;
 
     // 2. Create a position
-    let create_msg = app.auth_z(deps, Some(sender.clone()))?.execute(
+    let create_msg = app.auth_z(deps.as_ref(), Some(sender.clone()))?.execute(
         &env.contract.address,
         MsgCreatePosition {
             pool_id: config.pool_config.pool_id,
@@ -497,19 +1656,29 @@ pub(crate) fn _create_position(
             lower_tick,
             upper_tick,
             tokens_provided: assets_for_position.into(),
-            token_min_amount0: "0".to_string(),
-            token_min_amount1: "0".to_string(),
+            token_min_amount0: token_min_amount0.to_string(),
+            token_min_amount1: token_min_amount1.to_string(),
         },
     );
 
+    push_pending_position(
+        deps.storage,
+        PendingPosition {
+            name: name.unwrap_or_else(|| DEFAULT_POSITION.to_owned()),
+            weight,
+            token_min_amount0,
+            token_min_amount1,
+        },
+    )?;
+
     Ok((
         swap_msgs,
         // 3. Use a reply to get the stored position id
-        SubMsg::reply_on_success(create_msg, CREATE_POSITION_ID),
+        SubMsg::reply_always(create_msg, CREATE_POSITION_ID),
     ))
 }
 
-/// Sends autocompound rewards to the executor.
+/// Sends autocompound or rebalance rewards to the executor.
 /// In case user does not have not enough gas token the contract will swap some
 /// tokens for gas tokens.
 pub fn autocompound_executor_rewards(
@@ -521,11 +1690,10 @@ pub fn autocompound_executor_rewards(
     config: Config,
 ) -> AppResult<Vec<CosmosMsg>> {
     let rewards_config = config.autocompound_rewards_config;
+    let ans_host = app.ans_host(deps)?;
 
     // Get user balance of gas denom
-    let gas_denom = rewards_config
-        .gas_asset
-        .resolve(&deps.querier, &app.ans_host(deps)?)?;
+    let gas_denom = rewards_config.gas_asset.resolve(&deps.querier, &ans_host)?;
     let user_gas_balance = gas_denom.query_balance(&deps.querier, user.clone())?;
 
     let mut rewards_messages = vec![];
@@ -549,12 +1717,23 @@ pub fn autocompound_executor_rewards(
         // Swap as much as available if not enough for max_gas_balance
         let swap_amount = simulate_swap_response.return_amount.min(user_swap_balance);
 
+        if let Some(twap_config) = &config.twap_config {
+            assert_swap_twap(
+                deps,
+                env,
+                &ans_host,
+                twap_config,
+                &rewards_config.swap_asset,
+                &rewards_config.gas_asset,
+            )?;
+        }
+
         let msgs = swap_msg(
             deps,
             env,
             AnsAsset::new(rewards_config.swap_asset, swap_amount),
             rewards_config.gas_asset,
-            None,
+            Some(config.autocompound_max_spread),
             app,
         )?;
         rewards_messages.extend(msgs);
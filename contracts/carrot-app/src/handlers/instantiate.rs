@@ -1,20 +1,19 @@
-use abstract_app::sdk::{features::AbstractNameService, AbstractResponse};
+use abstract_app::sdk::{
+    feature_objects::AnsHost, features::AbstractNameService, AbstractResponse,
+};
 use abstract_app::std::ans_host::{AssetPairingFilter, AssetPairingMapEntry};
-use cosmwasm_std::{DepsMut, Env, MessageInfo};
+use cosmwasm_std::{ensure, Decimal, Deps, DepsMut, Env, MessageInfo};
 use cw_asset::AssetInfo;
-use osmosis_std::types::osmosis::{
-    concentratedliquidity::v1beta1::Pool, poolmanager::v1beta1::PoolmanagerQuerier,
-};
 
-use crate::helpers::nonpayable;
+use crate::helpers::{nonpayable, pool_funds, query_pool};
 use crate::{
     contract::{App, AppResult},
     error::AppError,
     msg::AppInstantiateMsg,
-    state::{Config, PoolConfig, CONFIG},
+    state::{add_principal, Config, PoolConfig, CONFIG, DEFAULT_AUTOCOMPOUND_MAX_SPREAD},
 };
 
-use super::execute::_create_position;
+use super::{execute::_create_position, swap_helpers::swap_route};
 
 pub fn instantiate_handler(
     deps: DepsMut,
@@ -25,14 +24,52 @@ pub fn instantiate_handler(
 ) -> AppResult {
     nonpayable(&info)?;
 
-    let pool: Pool = PoolmanagerQuerier::new(&deps.querier)
-        .pool(msg.pool_id)?
-        .pool
-        .unwrap()
-        .try_into()?;
+    let ans = app.name_service(deps.as_ref());
+    let (pool_config, dex_name) = resolve_pool_config(deps.as_ref(), &app, msg.pool_id)?;
+
+    let config: Config = Config {
+        pool_config,
+        autocompound_cooldown_seconds: msg.autocompound_cooldown_seconds,
+        autocompound_rewards_config: msg.autocompound_rewards_config,
+        rebalance_grace_period_seconds: msg.rebalance_grace_period_seconds,
+        twap_config: msg.twap_config,
+        incentives_swap_allowlist: msg.incentives_swap_allowlist.unwrap_or_default(),
+        autocompound_mode: msg.autocompound_mode.unwrap_or_default(),
+        compound_ratio: msg.compound_ratio.unwrap_or(Decimal::one()),
+        payout: msg.payout,
+        performance_fee: msg.performance_fee,
+        autocompound_max_spread: msg
+            .autocompound_max_spread
+            .unwrap_or(DEFAULT_AUTOCOMPOUND_MAX_SPREAD),
+        paused: false,
+    };
+    check_config(deps.as_ref(), ans.host(), &config, &dex_name)?;
+    CONFIG.save(deps.storage, &config)?;
+
+    let mut response = app.response("instantiate_savings_app");
+
+    // If provided - create position
+    if let Some(create_position_msg) = msg.create_position {
+        // Only the pool assets go into the position
+        let pool = query_pool(&deps.querier, msg.pool_id)?;
+        add_principal(deps.storage, &pool_funds(&pool, &create_position_msg.funds))?;
+        let (swap_msgs, create_msg) = _create_position(deps, &env, &app, create_position_msg)?;
+        response = response.add_messages(swap_msgs).add_submessage(create_msg);
+    }
+    Ok(response)
+}
+
+/// Queries the pool and resolves the ANS entries of its assets.
+/// Returns the pool config and the name of the dex the assets can be swapped on
+pub(crate) fn resolve_pool_config(
+    deps: Deps,
+    app: &App,
+    pool_id: u64,
+) -> AppResult<(PoolConfig, String)> {
+    let pool = query_pool(&deps.querier, pool_id)?;
 
     // We query the ANS for useful information on the tokens and pool
-    let ans = app.name_service(deps.as_ref());
+    let ans = app.name_service(deps);
     // ANS Asset entries to indentify the assets inside Abstract
     let asset_entries = ans.query(&vec![
         AssetInfo::Native(pool.token0.clone()),
@@ -54,30 +91,47 @@ pub fn instantiate_handler(
         .find(|(_, refs)| !refs.is_empty())
         .ok_or(AppError::NoSwapPossibility {})?
         .0;
-    let dex_name = pair.dex();
-
-    let autocompound_rewards_config = msg.autocompound_rewards_config;
-    // Check validity of autocompound rewards
-    autocompound_rewards_config.check(deps.as_ref(), dex_name, ans.host())?;
+    let dex_name = pair.dex().to_owned();
 
-    let config: Config = Config {
-        pool_config: PoolConfig {
-            pool_id: msg.pool_id,
+    Ok((
+        PoolConfig {
+            pool_id,
             asset0,
             asset1,
         },
-        autocompound_cooldown_seconds: msg.autocompound_cooldown_seconds,
-        autocompound_rewards_config,
-    };
-    CONFIG.save(deps.storage, &config)?;
-
-    let mut response = app.response("instantiate_savings_app");
+        dex_name,
+    ))
+}
 
-    // If provided - create position
-    if let Some(create_position_msg) = msg.create_position {
-        let (swap_msgs, create_msg) =
-            _create_position(deps.as_ref(), &env, &app, create_position_msg)?;
-        response = response.add_messages(swap_msgs).add_submessage(create_msg);
+/// Checks the config is valid for its pool
+pub(crate) fn check_config(
+    deps: Deps,
+    ans_host: &AnsHost,
+    config: &Config,
+    dex_name: &str,
+) -> AppResult<()> {
+    // Check validity of autocompound rewards
+    config
+        .autocompound_rewards_config
+        .check(deps, dex_name, ans_host)?;
+    config.check_rebalance()?;
+    if let Some(twap_config) = &config.twap_config {
+        twap_config.check()?;
     }
-    Ok(response)
+    // Check incentives can be swapped into the pool assets
+    for incentive in &config.incentives_swap_allowlist {
+        swap_route(deps, ans_host, config, incentive)?;
+    }
+    config.autocompound_mode.check(deps, ans_host)?;
+    config.check_payout(deps, ans_host)?;
+    if let Some(performance_fee) = &config.performance_fee {
+        performance_fee.check(deps)?;
+    }
+    ensure!(
+        config.autocompound_max_spread < Decimal::one(),
+        AppError::InvalidAutocompoundMaxSpread {
+            max_spread: config.autocompound_max_spread
+        }
+    );
+    Ok(())
 }
@@ -1,16 +1,21 @@
 use abstract_app::{objects::AssetEntry, sdk::AbstractResponse};
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{DepsMut, Env, Uint64};
+use cosmwasm_std::{Decimal, DepsMut, Env, Uint64};
 use cw_storage_plus::Item;
 
 use crate::{
     contract::{App, AppResult},
     msg::AppMigrateMsg,
-    state::{AutocompoundRewardsConfig, CarrotPosition, Config, PoolConfig, CONFIG},
+    state::{
+        seed_principal, AutocompoundMode, AutocompoundRewardsConfig, CarrotPosition, Config,
+        PoolConfig, CONFIG, DEFAULT_AUTOCOMPOUND_MAX_SPREAD, DEFAULT_POSITION, DEFAULT_WEIGHT,
+        LAST_COMPOUND,
+    },
 };
 
 const V0_1CONFIG: Item<V0_1Config> = Item::new("config");
 const V0_1POSITION: Item<V0_1Position> = Item::new("position");
+const V0_5POSITION: Item<V0_5Position> = Item::new("position2");
 
 #[cw_serde]
 pub struct V0_1Config {
@@ -35,9 +40,19 @@ pub struct V0_1Position {
     pub last_compound: cosmwasm_std::Timestamp,
 }
 
+#[cw_serde]
+pub struct V0_5Position {
+    pub position_id: u64,
+}
+
 /// Handle the app migrate msg
 /// The top-level Abstract app does version checking and dispatches to this handler
-pub fn migrate_handler(deps: DepsMut, mut env: Env, app: App, _msg: AppMigrateMsg) -> AppResult {
+pub fn migrate_handler(
+    mut deps: DepsMut,
+    mut env: Env,
+    app: App,
+    _msg: AppMigrateMsg,
+) -> AppResult {
     // Migrate old config
     let maybe_old_config = V0_1CONFIG.may_load(deps.storage)?;
     if let Some(old_config) = maybe_old_config {
@@ -49,6 +64,15 @@ pub fn migrate_handler(deps: DepsMut, mut env: Env, app: App, _msg: AppMigrateMs
             },
             autocompound_cooldown_seconds: old_config.autocompound_cooldown_seconds,
             autocompound_rewards_config: old_config.autocompound_rewards_config,
+            rebalance_grace_period_seconds: None,
+            twap_config: None,
+            incentives_swap_allowlist: vec![],
+            autocompound_mode: AutocompoundMode::default(),
+            compound_ratio: Decimal::one(),
+            payout: None,
+            performance_fee: None,
+            autocompound_max_spread: DEFAULT_AUTOCOMPOUND_MAX_SPREAD,
+            paused: false,
         };
         CONFIG.save(deps.storage, &new_config)?;
         V0_1CONFIG.remove(deps.storage);
@@ -57,12 +81,35 @@ pub fn migrate_handler(deps: DepsMut, mut env: Env, app: App, _msg: AppMigrateMs
         // save_position uses ENV for determining time, so need to trick it here a little
         env.block.time = old_position.last_compound;
         CarrotPosition::save_position(
-            deps.storage,
+            deps.branch(),
             &old_position.last_compound,
+            DEFAULT_POSITION,
             old_position.position_id,
+            DEFAULT_WEIGHT,
         )?;
         V0_1POSITION.remove(deps.storage);
     }
+    // Single position becomes the default position of the positions map
+    if let Some(old_position) = V0_5POSITION.may_load(deps.storage)? {
+        let last_compound = LAST_COMPOUND.load(deps.storage)?;
+        CarrotPosition::save_position(
+            deps.branch(),
+            &last_compound,
+            DEFAULT_POSITION,
+            old_position.position_id,
+            DEFAULT_WEIGHT,
+        )?;
+        V0_5POSITION.remove(deps.storage);
+    }
+    // Positions saved before their funds got tracked start tracking them.
+    // Principal wasn't tracked before, the current funds of the positions count as deposited
+    let mut funds = vec![];
+    for carrot_position in CarrotPosition::load_all(deps.as_ref())? {
+        let position_funds = carrot_position.funds()?;
+        CarrotPosition::save_funds(deps.storage, &carrot_position.name, position_funds.clone())?;
+        funds.extend(position_funds);
+    }
+    seed_principal(deps.storage, &funds)?;
 
     Ok(app.response("migrate"))
 }
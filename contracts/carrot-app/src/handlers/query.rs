@@ -1,19 +1,38 @@
 use abstract_app::{
-    std::objects::AnsAsset,
+    std::objects::{AnsAsset, AssetEntry},
     traits::{AbstractNameService, Resolve},
 };
 use abstract_dex_adapter::DexInterface;
-use cosmwasm_std::{ensure, to_json_binary, Binary, Coin, Decimal, Deps, Env};
+use cosmwasm_std::{ensure, to_json_binary, Binary, Coin, Decimal, Decimal256, Deps, Env, Int256};
 use cw_asset::Asset;
-use osmosis_std::try_proto_to_cosmwasm_coins;
+use osmosis_std::{
+    try_proto_to_cosmwasm_coins, types::osmosis::concentratedliquidity::v1beta1::Pool,
+};
 
 use crate::{
+    cl_math::{parse_osmosis_decimal, tick_to_price},
     contract::{App, AppResult, OSMOSIS},
     error::AppError,
-    handlers::swap_helpers::DEFAULT_MAX_SPREAD,
-    helpers::{get_balance, get_user},
-    msg::{AppQueryMsg, AssetsBalanceResponse, CompoundStatusResponse, PositionResponse},
-    state::{CarrotPosition, Config, CONFIG},
+    handlers::{
+        execute::{_withdraw_amounts, positions_to_withdraw, split_funds, withdraw_liquidity},
+        swap_helpers::{
+            expected_liquidity, ratio_for_range, simulate_enter_position, zap_funds,
+            DEFAULT_MAX_SPREAD,
+        },
+    },
+    helpers::{assert_twap, get_balance, get_user, pool_value, query_pool, range_to_ticks},
+    msg::{
+        AppQueryMsg, AssetsBalanceResponse, CompoundHistoryResponse, CompoundStatusResponse,
+        CreatePositionMessage, HodlComparisonResponse, PerformanceResponse, PositionBalance,
+        PositionDetails, PositionDetailsResponse, PositionInfo, PositionRebalanceStatus,
+        PositionResponse, PositionSimulation, RebalanceStatusResponse,
+        SimulateCreatePositionResponse, SimulateDepositResponse, SimulateWithdrawResponse,
+        SwapSimulation, WithdrawAmount, WithdrawSimulation,
+    },
+    state::{
+        load_compound_history, load_compounded, load_principal, CarrotPosition, Config, CONFIG,
+        DEFAULT_POSITION, LAST_COMPOUND,
+    },
 };
 
 pub fn query_handler(deps: Deps, env: Env, app: &App, msg: AppQueryMsg) -> AppResult<Binary> {
@@ -21,7 +40,41 @@ pub fn query_handler(deps: Deps, env: Env, app: &App, msg: AppQueryMsg) -> AppRe
         AppQueryMsg::Balance {} => to_json_binary(&query_balance(deps, app)?),
         AppQueryMsg::Config {} => to_json_binary(&query_config(deps)?),
         AppQueryMsg::Position {} => to_json_binary(&query_position(deps)?),
+        AppQueryMsg::PositionDetails {} => to_json_binary(&query_position_details(deps, env)?),
         AppQueryMsg::CompoundStatus {} => to_json_binary(&query_compound_status(deps, env, app)?),
+        AppQueryMsg::RebalanceStatus {} => to_json_binary(&query_rebalance_status(deps, env)?),
+        AppQueryMsg::SimulateDeposit {
+            funds,
+            max_spread,
+            belief_price0,
+            belief_price1,
+        } => to_json_binary(&query_simulate_deposit(
+            deps,
+            env,
+            app,
+            funds,
+            max_spread,
+            belief_price0,
+            belief_price1,
+        )?),
+        AppQueryMsg::SimulateWithdraw { amount, position } => {
+            to_json_binary(&query_simulate_withdraw(deps, amount, position)?)
+        }
+        AppQueryMsg::SimulateCreatePosition {
+            create_position_msg,
+        } => to_json_binary(&query_simulate_create_position(
+            deps,
+            env,
+            app,
+            create_position_msg,
+        )?),
+        AppQueryMsg::Performance {} => to_json_binary(&query_performance(deps, app)?),
+        AppQueryMsg::HodlComparison { quote_asset } => {
+            to_json_binary(&query_hodl_comparison(deps, quote_asset)?)
+        }
+        AppQueryMsg::CompoundHistory { start_after, limit } => {
+            to_json_binary(&query_compound_history(deps, start_after, limit)?)
+        }
     }
     .map_err(Into::into)
 }
@@ -31,17 +84,21 @@ pub fn query_handler(deps: Deps, env: Env, app: &App, msg: AppQueryMsg) -> AppRe
 fn query_compound_status(deps: Deps, env: Env, app: &App) -> AppResult<CompoundStatusResponse> {
     let config = CONFIG.load(deps.storage)?;
 
-    let (status, maybe_carrot_position) =
+    let (status, carrot_positions) =
         CarrotPosition::compound_status(deps, &env, config.autocompound_cooldown_seconds.u64())?;
 
-    let (spread_rewards, incentives) = if let Some(carrot_position) = maybe_carrot_position {
-        (
+    let mut spread_rewards = vec![];
+    let mut incentives = vec![];
+    for carrot_position in carrot_positions {
+        add_coins(
+            &mut spread_rewards,
             try_proto_to_cosmwasm_coins(carrot_position.position.claimable_spread_rewards)?,
+        );
+        add_coins(
+            &mut incentives,
             try_proto_to_cosmwasm_coins(carrot_position.position.claimable_incentives)?,
-        )
-    } else {
-        (vec![], vec![])
-    };
+        );
+    }
 
     let gas_denom = config
         .autocompound_rewards_config
@@ -84,9 +141,251 @@ fn query_compound_status(deps: Deps, env: Env, app: &App) -> AppResult<CompoundS
     })
 }
 
+/// Gets the status of the permissionless rebalance for every position
+fn query_rebalance_status(deps: Deps, env: Env) -> AppResult<RebalanceStatusResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let permissionless = config.rebalance_grace_period_seconds.is_some();
+    let grace_period_seconds = config
+        .rebalance_grace_period_seconds
+        .map(|grace_period| grace_period.u64())
+        .unwrap_or_default();
+
+    let carrot_positions = CarrotPosition::load_all(deps)?;
+    if carrot_positions.is_empty() {
+        return Ok(RebalanceStatusResponse {
+            positions: vec![],
+            permissionless,
+        });
+    }
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+
+    let positions = carrot_positions
+        .into_iter()
+        .map(|carrot_position| {
+            let status = carrot_position.rebalance_status(
+                deps.storage,
+                &env,
+                pool.current_tick,
+                grace_period_seconds,
+            )?;
+            Ok(PositionRebalanceStatus {
+                name: carrot_position.name,
+                status,
+            })
+        })
+        .collect::<AppResult<_>>()?;
+    Ok(RebalanceStatusResponse {
+        positions,
+        permissionless,
+    })
+}
+
+/// Previews a deposit the same way the deposit splits and swaps the funds, without the messages
+fn query_simulate_deposit(
+    deps: Deps,
+    env: Env,
+    app: &App,
+    funds: Vec<Coin>,
+    max_spread: Option<Decimal>,
+    belief_price0: Option<Decimal>,
+    belief_price1: Option<Decimal>,
+) -> AppResult<SimulateDepositResponse> {
+    let carrot_positions = CarrotPosition::load_all(deps)?;
+    if carrot_positions.is_empty() {
+        return Err(AppError::NoPosition {});
+    }
+    let config = CONFIG.load(deps.storage)?;
+    let (zap_swaps, funds) = zap_funds(deps, &env, app, &config, funds)?;
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+
+    let mut positions = vec![];
+    for (position_funds, carrot_position) in split_funds(funds, &carrot_positions)
+        .into_iter()
+        .zip(carrot_positions)
+    {
+        if position_funds.iter().all(|coin| coin.amount.is_zero()) {
+            continue;
+        }
+        let asset0: Coin = carrot_position.position.asset0.unwrap().try_into()?;
+        let asset1: Coin = carrot_position.position.asset1.unwrap().try_into()?;
+        let position_details = carrot_position.position.position.unwrap();
+
+        positions.push(simulate_position(
+            deps,
+            &env,
+            app,
+            &pool,
+            carrot_position.name,
+            (position_details.lower_tick, position_details.upper_tick),
+            position_funds,
+            (asset0, asset1),
+            max_spread,
+            belief_price0,
+            belief_price1,
+        )?);
+    }
+    Ok(SimulateDepositResponse {
+        zap_swaps,
+        positions,
+    })
+}
+
+/// Previews the funds a withdraw returns from every position
+fn query_simulate_withdraw(
+    deps: Deps,
+    amount: Option<WithdrawAmount>,
+    position: Option<String>,
+) -> AppResult<SimulateWithdrawResponse> {
+    let mut withdrawn = vec![];
+    let mut positions = vec![];
+    for carrot_position in positions_to_withdraw(deps, amount.as_ref(), position)? {
+        let liquidity_amount = withdraw_liquidity(deps, amount.as_ref(), &carrot_position)?;
+        let (liquidity_amount, total_liquidity, withdrawn_funds) =
+            _withdraw_amounts(liquidity_amount, &carrot_position)?;
+
+        add_coins(&mut withdrawn, withdrawn_funds.to_vec());
+        positions.push(WithdrawSimulation {
+            name: carrot_position.name,
+            liquidity_amount,
+            total_liquidity,
+            withdrawn: withdrawn_funds.to_vec(),
+        });
+    }
+    Ok(SimulateWithdrawResponse {
+        withdrawn,
+        positions,
+    })
+}
+
+/// Previews a position creation the same way the position gets created, without the messages
+fn query_simulate_create_position(
+    deps: Deps,
+    env: Env,
+    app: &App,
+    create_position_msg: CreatePositionMessage,
+) -> AppResult<SimulateCreatePositionResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    let (lower_tick, upper_tick) = range_to_ticks(&pool, &create_position_msg.range)?;
+    let ratio = ratio_for_range(&pool, lower_tick, upper_tick)?;
+
+    let position = simulate_position(
+        deps,
+        &env,
+        app,
+        &pool,
+        create_position_msg
+            .name
+            .unwrap_or_else(|| DEFAULT_POSITION.to_owned()),
+        (lower_tick, upper_tick),
+        create_position_msg.funds,
+        ratio,
+        create_position_msg.max_spread,
+        create_position_msg.belief_price0,
+        create_position_msg.belief_price1,
+    )?;
+    Ok(SimulateCreatePositionResponse {
+        lower_tick,
+        upper_tick,
+        position,
+    })
+}
+
+/// Simulates the swap and the provision of `funds` to the position of the `ticks` range,
+/// `ratio` is the token ratio the position takes
+#[allow(clippy::too_many_arguments)]
+fn simulate_position(
+    deps: Deps,
+    env: &Env,
+    app: &App,
+    pool: &Pool,
+    name: String,
+    (lower_tick, upper_tick): (i64, i64),
+    funds: Vec<Coin>,
+    (asset0, asset1): (Coin, Coin),
+    max_spread: Option<Decimal>,
+    belief_price0: Option<Decimal>,
+    belief_price1: Option<Decimal>,
+) -> AppResult<PositionSimulation> {
+    let (offer_asset, ask_asset, assets_for_position) = simulate_enter_position(
+        deps,
+        env,
+        funds,
+        app,
+        asset0,
+        asset1,
+        max_spread,
+        belief_price0,
+        belief_price1,
+    )?;
+    let (liquidity, dust) = expected_liquidity(pool, lower_tick, upper_tick, &assets_for_position)?;
+
+    let swap = (!offer_asset.amount.is_zero()).then(|| SwapSimulation {
+        offer_asset: offer_asset.name,
+        ask_asset,
+        amount: offer_asset.amount,
+    });
+    Ok(PositionSimulation {
+        name,
+        swap,
+        assets_for_position: vec![assets_for_position.asset0, assets_for_position.asset1],
+        liquidity: liquidity.atomics().to_string(),
+        dust: dust.to_vec(),
+    })
+}
+
 fn query_position(deps: Deps) -> AppResult<PositionResponse> {
-    let position_id = CarrotPosition::may_load(deps)?.map(|carrot_position| carrot_position.id);
-    Ok(PositionResponse { position_id })
+    let positions = CarrotPosition::load_all(deps)?
+        .into_iter()
+        .map(|carrot_position| PositionInfo {
+            name: carrot_position.name,
+            position_id: carrot_position.id,
+            weight: carrot_position.weight,
+        })
+        .collect();
+    Ok(PositionResponse { positions })
+}
+
+fn query_position_details(deps: Deps, env: Env) -> AppResult<PositionDetailsResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    let current_sqrt_price = parse_osmosis_decimal(&pool.current_sqrt_price)?;
+
+    let mut positions = vec![];
+    for carrot_position in CarrotPosition::load_all(deps)? {
+        let in_range = carrot_position.is_in_range(pool.current_tick);
+        let breakdown = carrot_position.position;
+        let position = breakdown.position.unwrap();
+        positions.push(PositionDetails {
+            name: carrot_position.name,
+            position_id: carrot_position.id,
+            weight: carrot_position.weight,
+            lower_tick: position.lower_tick,
+            upper_tick: position.upper_tick,
+            lower_price: tick_to_price(position.lower_tick)?,
+            upper_price: tick_to_price(position.upper_tick)?,
+            in_range,
+            liquidity: parse_osmosis_decimal(&position.liquidity)?,
+            balances: try_proto_to_cosmwasm_coins(vec![
+                breakdown.asset0.unwrap(),
+                breakdown.asset1.unwrap(),
+            ])?,
+            claimable_spread_rewards: try_proto_to_cosmwasm_coins(
+                breakdown.claimable_spread_rewards,
+            )?,
+            claimable_incentives: try_proto_to_cosmwasm_coins(breakdown.claimable_incentives)?,
+        });
+    }
+
+    let seconds_since_last_compound = LAST_COMPOUND
+        .may_load(deps.storage)?
+        .map(|last_compound| env.block.time.seconds() - last_compound.seconds());
+    Ok(PositionDetailsResponse {
+        current_tick: pool.current_tick,
+        current_price: current_sqrt_price * current_sqrt_price,
+        seconds_since_last_compound,
+        positions,
+    })
 }
 
 fn query_config(deps: Deps) -> AppResult<Config> {
@@ -94,32 +393,130 @@ fn query_config(deps: Deps) -> AppResult<Config> {
 }
 
 fn query_balance(deps: Deps, _app: &App) -> AppResult<AssetsBalanceResponse> {
-    let Some(carrot_position) = CarrotPosition::may_load(deps)? else {
-        return Ok(AssetsBalanceResponse {
-            balances: vec![],
-            liquidity: "0".to_string(),
+    let mut balances = vec![];
+    let mut liquidity = Decimal256::zero();
+    let mut positions = vec![];
+
+    for carrot_position in CarrotPosition::load_all(deps)? {
+        let position_balances = try_proto_to_cosmwasm_coins(vec![
+            carrot_position.position.asset0.unwrap(),
+            carrot_position.position.asset1.unwrap(),
+        ])?;
+        let position_liquidity: Decimal256 = carrot_position
+            .position
+            .position
+            .unwrap()
+            .liquidity
+            .parse()?;
+
+        add_coins(&mut balances, position_balances.clone());
+        liquidity += position_liquidity;
+        positions.push(PositionBalance {
+            name: carrot_position.name,
+            balances: position_balances,
+            // Decimal to atomics
+            liquidity: position_liquidity.atomics().to_string(),
         });
-    };
+    }
 
-    let balances = try_proto_to_cosmwasm_coins(vec![
-        carrot_position.position.asset0.unwrap(),
-        carrot_position.position.asset1.unwrap(),
-    ])?;
-    // Decimal to atomics
-    let liquidity = carrot_position
-        .position
-        .position
-        .unwrap()
-        .liquidity
-        .replace('.', "");
     Ok(AssetsBalanceResponse {
         balances,
-        liquidity,
+        liquidity: liquidity.atomics().to_string(),
+        positions,
+    })
+}
+
+fn query_performance(deps: Deps, app: &App) -> AppResult<PerformanceResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+
+    let principal = load_principal(deps.storage)?;
+    let balances = query_balance(deps, app)?.balances;
+    let principal_value = pool_value(&pool, &principal, &pool.token1)?;
+    let balance_value = pool_value(&pool, &balances, &pool.token1)?;
+
+    Ok(PerformanceResponse {
+        principal,
+        compounded: load_compounded(deps.storage)?,
+        balances,
+        principal_value,
+        balance_value,
+        net_yield: Int256::from(balance_value.u128()) - Int256::from(principal_value.u128()),
     })
 }
 
+fn query_hodl_comparison(
+    deps: Deps,
+    quote_asset: Option<AssetEntry>,
+) -> AppResult<HodlComparisonResponse> {
+    let config = CONFIG.load(deps.storage)?;
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    let quote_asset = quote_asset.unwrap_or_else(|| config.pool_config.asset1.clone());
+    let quote_denom = if quote_asset == config.pool_config.asset0 {
+        &pool.token0
+    } else if quote_asset == config.pool_config.asset1 {
+        &pool.token1
+    } else {
+        return Err(AppError::NotPoolAsset { asset: quote_asset });
+    };
+
+    let mut balances = vec![];
+    let mut unclaimed_rewards = cosmwasm_std::Coins::default();
+    for carrot_position in CarrotPosition::load_all(deps)? {
+        let position = carrot_position.position;
+        balances.extend(try_proto_to_cosmwasm_coins(vec![
+            position.asset0.unwrap(),
+            position.asset1.unwrap(),
+        ])?);
+        for coin in try_proto_to_cosmwasm_coins(position.claimable_spread_rewards)?
+            .into_iter()
+            .chain(try_proto_to_cosmwasm_coins(position.claimable_incentives)?)
+        {
+            unclaimed_rewards.add(coin)?;
+        }
+    }
+
+    let hodl_value = pool_value(&pool, &load_principal(deps.storage)?, quote_denom)?;
+    let position_value = pool_value(&pool, &balances, quote_denom)?;
+    let compounded_value = pool_value(&pool, &load_compounded(deps.storage)?, quote_denom)?;
+    let unclaimed_value = pool_value(&pool, &unclaimed_rewards.to_vec(), quote_denom)?;
+
+    let hodl = Int256::from(hodl_value.u128());
+    Ok(HodlComparisonResponse {
+        quote_asset,
+        hodl_value,
+        position_value,
+        fees_earned: compounded_value + unclaimed_value,
+        impermanent_loss: Int256::from(position_value.u128())
+            - Int256::from(compounded_value.u128())
+            - hodl,
+        lp_vs_hodl: Int256::from((position_value + unclaimed_value).u128()) - hodl,
+    })
+}
+
+fn query_compound_history(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> AppResult<CompoundHistoryResponse> {
+    Ok(CompoundHistoryResponse {
+        events: load_compound_history(deps.storage, start_after, limit)?,
+    })
+}
+
+/// Adds coins to the total, keeping the order of the denoms
+fn add_coins(total: &mut Vec<Coin>, coins: Vec<Coin>) {
+    for coin in coins {
+        match total.iter_mut().find(|c| c.denom == coin.denom) {
+            Some(total_coin) => total_coin.amount += coin.amount,
+            None => total.push(coin),
+        }
+    }
+}
+
 pub fn query_price(
     deps: Deps,
+    env: &Env,
     funds: &[Coin],
     app: &App,
     max_spread: Option<Decimal>,
@@ -183,5 +580,25 @@ pub fn query_price(
         price
     };
 
+    // Reject spot prices manipulated away from the time weighted average of the pool,
+    // the price impact of the swap itself doesn't count
+    if let Some(twap_config) = config.twap_config {
+        let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+        let sqrt_price = parse_osmosis_decimal(&pool.current_sqrt_price)?;
+        // Price of token1 in token0, the same way as the TWAP
+        let spot_price = Decimal256::one() / (sqrt_price * sqrt_price);
+        let spot_price = Decimal::try_from(spot_price)
+            .map_err(|_| AppError::PriceOutOfBounds { price: spot_price })?;
+        assert_twap(
+            &deps.querier,
+            env,
+            &twap_config,
+            config.pool_config.pool_id,
+            token0,
+            token1,
+            spot_price,
+        )?;
+    }
+
     Ok(price)
 }
@@ -1,16 +1,27 @@
 use abstract_app::{
-    objects::{AnsAsset, AssetEntry},
-    sdk::AuthZInterface,
+    objects::{AnsAsset, AssetEntry, DexAssetPairing},
+    sdk::{feature_objects::AnsHost, AuthZInterface},
+    traits::{AbstractNameService, Resolve},
 };
 use abstract_dex_adapter::{msg::GenerateMessagesResponse, DexInterface};
-use cosmwasm_std::{Coin, CosmosMsg, Decimal, Deps, Env, Uint128};
-use osmosis_std::cosmwasm_to_proto_coins;
+use cosmwasm_std::{
+    ensure, Coin, CosmosMsg, Decimal, Decimal256, Deps, DepsMut, Env, Fraction, StdResult, SubMsg,
+    Uint128, Uint256,
+};
+use cw_asset::AssetInfo;
+use osmosis_std::{cosmwasm_to_proto_coins, types::osmosis::concentratedliquidity::v1beta1::Pool};
 pub const DEFAULT_MAX_SPREAD: Decimal = Decimal::percent(20);
+/// Biggest amount used to express the token ratio of a range
+const RATIO_PRECISION: u128 = 1_000_000_000_000;
 
 use crate::{
+    cl_math::{amounts_for_liquidity, liquidity_for_amounts, parse_osmosis_decimal},
     contract::{App, AppResult, OSMOSIS},
-    helpers::get_user,
-    state::CONFIG,
+    error::AppError,
+    helpers::{assert_twap, get_user, pool_funds, query_pool, query_spot_price},
+    msg::SwapSimulation,
+    replies::ZAP_ID,
+    state::{Config, PendingZap, TwapConfig, ZapTarget, CONFIG, TEMP_ZAP},
 };
 
 use super::query::query_price;
@@ -31,6 +42,35 @@ impl From<AssetsForPosition> for Vec<osmosis_std::types::cosmos::base::v1beta1::
     }
 }
 
+impl AssetsForPosition {
+    /// Minimum amounts the position has to take, protects the liquidity provision against
+    /// price moves between the swap and the provision.
+    /// Derived from `max_spread` when not provided explicitly
+    pub fn min_amounts(
+        &self,
+        max_spread: Option<Decimal>,
+        token_min_amount0: Option<Uint128>,
+        token_min_amount1: Option<Uint128>,
+    ) -> AppResult<(Uint128, Uint128)> {
+        let min_share = Decimal::one().saturating_sub(max_spread.unwrap_or(DEFAULT_MAX_SPREAD));
+        let token_min_amount0 =
+            token_min_amount0.unwrap_or_else(|| self.asset0.amount.mul_floor(min_share));
+        let token_min_amount1 =
+            token_min_amount1.unwrap_or_else(|| self.asset1.amount.mul_floor(min_share));
+
+        // Fail early if the swapped funds can't meet the minimum amounts
+        ensure!(
+            token_min_amount0 <= self.asset0.amount && token_min_amount1 <= self.asset1.amount,
+            AppError::LiquiditySlippage {
+                token_min_amount0,
+                token_min_amount1,
+                reason: format!("expected to provide {} and {}", self.asset0, self.asset1),
+            }
+        );
+        Ok((token_min_amount0, token_min_amount1))
+    }
+}
+
 pub(crate) fn swap_msg(
     deps: Deps,
     env: &Env,
@@ -101,13 +141,15 @@ pub(crate) fn tokens_to_swap(
     // Therefore, the following equation needs to be true
     // (x0-y0)*A1 = (x1 + y0/price)*A0 or y0 = (x0*a1 - x1*a0)/(a1 + a0/p)
 
-    let x0_a1 = x0.amount * asset1.amount;
-    let x1_a0 = x1.amount * asset0.amount;
+    // Products of amounts overflow 128 bits for big amounts
+    let x0_a1 = x0.amount.full_mul(asset1.amount);
+    let x1_a0 = x1.amount.full_mul(asset0.amount);
 
     let (offer_asset, ask_asset, assets_for_position) = if x0_a1 < x1_a0 {
         let numerator = x1_a0 - x0_a1;
-        let denominator = asset0.amount + price * asset1.amount;
-        let y1 = numerator / denominator;
+        let denominator =
+            Uint256::from(asset0.amount) + Uint256::from(asset1.amount).mul_floor(price);
+        let y1 = Uint128::try_from(numerator / denominator)?;
 
         (
             AnsAsset::new(config.pool_config.asset1, y1),
@@ -125,12 +167,12 @@ pub(crate) fn tokens_to_swap(
         )
     } else {
         let numerator = x0_a1 - x1_a0;
-        let denominator =
-            asset1.amount + Decimal::from_ratio(asset0.amount, 1u128) / price * Uint128::one();
-        let y0 = numerator / denominator;
+        let denominator = Uint256::from(asset1.amount)
+            + Uint256::from(asset0.amount).multiply_ratio(price.denominator(), price.numerator());
+        let y0 = Uint128::try_from(numerator / denominator)?;
 
         (
-            AnsAsset::new(config.pool_config.asset0, numerator / denominator),
+            AnsAsset::new(config.pool_config.asset0, y0),
             config.pool_config.asset1,
             AssetsForPosition {
                 asset0: Coin {
@@ -138,7 +180,7 @@ pub(crate) fn tokens_to_swap(
                     denom: x0.denom,
                 },
                 asset1: Coin {
-                    amount: x1.amount + Decimal::from_ratio(y0, 1u128) / price * Uint128::one(),
+                    amount: x1.amount + y0.multiply_ratio(price.denominator(), price.numerator()),
                     denom: x1.denom,
                 },
             },
@@ -148,6 +190,318 @@ pub(crate) fn tokens_to_swap(
     Ok((offer_asset, ask_asset, assets_for_position))
 }
 
+/// Route of assets to swap `offer_asset` into one of the pool assets.
+/// Swaps directly when the asset has a pairing with a pool asset,
+/// otherwise hops through the gas asset of the autocompound rewards
+pub(crate) fn swap_route(
+    deps: Deps,
+    ans_host: &AnsHost,
+    config: &Config,
+    offer_asset: &AssetEntry,
+) -> AppResult<Vec<AssetEntry>> {
+    let pool_config = &config.pool_config;
+    if let Ok(pool_asset) = pool_config.swap_target(deps, OSMOSIS, ans_host, offer_asset) {
+        return Ok(vec![pool_asset]);
+    }
+
+    let hop_asset = &config.autocompound_rewards_config.gas_asset;
+    ensure!(offer_asset != hop_asset, AppError::NoSwapPossibility {});
+    DexAssetPairing::new(offer_asset.clone(), hop_asset.clone(), OSMOSIS)
+        .resolve(&deps.querier, ans_host)
+        .map_err(|_| AppError::NoSwapPossibility {})?;
+    let pool_asset = pool_config.swap_target(deps, OSMOSIS, ans_host, hop_asset)?;
+    Ok(vec![hop_asset.clone(), pool_asset])
+}
+
+/// Route of assets to swap `offer_asset` into `ask_asset`.
+/// Swaps directly when the assets have a pairing,
+/// otherwise hops through one of the pool assets or the gas asset of the autocompound rewards
+pub(crate) fn route_to_asset(
+    deps: Deps,
+    ans_host: &AnsHost,
+    config: &Config,
+    offer_asset: &AssetEntry,
+    ask_asset: &AssetEntry,
+) -> AppResult<Vec<AssetEntry>> {
+    let has_pairing = |a: &AssetEntry, b: &AssetEntry| {
+        DexAssetPairing::new(a.clone(), b.clone(), OSMOSIS)
+            .resolve(&deps.querier, ans_host)
+            .is_ok()
+    };
+    if has_pairing(offer_asset, ask_asset) {
+        return Ok(vec![ask_asset.clone()]);
+    }
+
+    [
+        &config.pool_config.asset0,
+        &config.pool_config.asset1,
+        &config.autocompound_rewards_config.gas_asset,
+    ]
+    .into_iter()
+    .filter(|hop_asset| *hop_asset != offer_asset && *hop_asset != ask_asset)
+    .find(|hop_asset| has_pairing(offer_asset, hop_asset) && has_pairing(hop_asset, ask_asset))
+    .map(|hop_asset| vec![hop_asset.clone(), ask_asset.clone()])
+    .ok_or(AppError::NoSwapPossibility {})
+}
+
+/// Rejects the swap of `offer_asset` into `ask_asset` when the spot price of the pool
+/// it goes through deviates from the TWAP of the pool, see [`TwapConfig`]
+pub(crate) fn assert_swap_twap(
+    deps: Deps,
+    env: &Env,
+    ans_host: &AnsHost,
+    twap_config: &TwapConfig,
+    offer_asset: &AssetEntry,
+    ask_asset: &AssetEntry,
+) -> AppResult<()> {
+    let pool_id = DexAssetPairing::new(offer_asset.clone(), ask_asset.clone(), OSMOSIS)
+        .resolve(&deps.querier, ans_host)?
+        .first()
+        .ok_or(AppError::NoSwapPossibility {})?
+        .pool_address
+        .expect_id()?;
+    let offer_denom = offer_asset.resolve(&deps.querier, ans_host)?.inner();
+    let ask_denom = ask_asset.resolve(&deps.querier, ans_host)?.inner();
+    let spot_price = query_spot_price(
+        &deps.querier,
+        pool_id,
+        offer_denom.clone(),
+        ask_denom.clone(),
+    )?;
+    assert_twap(
+        &deps.querier,
+        env,
+        twap_config,
+        pool_id,
+        offer_denom,
+        ask_denom,
+        spot_price,
+    )
+}
+
+/// Simulates the swaps of `offer_asset` along the `route`, the amount of every hop is simulated from the previous one.
+/// Every hop goes through the TWAP guard when configured.
+/// Returns the swaps and the simulated amount of the last asset of the route
+pub(crate) fn simulate_route(
+    deps: Deps,
+    env: &Env,
+    app: &App,
+    offer_asset: AnsAsset,
+    route: &[AssetEntry],
+) -> AppResult<(Vec<SwapSimulation>, Uint128)> {
+    let twap_config = CONFIG.load(deps.storage)?.twap_config;
+    let ans_host = app.ans_host(deps)?;
+    let dex = app.ans_dex(deps, OSMOSIS.to_string());
+    let mut swaps = vec![];
+    let mut offer_asset = offer_asset;
+    for ask_asset in route {
+        if let Some(twap_config) = &twap_config {
+            assert_swap_twap(
+                deps,
+                env,
+                &ans_host,
+                twap_config,
+                &offer_asset.name,
+                ask_asset,
+            )?;
+        }
+        let simulation_result = dex.simulate_swap(offer_asset.clone(), ask_asset.clone())?;
+        swaps.push(SwapSimulation {
+            offer_asset: offer_asset.name,
+            ask_asset: ask_asset.clone(),
+            amount: offer_asset.amount,
+        });
+        offer_asset = AnsAsset::new(ask_asset.clone(), simulation_result.return_amount);
+    }
+    Ok((swaps, offer_asset.amount))
+}
+
+/// Messages executing the simulated swaps
+pub(crate) fn simulated_swap_msgs(
+    deps: Deps,
+    env: &Env,
+    app: &App,
+    swaps: Vec<SwapSimulation>,
+    max_spread: Option<Decimal>,
+) -> AppResult<Vec<CosmosMsg>> {
+    let mut swap_msgs = vec![];
+    for swap in swaps {
+        swap_msgs.extend(swap_msg(
+            deps,
+            env,
+            AnsAsset::new(swap.offer_asset, swap.amount),
+            swap.ask_asset,
+            max_spread,
+            app,
+        )?);
+    }
+    Ok(swap_msgs)
+}
+
+/// Swaps `offer_asset` along the `route`, the amount of every hop is simulated from the previous one.
+/// Returns the swap messages and the simulated amount of the last asset of the route
+pub(crate) fn route_swap_msgs(
+    deps: Deps,
+    env: &Env,
+    app: &App,
+    offer_asset: AnsAsset,
+    route: &[AssetEntry],
+    max_spread: Option<Decimal>,
+) -> AppResult<(Vec<CosmosMsg>, Uint128)> {
+    let (swaps, amount) = simulate_route(deps, env, app, offer_asset, route)?;
+    let swap_msgs = simulated_swap_msgs(deps, env, app, swaps, max_spread)?;
+    Ok((swap_msgs, amount))
+}
+
+/// Plans the swaps of the funds that are not pool assets into the pool assets.
+/// Returns the swaps and the funds expressed in pool assets
+pub(crate) fn zap_funds(
+    deps: Deps,
+    env: &Env,
+    app: &App,
+    config: &Config,
+    funds: Vec<Coin>,
+) -> AppResult<(Vec<SwapSimulation>, Vec<Coin>)> {
+    let ans = app.name_service(deps);
+    let pool_assets = [&config.pool_config.asset0, &config.pool_config.asset1];
+
+    let mut swaps = vec![];
+    let mut zapped_funds = cosmwasm_std::Coins::default();
+    for coin in funds {
+        let asset = ans.query(&AssetInfo::native(&coin.denom))?;
+        if pool_assets.contains(&&asset) || coin.amount.is_zero() {
+            zapped_funds.add(coin)?;
+            continue;
+        }
+
+        let route = swap_route(deps, ans.host(), config, &asset)?;
+        let (route_swaps, amount) =
+            simulate_route(deps, env, app, AnsAsset::new(asset, coin.amount), &route)?;
+        swaps.extend(route_swaps);
+        zapped_funds.add(Coin {
+            denom: ans.query(route.last().unwrap())?.inner(),
+            amount,
+        })?;
+    }
+    Ok((swaps, zapped_funds.into()))
+}
+
+/// Swaps the funds that are not pool assets into the pool assets.
+/// The swapped amounts are only known once the swaps are executed, the reply of the last swap
+/// measures them and uses the funds for the `target`.
+/// Returns no messages when all funds are pool assets already
+pub(crate) fn zap_msgs(
+    deps: DepsMut,
+    env: &Env,
+    app: &App,
+    config: &Config,
+    funds: Vec<Coin>,
+    max_spread: Option<Decimal>,
+    target: ZapTarget,
+) -> AppResult<Vec<SubMsg>> {
+    let (zap_swaps, _) = zap_funds(deps.as_ref(), env, app, config, funds.clone())?;
+    let mut swap_msgs = simulated_swap_msgs(deps.as_ref(), env, app, zap_swaps, max_spread)?;
+    let Some(last_swap_msg) = swap_msgs.pop() else {
+        return Ok(vec![]);
+    };
+
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+    let user = get_user(deps.as_ref(), app)?;
+    let balances_before = [&pool.token0, &pool.token1]
+        .into_iter()
+        .map(|denom| deps.querier.query_balance(&user, denom))
+        .collect::<StdResult<_>>()?;
+    TEMP_ZAP.save(
+        deps.storage,
+        &PendingZap {
+            funds: pool_funds(&pool, &funds),
+            balances_before,
+            target,
+        },
+    )?;
+    Ok(swap_msgs
+        .into_iter()
+        .map(SubMsg::new)
+        .chain([SubMsg::reply_on_success(last_swap_msg, ZAP_ID)])
+        .collect())
+}
+
+/// Incentive tokens of the allowlist, they get swapped into the pool assets when compounded.
+/// Other incentive tokens stay in the wallet
+pub(crate) fn allowlisted_incentives(
+    deps: Deps,
+    app: &App,
+    config: &Config,
+    incentives: &[Coin],
+) -> AppResult<Vec<Coin>> {
+    let mut allowlisted = vec![];
+    if incentives.is_empty() {
+        return Ok(allowlisted);
+    }
+
+    let ans_host = app.ans_host(deps)?;
+    for incentive_asset in &config.incentives_swap_allowlist {
+        let incentive_info = incentive_asset.resolve(&deps.querier, &ans_host)?;
+        if let Some(incentive) = incentives
+            .iter()
+            .find(|coin| incentive_info == AssetInfo::native(&coin.denom))
+        {
+            allowlisted.push(incentive.clone());
+        }
+    }
+    Ok(allowlisted)
+}
+
+/// Computes the token0/token1 amounts a position in the `[lower_tick, upper_tick)` range
+/// holds at the current price of the pool.
+/// Only the ratio between the returned amounts is meaningful, see [`tokens_to_swap`]
+pub(crate) fn ratio_for_range(
+    pool: &Pool,
+    lower_tick: i64,
+    upper_tick: i64,
+) -> AppResult<(Coin, Coin)> {
+    let sqrt_price = parse_osmosis_decimal(&pool.current_sqrt_price)?;
+    let (amount0, amount1) =
+        amounts_for_liquidity(sqrt_price, lower_tick, upper_tick, Decimal256::one())?;
+
+    // Scale amounts up so the biggest one equals `RATIO_PRECISION`
+    let scale = Decimal256::from_ratio(RATIO_PRECISION, 1u128) / amount0.max(amount1);
+    let asset0 = Coin {
+        denom: pool.token0.clone(),
+        amount: Uint128::try_from((amount0 * scale).to_uint_floor())?,
+    };
+    let asset1 = Coin {
+        denom: pool.token1.clone(),
+        amount: Uint128::try_from((amount1 * scale).to_uint_floor())?,
+    };
+    Ok((asset0, asset1))
+}
+
+/// Computes the swap balancing `funds` to the `asset0`/`asset1` ratio and the assets that go into the position
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn simulate_enter_position(
+    deps: Deps,
+    env: &Env,
+    funds: Vec<Coin>,
+    app: &App,
+    asset0: Coin,
+    asset1: Coin,
+    max_spread: Option<Decimal>,
+    belief_price0: Option<Decimal>,
+    belief_price1: Option<Decimal>,
+) -> AppResult<(AnsAsset, AssetEntry, AssetsForPosition)> {
+    let price = query_price(
+        deps,
+        env,
+        &funds,
+        app,
+        max_spread,
+        belief_price0,
+        belief_price1,
+    )?;
+    tokens_to_swap(deps, funds, asset0, asset1, price)
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn swap_to_enter_position(
     deps: Deps,
@@ -160,9 +514,17 @@ pub fn swap_to_enter_position(
     belief_price0: Option<Decimal>,
     belief_price1: Option<Decimal>,
 ) -> AppResult<(Vec<CosmosMsg>, AssetsForPosition)> {
-    let price = query_price(deps, &funds, app, max_spread, belief_price0, belief_price1)?;
-    let (offer_asset, ask_asset, assets_for_position) =
-        tokens_to_swap(deps, funds, asset0, asset1, price)?;
+    let (offer_asset, ask_asset, assets_for_position) = simulate_enter_position(
+        deps,
+        env,
+        funds,
+        app,
+        asset0,
+        asset1,
+        max_spread,
+        belief_price0,
+        belief_price1,
+    )?;
 
     Ok((
         swap_msg(deps, env, offer_asset, ask_asset, max_spread, app)?,
@@ -170,11 +532,48 @@ pub fn swap_to_enter_position(
     ))
 }
 
+/// Liquidity the `[lower_tick, upper_tick)` range gets from `assets_for_position` at the current price of the pool.
+/// Returns the liquidity and the dust: the funds the range can't take
+pub(crate) fn expected_liquidity(
+    pool: &Pool,
+    lower_tick: i64,
+    upper_tick: i64,
+    assets_for_position: &AssetsForPosition,
+) -> AppResult<(Decimal256, [Coin; 2])> {
+    let sqrt_price = parse_osmosis_decimal(&pool.current_sqrt_price)?;
+    let provided0 = assets_for_position.asset0.amount;
+    let provided1 = assets_for_position.asset1.amount;
+
+    let liquidity = liquidity_for_amounts(
+        sqrt_price,
+        lower_tick,
+        upper_tick,
+        Decimal256::from_ratio(provided0, 1u128),
+        Decimal256::from_ratio(provided1, 1u128),
+    )?;
+    let (used0, used1) = amounts_for_liquidity(sqrt_price, lower_tick, upper_tick, liquidity)?;
+
+    let dust = [
+        Coin {
+            denom: assets_for_position.asset0.denom.clone(),
+            amount: provided0.saturating_sub(Uint128::try_from(used0.to_uint_ceil())?),
+        },
+        Coin {
+            denom: assets_for_position.asset1.denom.clone(),
+            amount: provided1.saturating_sub(Uint128::try_from(used1.to_uint_ceil())?),
+        },
+    ];
+    Ok((liquidity, dust))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::state::{AutocompoundRewardsConfig, Config, PoolConfig};
+    use crate::state::{
+        AutocompoundMode, AutocompoundRewardsConfig, Config, PoolConfig,
+        DEFAULT_AUTOCOMPOUND_MAX_SPREAD,
+    };
     use cosmwasm_std::{coin, coins, testing::mock_dependencies, DepsMut, Uint64};
     pub const DEPOSIT_TOKEN: &str = "USDC";
     pub const TOKEN0: &str = "USDT";
@@ -206,6 +605,15 @@ mod tests {
                     min_gas_balance: Uint128::zero(),
                     max_gas_balance: Uint128::new(1),
                 },
+                rebalance_grace_period_seconds: None,
+                twap_config: None,
+                incentives_swap_allowlist: vec![],
+                autocompound_mode: AutocompoundMode::default(),
+                compound_ratio: Decimal::one(),
+                payout: None,
+                performance_fee: None,
+                autocompound_max_spread: DEFAULT_AUTOCOMPOUND_MAX_SPREAD,
+                paused: false,
             },
         )?;
         Ok(())
@@ -235,6 +643,42 @@ mod tests {
         assert_eq!(ask_asset, AssetEntry::new("usdt"));
     }
 
+    #[test]
+    fn swap_for_big_amounts() {
+        let mut deps = mock_dependencies();
+        setup_config(deps.as_mut()).unwrap();
+        let amount = 1_000_000_000_000_000_000_000_000u128;
+
+        let (swap, ask_asset, final_asset) = tokens_to_swap(
+            deps.as_ref(),
+            coins(amount, TOKEN1),
+            coin(3 * amount, TOKEN0),
+            coin(amount, TOKEN1),
+            Decimal::one(),
+        )
+        .unwrap();
+        assert_eq!(swap.amount, Uint128::new(amount * 3 / 4));
+        assert_eq!(swap.name, AssetEntry::new(TOKEN1));
+        assert_eq!(ask_asset, AssetEntry::new(TOKEN0));
+        assert_eq!(final_asset.asset0.amount, Uint128::new(amount * 3 / 4));
+        assert_eq!(final_asset.asset1.amount, Uint128::new(amount / 4));
+
+        let (swap, ask_asset, final_asset) = tokens_to_swap(
+            deps.as_ref(),
+            coins(amount, TOKEN0),
+            coin(amount, TOKEN0),
+            coin(3 * amount, TOKEN1),
+            Decimal::percent(50),
+        )
+        .unwrap();
+        // y0 = x0*a1 / (a1 + a0/p)
+        assert_eq!(swap.amount, Uint128::new(amount * 3 / 5));
+        assert_eq!(swap.name, AssetEntry::new(TOKEN0));
+        assert_eq!(ask_asset, AssetEntry::new(TOKEN1));
+        assert_eq!(final_asset.asset0.amount, Uint128::new(amount * 2 / 5));
+        assert_eq!(final_asset.asset1.amount, Uint128::new(amount * 6 / 5));
+    }
+
     #[test]
     fn swap_for_ratio_close_to_one() {
         let mut deps = mock_dependencies();
@@ -1,10 +1,23 @@
-use abstract_app::sdk::Resolve;
+use abstract_app::sdk::{AuthZInterface, Resolve};
 use abstract_app::{objects::AssetEntry, traits::AbstractNameService};
-use cosmwasm_std::{Addr, Deps, MessageInfo, Uint128};
+use cw_asset::Asset;
+use std::str::FromStr;
+
+use cosmwasm_std::{
+    ensure, Addr, Coin, CosmosMsg, Decimal, Decimal256, Deps, Env, MessageInfo, QuerierWrapper,
+    Uint128,
+};
+use osmosis_std::types::osmosis::{
+    concentratedliquidity::v1beta1::Pool, poolmanager::v1beta1::PoolmanagerQuerier,
+    twap::v1beta1::TwapQuerier,
+};
 
 use crate::{
+    cl_math::{align_tick_down, align_tick_up, parse_osmosis_decimal, price_to_tick},
     contract::{App, AppResult},
     error::AppError,
+    msg::PositionRange,
+    state::TwapConfig,
 };
 
 pub fn get_user(deps: Deps, app: &App) -> AppResult<Addr> {
@@ -22,6 +35,59 @@ pub fn get_balance(a: AssetEntry, deps: Deps, address: Addr, app: &App) -> AppRe
     Ok(user_gas_balance)
 }
 
+/// Forwards funds of the user to the `recipient`.
+/// Funds go through the contract, the `MsgSend` authorization of the user is limited to the contract address
+pub fn forward_to_recipient(
+    deps: Deps,
+    env: &Env,
+    app: &App,
+    funds: Vec<Asset>,
+    recipient: &Addr,
+) -> AppResult<Vec<CosmosMsg>> {
+    let user = get_user(deps, app)?;
+    let authz = app.auth_z(deps, Some(user))?;
+
+    let mut forward_msgs = vec![];
+    for asset in funds.into_iter().filter(|asset| !asset.amount.is_zero()) {
+        let send_to_contract_msg = asset.transfer_msg(env.contract.address.to_string())?;
+        forward_msgs.push(authz.execute(&env.contract.address, send_to_contract_msg));
+        forward_msgs.push(asset.transfer_msg(recipient)?);
+    }
+    Ok(forward_msgs)
+}
+
+/// Value of pool asset funds in `quote_denom`, one of the pool tokens, at the current price of the pool.
+/// Funds that are not pool assets are ignored
+pub fn pool_value(pool: &Pool, funds: &[Coin], quote_denom: &str) -> AppResult<Uint128> {
+    let sqrt_price = parse_osmosis_decimal(&pool.current_sqrt_price)?;
+    // Price of token0 in token1
+    let price = sqrt_price * sqrt_price;
+
+    // Value in token1 first
+    let mut value = Decimal256::zero();
+    for coin in funds {
+        let amount = Decimal256::from_ratio(coin.amount, 1u128);
+        if coin.denom == pool.token0 {
+            value += amount * price;
+        } else if coin.denom == pool.token1 {
+            value += amount;
+        }
+    }
+    if quote_denom == pool.token0 {
+        value /= price;
+    }
+    Ok(Uint128::try_from(value.to_uint_floor())?)
+}
+
+/// Funds in the pool assets, other denoms are left out
+pub fn pool_funds(pool: &Pool, funds: &[Coin]) -> Vec<Coin> {
+    funds
+        .iter()
+        .filter(|coin| coin.denom == pool.token0 || coin.denom == pool.token1)
+        .cloned()
+        .collect()
+}
+
 /// Copy of [`cw_utils::nonpayable`] but with custom error type
 pub fn nonpayable(info: &MessageInfo) -> AppResult<()> {
     if info.funds.is_empty() {
@@ -30,3 +96,123 @@ pub fn nonpayable(info: &MessageInfo) -> AppResult<()> {
         Err(AppError::RedundantFunds {})
     }
 }
+
+/// Query the concentrated liquidity pool by its id
+pub fn query_pool(querier: &QuerierWrapper, pool_id: u64) -> AppResult<Pool> {
+    let pool: Pool = PoolmanagerQuerier::new(querier)
+        .pool(pool_id)?
+        .pool
+        .unwrap()
+        .try_into()?;
+    Ok(pool)
+}
+
+/// Query the arithmetic TWAP of the pool over the last `window_seconds`,
+/// expressed as the price of token1 in token0
+pub fn query_twap(
+    querier: &QuerierWrapper,
+    env: &Env,
+    pool_id: u64,
+    token0: String,
+    token1: String,
+    window_seconds: u64,
+) -> AppResult<Decimal> {
+    let start_time = env.block.time.minus_seconds(window_seconds);
+    let response = TwapQuerier::new(querier).arithmetic_twap_to_now(
+        pool_id,
+        token1,
+        token0,
+        Some(osmosis_std::shim::Timestamp {
+            seconds: start_time.seconds() as i64,
+            nanos: start_time.subsec_nanos() as i32,
+        }),
+    )?;
+    Ok(Decimal::from_str(&response.arithmetic_twap)?)
+}
+
+/// Query the spot price of the pool, expressed the same way as [`query_twap`]
+pub fn query_spot_price(
+    querier: &QuerierWrapper,
+    pool_id: u64,
+    token0: String,
+    token1: String,
+) -> AppResult<Decimal> {
+    let response = PoolmanagerQuerier::new(querier).spot_price(pool_id, token1, token0)?;
+    Ok(Decimal::from_str(&response.spot_price)?)
+}
+
+/// Rejects the `price` of the pool, expressed the same way as [`query_twap`],
+/// when it deviates from the TWAP of the pool by more than the configured bound
+pub fn assert_twap(
+    querier: &QuerierWrapper,
+    env: &Env,
+    twap_config: &TwapConfig,
+    pool_id: u64,
+    token0: String,
+    token1: String,
+    price: Decimal,
+) -> AppResult<()> {
+    let twap = query_twap(
+        querier,
+        env,
+        pool_id,
+        token0,
+        token1,
+        twap_config.window_seconds.u64(),
+    )?;
+    ensure!(
+        price.abs_diff(twap) <= twap * twap_config.max_deviation,
+        AppError::TwapDeviation { price, twap }
+    );
+    Ok(())
+}
+
+/// Converts the range into ticks aligned to the tick spacing of the pool
+pub fn range_to_ticks(pool: &Pool, range: &PositionRange) -> AppResult<(i64, i64)> {
+    let (lower_tick, upper_tick) = match range {
+        PositionRange::Ticks {
+            lower_tick,
+            upper_tick,
+        } => (*lower_tick, *upper_tick),
+        PositionRange::Prices {
+            lower_price,
+            upper_price,
+        } => price_range_to_ticks(*lower_price, *upper_price, pool.tick_spacing)?,
+        PositionRange::AroundSpot { percentage } => {
+            ensure!(
+                !percentage.is_zero() && *percentage < Decimal::one(),
+                AppError::InvalidSpotPercentage {
+                    percentage: *percentage
+                }
+            );
+            let sqrt_price = parse_osmosis_decimal(&pool.current_sqrt_price)?;
+            let spot_price = sqrt_price * sqrt_price;
+            let percentage = Decimal256::from(*percentage);
+            price_range_to_ticks(
+                spot_price * (Decimal256::one() - percentage),
+                spot_price * (Decimal256::one() + percentage),
+                pool.tick_spacing,
+            )?
+        }
+    };
+    ensure!(
+        lower_tick < upper_tick,
+        AppError::InvalidTickRange {
+            lower_tick,
+            upper_tick
+        }
+    );
+    Ok((lower_tick, upper_tick))
+}
+
+// Ticks get rounded outwards so the range is never narrower than requested
+fn price_range_to_ticks(
+    lower_price: Decimal256,
+    upper_price: Decimal256,
+    tick_spacing: u64,
+) -> AppResult<(i64, i64)> {
+    Ok((
+        align_tick_down(price_to_tick(lower_price)?, tick_spacing),
+        align_tick_up(price_to_tick(upper_price)?, tick_spacing),
+    ))
+}
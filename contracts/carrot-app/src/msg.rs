@@ -1,9 +1,15 @@
 use abstract_app::objects::AssetEntry;
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Coin, Decimal, Uint256, Uint64};
+use cosmwasm_std::{Coin, Decimal, Decimal256, Int256, Uint128, Uint256, Uint64};
 use cw_asset::AssetBase;
 
-use crate::{contract::App, state::AutocompoundRewardsConfig};
+use crate::{
+    contract::App,
+    state::{
+        AutocompoundMode, AutocompoundRewardsConfig, CompoundEvent, PayoutConfig, PerformanceFee,
+        TwapConfig,
+    },
+};
 
 // This is used for type safety and re-exporting the contract endpoint structs.
 abstract_app::app_msg_types!(App, AppExecuteMsg, AppQueryMsg);
@@ -17,6 +23,27 @@ pub struct AppInstantiateMsg {
     pub autocompound_cooldown_seconds: Uint64,
     /// Configuration of rewards to the address who helped to execute autocompound
     pub autocompound_rewards_config: AutocompoundRewardsConfig,
+    /// Seconds the position has to be out of range before anyone can rebalance it.
+    /// Only the admin can rebalance when omitted, has to be bigger than 0
+    pub rebalance_grace_period_seconds: Option<Uint64>,
+    /// Swaps get rejected when the pool price deviates from its TWAP.
+    /// Disabled when omitted
+    pub twap_config: Option<TwapConfig>,
+    /// Incentive tokens that get swapped into the pool assets on autocompound.
+    /// Incentive tokens that are not pool assets are kept in the wallet when omitted
+    pub incentives_swap_allowlist: Option<Vec<AssetEntry>>,
+    /// What the autocompound does with the rewards, compounds them when omitted
+    pub autocompound_mode: Option<AutocompoundMode>,
+    /// Share of the rewards that the autocompound deposits back into the positions,
+    /// everything gets compounded when omitted
+    pub compound_ratio: Option<Decimal>,
+    /// Destination of the rewards that are not compounded, required when `compound_ratio` is below 1
+    pub payout: Option<PayoutConfig>,
+    /// Fee taken from the rewards on autocompound, no fee when omitted
+    pub performance_fee: Option<PerformanceFee>,
+    /// Max spread of the swaps of the autocompound, the payout max spread takes precedence for the payout swaps.
+    /// Defaults to 5%
+    pub autocompound_max_spread: Option<Decimal>,
     /// Create position with instantiation.
     /// Will not create position if omitted
     pub create_position: Option<CreatePositionMessage>,
@@ -24,17 +51,57 @@ pub struct AppInstantiateMsg {
 
 #[cosmwasm_schema::cw_serde]
 pub struct CreatePositionMessage {
-    pub lower_tick: i64,
-    pub upper_tick: i64,
+    /// Name of the position, defaults to "default"
+    pub name: Option<String>,
+    /// Share of the deposits going into this position relative to the other positions, defaults to 1
+    pub weight: Option<u64>,
+    /// Price range of the position
+    pub range: PositionRange,
     // Funds to use to deposit on the account
     pub funds: Vec<Coin>,
-    /// The two next fields indicate the token0/token1 ratio we want to deposit inside the current ticks
-    pub asset0: Coin,
-    pub asset1: Coin,
     // Slippage
     pub max_spread: Option<Decimal>,
     pub belief_price0: Option<Decimal>,
     pub belief_price1: Option<Decimal>,
+    /// Minimum amounts of token0 and token1 that have to land in the position,
+    /// derived from `max_spread` when omitted
+    pub token_min_amount0: Option<Uint128>,
+    pub token_min_amount1: Option<Uint128>,
+}
+
+/// Update of an optional config value, left unchanged when the update is omitted
+#[cosmwasm_schema::cw_serde]
+pub enum OptionalUpdate<T> {
+    /// Sets the value
+    Set(T),
+    /// Removes the value
+    Clear {},
+}
+
+impl<T> OptionalUpdate<T> {
+    pub fn into_option(self) -> Option<T> {
+        match self {
+            Self::Set(value) => Some(value),
+            Self::Clear {} => None,
+        }
+    }
+}
+
+/// Price range of a position.
+/// Prices are expressed as the price of asset0 in asset1, the same way as on the pool.
+/// Prices get converted to ticks aligned to the tick spacing of the pool,
+/// rounding outwards so the range is never narrower than requested
+#[cosmwasm_schema::cw_serde]
+pub enum PositionRange {
+    /// Raw ticks of the pool
+    Ticks { lower_tick: i64, upper_tick: i64 },
+    /// Absolute min and max prices
+    Prices {
+        lower_price: Decimal256,
+        upper_price: Decimal256,
+    },
+    /// Range of ± `percentage` around the current spot price, 0.05 is ±5%
+    AroundSpot { percentage: Decimal },
 }
 
 /// App execute messages
@@ -45,25 +112,96 @@ pub enum AppExecuteMsg {
     UpdateConfig {
         autocompound_cooldown_seconds: Option<Uint64>,
         autocompound_rewards_config: Option<AutocompoundRewardsConfig>,
+        /// Replaces or clears the grace period of the permissionless rebalance
+        rebalance_grace_period_seconds: Option<OptionalUpdate<Uint64>>,
+        /// Replaces or clears the TWAP guard of the swaps
+        twap_config: Option<OptionalUpdate<TwapConfig>>,
+        /// Replaces the incentive tokens that get swapped into the pool assets on autocompound
+        incentives_swap_allowlist: Option<Vec<AssetEntry>>,
+        /// Switches the autocompound between compounding and harvesting the rewards
+        autocompound_mode: Option<AutocompoundMode>,
+        /// Replaces the share of the rewards that the autocompound deposits back into the positions
+        compound_ratio: Option<Decimal>,
+        /// Replaces or clears the destination of the rewards that are not compounded
+        payout: Option<OptionalUpdate<PayoutConfig>>,
+        /// Replaces or clears the fee taken from the rewards on autocompound
+        performance_fee: Option<OptionalUpdate<PerformanceFee>>,
+        /// Replaces the max spread of the swaps of the autocompound
+        autocompound_max_spread: Option<Decimal>,
     },
-    /// Create the initial liquidity position
+    /// Create a liquidity position, several positions with different names can be open at once
     CreatePosition(CreatePositionMessage),
-    /// Deposit funds onto the app
+    /// Deposit funds onto the app, funds are split between positions according to their weights.
+    /// Funds in other assets than the pool assets get swapped into the pool assets through the ANS routes
     Deposit {
         funds: Vec<Coin>,
         max_spread: Option<Decimal>,
         belief_price0: Option<Decimal>,
         belief_price1: Option<Decimal>,
+        /// Minimum amounts of token0 and token1 that have to land in the positions,
+        /// derived from `max_spread` when omitted
+        token_min_amount0: Option<Uint128>,
+        token_min_amount1: Option<Uint128>,
     },
     /// Withdraw liquidity available on the position
     Withdraw {
-        /// Liquidity amount, withdraws everything when omitted
-        amount: Option<Uint256>,
+        /// Amount to withdraw, withdraws everything when omitted
+        amount: Option<WithdrawAmount>,
         /// When provided, will swap withdrawn liquidity to single asset
         swap_to: Option<SwapToAsset>,
+        /// Name of the position to withdraw from, withdraws from all positions when omitted.
+        /// Liquidity `amount` requires the position name when there are several positions
+        position: Option<String>,
+        /// Address receiving the withdrawn funds, the account owner when omitted
+        recipient: Option<String>,
     },
-    /// Auto-compounds the pool rewards into the pool
+    /// Auto-compounds the pool rewards of all positions, rewards are split according to position weights.
+    /// Harvests the rewards instead when the config is in harvest mode
     Autocompound {},
+    /// Collects the rewards of all positions into the wallet without compounding them
+    Harvest {
+        /// When provided, swaps the rewards into a single asset,
+        /// `max_spread` of the swaps defaults to the autocompound max spread
+        swap_to: Option<SwapToAsset>,
+    },
+    /// Withdraws the position once it went out of range and
+    /// creates a new one of the same width, centered on the current price.
+    /// Anyone can rebalance once the position is out of range for the grace period,
+    /// the first call of a non-admin starts the grace period.
+    /// The grace period starts over when the TWAP over it is not out of range on the side of the price
+    Rebalance {
+        /// Name of the position to rebalance, defaults to "default"
+        position: Option<String>,
+        max_spread: Option<Decimal>,
+        belief_price0: Option<Decimal>,
+        belief_price1: Option<Decimal>,
+    },
+    /// Forgets the positions that don't exist on osmosis anymore, after they got withdrawn outside of the app.
+    /// Their names and weights get reused for the positions of the user in the pool that the app doesn't track yet
+    Reconcile {},
+    /// Starts tracking a position of the user in the pool, created outside of the app.
+    /// The position gets saved under the default name
+    ImportPosition { position_id: u64 },
+    /// Moves the app to another pool.
+    /// All the positions get withdrawn and their funds swapped into a single position in the new pool
+    MigratePool {
+        new_pool_id: u64,
+        range: PositionRange,
+        /// Max spread of the swaps into the new pool assets and of the new position
+        max_spread: Option<Decimal>,
+        /// Minimum amounts of token0 and token1 of the new pool that have to land in the new position,
+        /// derived from `max_spread` when omitted
+        token_min_amount0: Option<Uint128>,
+        token_min_amount1: Option<Uint128>,
+    },
+    /// Rejects deposits, autocompounds, position creations and imports, rebalances and pool migrations until unpaused
+    Pause {},
+    /// Accepts all of the paused operations again
+    Unpause {},
+    /// Withdraws all the positions into the wallet of the user, without any swap.
+    /// Osmosis claims the spread rewards of the fully withdrawn positions along with the liquidity.
+    /// Only depends on the concentrated liquidity module, to get the funds out when ANS or the dex adapter are not available
+    EmergencyExit {},
 }
 
 /// App query messages
@@ -74,16 +212,81 @@ pub enum AppQueryMsg {
     /// Get the config of the carrot app
     #[returns(crate::state::Config)]
     Config {},
-    /// Get the balance and liquidity of the position
+    /// Get the balance and liquidity of the positions
     #[returns(AssetsBalanceResponse)]
     Balance {},
-    /// Get the position id
+    /// Get the ids and weights of the positions
     #[returns(PositionResponse)]
     Position {},
+    /// Get the ticks, prices, liquidity and claimable rewards of every position
+    #[returns(PositionDetailsResponse)]
+    PositionDetails {},
     /// Get the status of the compounding logic of the application and pool rewards
     /// Returns [`CompoundStatusResponse`]
     #[returns(CompoundStatusResponse)]
     CompoundStatus {},
+    /// Get the status of the permissionless rebalance of every position
+    /// Returns [`RebalanceStatusResponse`]
+    #[returns(RebalanceStatusResponse)]
+    RebalanceStatus {},
+    /// Preview of a deposit: the swaps, the funds landing in every position and their liquidity
+    #[returns(SimulateDepositResponse)]
+    SimulateDeposit {
+        funds: Vec<Coin>,
+        max_spread: Option<Decimal>,
+        belief_price0: Option<Decimal>,
+        belief_price1: Option<Decimal>,
+    },
+    /// Preview of a withdraw: the funds withdrawn from every position
+    #[returns(SimulateWithdrawResponse)]
+    SimulateWithdraw {
+        amount: Option<WithdrawAmount>,
+        position: Option<String>,
+    },
+    /// Preview of a position creation: the ticks, the swap, the funds landing in the position and its liquidity
+    #[returns(SimulateCreatePositionResponse)]
+    SimulateCreatePosition {
+        create_position_msg: CreatePositionMessage,
+    },
+    /// Get the deposited principal, the compounded rewards and the net yield
+    #[returns(PerformanceResponse)]
+    Performance {},
+    /// Compare the value of the positions with holding the deposited principal,
+    /// both valued in `quote_asset` at the current price of the pool
+    #[returns(HodlComparisonResponse)]
+    HodlComparison {
+        /// One of the pool assets, the second one when omitted
+        quote_asset: Option<AssetEntry>,
+    },
+    /// Get the last autocompounds, oldest first.
+    /// Only the last [`crate::state::COMPOUND_HISTORY_SIZE`] autocompounds are kept
+    #[returns(CompoundHistoryResponse)]
+    CompoundHistory {
+        /// Id of the autocompound to start after
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+}
+
+/// Basis points of a whole
+pub const MAX_BPS: u16 = 10_000;
+
+/// Amount to withdraw from the positions
+#[cw_serde]
+pub enum WithdrawAmount {
+    /// Raw liquidity amount of the position, as returned by the balance query
+    Liquidity(Uint256),
+    /// Share of the liquidity of every position in basis points, 10_000 withdraws everything
+    Percentage { bps: u16 },
+    /// Value to withdraw, expressed in one of the pool assets at the current price of the pool
+    Value { asset: AssetEntry, amount: Uint128 },
+}
+
+impl WithdrawAmount {
+    /// Liquidity and value are specific to a position, a percentage applies to all of them
+    pub fn is_position_specific(&self) -> bool {
+        !matches!(self, Self::Percentage { .. })
+    }
 }
 
 #[cosmwasm_schema::cw_serde]
@@ -96,13 +299,151 @@ pub struct BalanceResponse {
 
 #[cw_serde]
 pub struct AssetsBalanceResponse {
+    /// Sum of the balances of all positions
+    pub balances: Vec<Coin>,
+    /// Sum of the liquidity of all positions
+    pub liquidity: String,
+    pub positions: Vec<PositionBalance>,
+}
+
+#[cw_serde]
+pub struct PerformanceResponse {
+    /// Deposited funds still in the positions, withdraws reduce it by their share of the value of the positions
+    pub principal: Vec<Coin>,
+    /// Rewards compounded into the positions since the instantiation
+    pub compounded: Vec<Coin>,
+    /// Sum of the balances of all positions
+    pub balances: Vec<Coin>,
+    /// Value of the principal in the second pool asset, at the current price of the pool
+    pub principal_value: Uint128,
+    /// Value of the balances in the second pool asset, at the current price of the pool
+    pub balance_value: Uint128,
+    /// Balance value minus principal value, negative when the positions lost value
+    pub net_yield: Int256,
+}
+
+#[cw_serde]
+pub struct HodlComparisonResponse {
+    pub quote_asset: AssetEntry,
+    /// Value of the principal if it had been held instead
+    pub hodl_value: Uint128,
+    /// Value of the balances of all positions, compounded rewards included
+    pub position_value: Uint128,
+    /// Value of the compounded rewards and of the rewards waiting to be claimed.
+    /// Rewards that are not pool assets are not valued
+    pub fees_earned: Uint128,
+    /// Position value without the compounded rewards minus the hodl value,
+    /// what the price movements cost compared to holding
+    pub impermanent_loss: Int256,
+    /// Position value with the unclaimed rewards minus the hodl value,
+    /// positive when providing liquidity beats holding
+    pub lp_vs_hodl: Int256,
+}
+
+#[cw_serde]
+pub struct CompoundHistoryResponse {
+    pub events: Vec<CompoundEvent>,
+}
+
+#[cw_serde]
+pub struct PositionBalance {
+    pub name: String,
     pub balances: Vec<Coin>,
     pub liquidity: String,
 }
 
 #[cw_serde]
 pub struct PositionResponse {
-    pub position_id: Option<u64>,
+    pub positions: Vec<PositionInfo>,
+}
+
+#[cw_serde]
+pub struct PositionInfo {
+    pub name: String,
+    pub position_id: u64,
+    pub weight: u64,
+}
+
+#[cw_serde]
+pub struct PositionDetailsResponse {
+    /// Current tick of the pool
+    pub current_tick: i64,
+    /// Current price of the pool, price of the first pool asset in the second one
+    pub current_price: Decimal256,
+    /// Seconds since the last compound, `None` before the first position got created
+    pub seconds_since_last_compound: Option<u64>,
+    pub positions: Vec<PositionDetails>,
+}
+
+#[cw_serde]
+pub struct PositionDetails {
+    pub name: String,
+    pub position_id: u64,
+    pub weight: u64,
+    pub lower_tick: i64,
+    pub upper_tick: i64,
+    /// Price at the lower tick
+    pub lower_price: Decimal256,
+    /// Price at the upper tick
+    pub upper_price: Decimal256,
+    /// Whether the current tick of the pool is inside the range of the position
+    pub in_range: bool,
+    pub liquidity: Decimal256,
+    pub balances: Vec<Coin>,
+    pub claimable_spread_rewards: Vec<Coin>,
+    pub claimable_incentives: Vec<Coin>,
+}
+
+/// Swap planned by a simulation
+#[cw_serde]
+pub struct SwapSimulation {
+    pub offer_asset: AssetEntry,
+    pub ask_asset: AssetEntry,
+    /// Amount of `offer_asset` to swap
+    pub amount: Uint128,
+}
+
+/// Provision to a position planned by a simulation, prices of the swaps are not taken into account
+#[cw_serde]
+pub struct PositionSimulation {
+    pub name: String,
+    /// Swap to the token ratio of the position, `None` when nothing has to be swapped
+    pub swap: Option<SwapSimulation>,
+    /// Funds provided to the position after the swap
+    pub assets_for_position: Vec<Coin>,
+    /// Liquidity the position is expected to get
+    pub liquidity: String,
+    /// Funds the position can't take at the current price, they stay in the wallet
+    pub dust: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct SimulateDepositResponse {
+    /// Swaps of the funds that are not pool assets into the pool assets
+    pub zap_swaps: Vec<SwapSimulation>,
+    pub positions: Vec<PositionSimulation>,
+}
+
+#[cw_serde]
+pub struct SimulateCreatePositionResponse {
+    pub lower_tick: i64,
+    pub upper_tick: i64,
+    pub position: PositionSimulation,
+}
+
+#[cw_serde]
+pub struct SimulateWithdrawResponse {
+    /// Sum of the funds withdrawn from all positions
+    pub withdrawn: Vec<Coin>,
+    pub positions: Vec<WithdrawSimulation>,
+}
+
+#[cw_serde]
+pub struct WithdrawSimulation {
+    pub name: String,
+    pub liquidity_amount: Uint256,
+    pub total_liquidity: Uint256,
+    pub withdrawn: Vec<Coin>,
 }
 
 #[cw_serde]
@@ -134,8 +475,44 @@ impl CompoundStatus {
     }
 }
 
+#[cw_serde]
+pub struct RebalanceStatusResponse {
+    pub positions: Vec<PositionRebalanceStatus>,
+    /// Wether anyone is allowed to rebalance the positions
+    pub permissionless: bool,
+}
+
+#[cw_serde]
+pub struct PositionRebalanceStatus {
+    pub name: String,
+    pub status: RebalanceStatus,
+}
+
+#[cw_serde]
+/// Wether position is ready for the rebalance
+pub enum RebalanceStatus {
+    /// Position is in range, nothing to rebalance
+    InRange {},
+    /// Position is out of range, grace period did not start yet
+    OutOfRange {},
+    /// How much seconds left before anyone can rebalance
+    GracePeriod(Uint64),
+    /// Anyone can rebalance the position
+    Ready {},
+}
+
+impl RebalanceStatus {
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Self::Ready {})
+    }
+}
+
 #[cw_serde]
 pub struct SwapToAsset {
     pub to_asset: AssetEntry,
     pub max_spread: Option<Decimal>,
+    /// Minimum total amount of `to_asset` the withdraw has to return, across all of its swaps
+    pub min_receive: Option<Uint128>,
+    /// Address receiving the swapped funds, takes precedence over the `recipient` of the withdraw
+    pub recipient: Option<String>,
 }
@@ -5,14 +5,19 @@ use osmosis_std::types::osmosis::concentratedliquidity::v1beta1::MsgAddToPositio
 use crate::{
     contract::{App, AppResult},
     error::AppError,
-    state::CarrotPosition,
+    state::{pop_pending_position, CarrotPosition},
 };
 
 pub fn add_to_position_reply(deps: DepsMut, env: Env, app: App, reply: Reply) -> AppResult {
-    let SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) = reply.result else {
-        return Err(AppError::Std(StdError::generic_err(
-            "Failed to create position",
-        )));
+    let pending = pop_pending_position(deps.storage)?;
+    let b = match reply.result {
+        SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) => b,
+        SubMsgResult::Ok(_) => {
+            return Err(AppError::Std(StdError::generic_err(
+                "Failed to create position",
+            )))
+        }
+        SubMsgResult::Err(reason) => return Err(pending.provision_error(reason)),
     };
 
     // Parse the msg exec response from the reply
@@ -22,9 +27,16 @@ pub fn add_to_position_reply(deps: DepsMut, env: Env, app: App, reply: Reply) ->
     let response: MsgAddToPositionResponse = parsed.data.unwrap_or_default().try_into()?;
 
     // We update the position
-    CarrotPosition::save_position(deps.storage, &env.block.time, response.position_id)?;
+    CarrotPosition::save_position(
+        deps,
+        &env.block.time,
+        &pending.name,
+        response.position_id,
+        pending.weight,
+    )?;
 
     Ok(app
         .response("create_position_reply")
+        .add_attribute("position", pending.name)
         .add_attribute("updated_position_id", response.position_id.to_string()))
 }
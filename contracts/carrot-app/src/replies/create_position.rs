@@ -5,14 +5,19 @@ use osmosis_std::types::osmosis::concentratedliquidity::v1beta1::MsgCreatePositi
 use crate::{
     contract::{App, AppResult},
     error::AppError,
-    state::CarrotPosition,
+    state::{pop_pending_position, CarrotPosition},
 };
 
 pub fn create_position_reply(deps: DepsMut, env: Env, app: App, reply: Reply) -> AppResult {
-    let SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) = reply.result else {
-        return Err(AppError::Std(StdError::generic_err(
-            "Failed to create position",
-        )));
+    let pending = pop_pending_position(deps.storage)?;
+    let b = match reply.result {
+        SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) => b,
+        SubMsgResult::Ok(_) => {
+            return Err(AppError::Std(StdError::generic_err(
+                "Failed to create position",
+            )))
+        }
+        SubMsgResult::Err(reason) => return Err(pending.provision_error(reason)),
     };
 
     let parsed = cw_utils::parse_execute_response_data(&b)?;
@@ -21,9 +26,16 @@ pub fn create_position_reply(deps: DepsMut, env: Env, app: App, reply: Reply) ->
     let response: MsgCreatePositionResponse = parsed.data.clone().unwrap_or_default().try_into()?;
 
     // We save the position
-    CarrotPosition::save_position(deps.storage, &env.block.time, response.position_id)?;
+    CarrotPosition::save_position(
+        deps,
+        &env.block.time,
+        &pending.name,
+        response.position_id,
+        pending.weight,
+    )?;
 
     Ok(app
         .response("create_position_reply")
+        .add_attribute("position", pending.name)
         .add_attribute("initial_position_id", response.position_id.to_string()))
 }
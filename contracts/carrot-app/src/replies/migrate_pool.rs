@@ -0,0 +1,96 @@
+use abstract_app::sdk::AbstractResponse;
+use cosmwasm_std::{Coin, Decimal, DepsMut, Env, Reply};
+
+use crate::{
+    contract::{App, AppResult},
+    handlers::{execute::_create_position, swap_helpers::zap_msgs},
+    helpers::get_user,
+    msg::CreatePositionMessage,
+    state::{
+        add_principal, reduce_principal, CarrotPosition, MigratePoolParams, MigratedPosition,
+        ZapTarget, CONFIG, TEMP_MIGRATE_POOL,
+    },
+};
+
+pub fn migrate_pool_reply(mut deps: DepsMut, env: Env, app: App, _reply: Reply) -> AppResult {
+    let MigratePoolParams {
+        pool_config,
+        position,
+        balances_before,
+    } = TEMP_MIGRATE_POOL.load(deps.storage)?;
+    TEMP_MIGRATE_POOL.remove(deps.storage);
+
+    // Everything withdrawn from the positions goes into the new pool
+    let user = get_user(deps.as_ref(), &app)?;
+    let mut funds = vec![];
+    for before in balances_before {
+        let balance = deps.querier.query_balance(&user, before.denom)?;
+        funds.push(Coin {
+            amount: balance.amount - before.amount,
+            denom: balance.denom,
+        });
+    }
+
+    // Fully withdrawn positions don't exist on osmosis anymore
+    for (name, _, _) in CarrotPosition::load_stale(deps.as_ref())? {
+        CarrotPosition::remove(deps.storage, &name);
+    }
+    let mut config = CONFIG.load(deps.storage)?;
+    config.pool_config = pool_config;
+    CONFIG.save(deps.storage, &config)?;
+
+    // Assets of the previous pool get swapped into the new pool assets first,
+    // the position gets created with the received amounts in the reply of the swaps
+    let zap_msgs = zap_msgs(
+        deps.branch(),
+        &env,
+        &app,
+        &config,
+        funds.clone(),
+        position.max_spread,
+        ZapTarget::MigratedPosition {
+            position: position.clone(),
+        },
+    )?;
+    if !zap_msgs.is_empty() {
+        return Ok(app.response("migrate_pool_reply").add_submessages(zap_msgs));
+    }
+
+    create_migrated_position(deps, &env, &app, funds, position)
+}
+
+/// Creates the position in the new pool with the funds of the previous positions,
+/// in the assets of the new pool
+pub(crate) fn create_migrated_position(
+    deps: DepsMut,
+    env: &Env,
+    app: &App,
+    funds: Vec<Coin>,
+    position: MigratedPosition,
+) -> AppResult {
+    // Principal is carried over in the assets of the new pool
+    reduce_principal(deps.storage, Decimal::one())?;
+    add_principal(deps.storage, &funds)?;
+
+    let (swap_msgs, create_msg) = _create_position(
+        deps,
+        env,
+        app,
+        CreatePositionMessage {
+            name: None,
+            weight: None,
+            range: position.range,
+            funds,
+            max_spread: position.max_spread,
+            belief_price0: None,
+            belief_price1: None,
+            token_min_amount0: position.token_min_amount0,
+            token_min_amount1: position.token_min_amount1,
+        },
+    )?;
+
+    Ok(app
+        .response("migrate_pool_reply")
+        .add_messages(swap_msgs)
+        .add_submessage(create_msg))
+}
@@ -1,11 +1,24 @@
 mod add_to_position;
 mod create_position;
+mod migrate_pool;
+mod rebalance;
 mod withdraw_to_asset;
+mod withdraw_to_recipient;
+mod zap;
 
 pub const CREATE_POSITION_ID: u64 = 1;
 pub const ADD_TO_POSITION_ID: u64 = 2;
 pub const WITHDRAW_TO_ASSET_ID: u64 = 3;
+pub const REBALANCE_ID: u64 = 4;
+pub const WITHDRAW_SWAPS_ID: u64 = 5;
+pub const WITHDRAW_TO_RECIPIENT_ID: u64 = 6;
+pub const MIGRATE_POOL_ID: u64 = 7;
+pub const ZAP_ID: u64 = 8;
 
 pub use add_to_position::add_to_position_reply;
 pub use create_position::create_position_reply;
-pub use withdraw_to_asset::withdraw_to_asset_reply;
+pub use migrate_pool::migrate_pool_reply;
+pub use rebalance::rebalance_reply;
+pub use withdraw_to_asset::{withdraw_swaps_reply, withdraw_to_asset_reply};
+pub use withdraw_to_recipient::withdraw_to_recipient_reply;
+pub use zap::zap_reply;
@@ -0,0 +1,67 @@
+use abstract_app::sdk::AbstractResponse;
+use cosmwasm_std::{Coin, DepsMut, Env, Reply, StdError, SubMsgResponse, SubMsgResult};
+use osmosis_std::types::osmosis::concentratedliquidity::v1beta1::MsgWithdrawPositionResponse;
+
+use crate::{
+    contract::{App, AppResult},
+    error::AppError,
+    handlers::execute::_create_position,
+    helpers::query_pool,
+    msg::{CreatePositionMessage, PositionRange},
+    state::{CONFIG, TEMP_REBALANCE},
+};
+
+pub fn rebalance_reply(deps: DepsMut, env: Env, app: App, reply: Reply) -> AppResult {
+    let SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) = reply.result else {
+        return Err(AppError::Std(StdError::generic_err(
+            "Failed to rebalance position",
+        )));
+    };
+
+    // Parse the msg exec response from the reply
+    let parsed = cw_utils::parse_execute_response_data(&b)?;
+
+    // Parse the withdraw response from the message
+    let response: MsgWithdrawPositionResponse = parsed.data.unwrap_or_default().try_into()?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let payload = TEMP_REBALANCE.load(deps.storage)?;
+    TEMP_REBALANCE.remove(deps.storage);
+    let pool = query_pool(&deps.querier, config.pool_config.pool_id)?;
+
+    // Everything withdrawn goes into the new position
+    let funds = vec![
+        Coin {
+            denom: pool.token0.clone(),
+            amount: response.amount0.parse()?,
+        },
+        Coin {
+            denom: pool.token1.clone(),
+            amount: response.amount1.parse()?,
+        },
+    ];
+    let (swap_msgs, create_msg) = _create_position(
+        deps,
+        &env,
+        &app,
+        CreatePositionMessage {
+            name: Some(payload.name),
+            weight: Some(payload.weight),
+            range: PositionRange::Ticks {
+                lower_tick: payload.lower_tick,
+                upper_tick: payload.upper_tick,
+            },
+            funds,
+            max_spread: payload.max_spread,
+            belief_price0: payload.belief_price0,
+            belief_price1: payload.belief_price1,
+            token_min_amount0: None,
+            token_min_amount1: None,
+        },
+    )?;
+
+    Ok(app
+        .response("rebalance_reply")
+        .add_messages(swap_msgs)
+        .add_submessage(create_msg))
+}
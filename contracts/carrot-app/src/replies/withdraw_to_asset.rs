@@ -1,14 +1,24 @@
-use abstract_app::sdk::AbstractResponse;
-use cosmwasm_std::{DepsMut, Env, Reply, StdError, SubMsgResponse, SubMsgResult};
+use abstract_app::{
+    objects::AnsAsset,
+    sdk::AbstractResponse,
+    traits::{AbstractNameService, Resolve},
+};
+use cosmwasm_std::{
+    ensure, CosmosMsg, DepsMut, Env, Reply, StdError, SubMsg, SubMsgResponse, SubMsgResult, Uint128,
+};
+use cw_asset::Asset;
 use osmosis_std::types::osmosis::concentratedliquidity::v1beta1::MsgWithdrawPositionResponse;
 
 use crate::{
     contract::{App, AppResult},
     error::AppError,
-    handlers::swap_helpers::swap_msg,
-    state::{CONFIG, TEMP_WITHDRAW_TO_ASSET},
+    handlers::swap_helpers::{route_swap_msgs, route_to_asset},
+    helpers::{forward_to_recipient, get_balance, get_user},
+    state::{WithdrawToAsset, CONFIG, TEMP_WITHDRAW_TO_ASSET},
 };
 
+use super::WITHDRAW_SWAPS_ID;
+
 pub fn withdraw_to_asset_reply(deps: DepsMut, env: Env, app: App, reply: Reply) -> AppResult {
     let SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) = reply.result else {
         return Err(AppError::Std(StdError::generic_err(
@@ -23,36 +33,110 @@ pub fn withdraw_to_asset_reply(deps: DepsMut, env: Env, app: App, reply: Reply)
     let response: MsgWithdrawPositionResponse = parsed.data.unwrap_or_default().try_into()?;
 
     let config = CONFIG.load(deps.storage)?;
-    let payload = TEMP_WITHDRAW_TO_ASSET.load(deps.storage)?;
+    let mut payload = TEMP_WITHDRAW_TO_ASSET.load(deps.storage)?;
+    let to_asset = &payload.swap_to.to_asset;
+    let ans_host = app.ans_host(deps.as_ref())?;
 
+    let withdrawn = [
+        (
+            config.pool_config.asset0.clone(),
+            response.amount0.parse::<Uint128>()?,
+        ),
+        (
+            config.pool_config.asset1.clone(),
+            response.amount1.parse::<Uint128>()?,
+        ),
+    ];
     let mut swap_msgs = vec![];
-    if config.pool_config.asset0 != payload.to_asset {
-        swap_msgs.extend(swap_msg(
+    for (pool_asset, amount) in withdrawn {
+        if &pool_asset == to_asset || amount.is_zero() {
+            continue;
+        }
+        let route = route_to_asset(deps.as_ref(), &ans_host, &config, &pool_asset, to_asset)?;
+        let (msgs, _) = route_swap_msgs(
             deps.as_ref(),
             &env,
-            abstract_app::objects::AnsAsset {
-                name: config.pool_config.asset0,
-                amount: response.amount0.parse()?,
-            },
-            payload.to_asset.clone(),
-            payload.max_spread,
             &app,
-        )?);
+            AnsAsset::new(pool_asset, amount),
+            &route,
+            payload.swap_to.max_spread,
+        )?;
+        swap_msgs.extend(msgs);
     }
-    if config.pool_config.asset1 != payload.to_asset {
-        swap_msgs.extend(swap_msg(
-            deps.as_ref(),
-            &env,
-            abstract_app::objects::AnsAsset {
-                name: config.pool_config.asset1,
-                amount: response.amount1.parse()?,
-            },
-            payload.to_asset,
-            payload.max_spread,
-            &app,
-        )?);
+
+    let response = app.response("withdraw_to_asset_reply");
+    payload.pending_withdraws -= 1;
+    if payload.pending_withdraws > 0 {
+        TEMP_WITHDRAW_TO_ASSET.save(deps.storage, &payload)?;
+        return Ok(response.add_messages(swap_msgs));
+    }
+
+    // Last withdraw, the received amount can be settled once its swaps are done
+    if payload.swap_to.min_receive.is_none() && payload.recipient.is_none() {
+        TEMP_WITHDRAW_TO_ASSET.remove(deps.storage);
+        return Ok(response.add_messages(swap_msgs));
     }
+    match swap_msgs.pop() {
+        Some(last_swap_msg) => {
+            TEMP_WITHDRAW_TO_ASSET.save(deps.storage, &payload)?;
+            Ok(response
+                .add_messages(swap_msgs)
+                .add_submessage(SubMsg::reply_on_success(last_swap_msg, WITHDRAW_SWAPS_ID)))
+        }
+        None => {
+            let (received, forward_msgs) = settle_withdraw(deps, &env, &app, payload)?;
+            Ok(response
+                .add_attribute("received", received)
+                .add_messages(forward_msgs))
+        }
+    }
+}
+
+pub fn withdraw_swaps_reply(deps: DepsMut, env: Env, app: App, _reply: Reply) -> AppResult {
+    let payload = TEMP_WITHDRAW_TO_ASSET.load(deps.storage)?;
+    let (received, forward_msgs) = settle_withdraw(deps, &env, &app, payload)?;
+
     Ok(app
-        .response("withdraw_to_asset_reply")
-        .add_messages(swap_msgs))
+        .response("withdraw_swaps_reply")
+        .add_attribute("received", received)
+        .add_messages(forward_msgs))
+}
+
+/// Ensures the user received at least `min_receive` of `to_asset` since the start of the withdraw
+/// and forwards the received amount to the recipient
+fn settle_withdraw(
+    deps: DepsMut,
+    env: &Env,
+    app: &App,
+    payload: WithdrawToAsset,
+) -> AppResult<(Uint128, Vec<CosmosMsg>)> {
+    TEMP_WITHDRAW_TO_ASSET.remove(deps.storage);
+
+    let user = get_user(deps.as_ref(), app)?;
+    let to_asset = payload.swap_to.to_asset;
+    let balance_after = get_balance(to_asset.clone(), deps.as_ref(), user, app)?;
+    let received = balance_after.saturating_sub(payload.balance_before);
+    let min_receive = payload.swap_to.min_receive.unwrap_or_default();
+    ensure!(
+        received >= min_receive,
+        AppError::MinReceive {
+            received,
+            min_receive,
+        }
+    );
+
+    let forward_msgs = match payload.recipient {
+        Some(recipient) => {
+            let to_asset_info = to_asset.resolve(&deps.querier, &app.ans_host(deps.as_ref())?)?;
+            forward_to_recipient(
+                deps.as_ref(),
+                env,
+                app,
+                vec![Asset::new(to_asset_info, received)],
+                &recipient,
+            )?
+        }
+        None => vec![],
+    };
+    Ok((received, forward_msgs))
 }
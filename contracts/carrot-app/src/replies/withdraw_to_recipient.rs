@@ -0,0 +1,54 @@
+use abstract_app::{sdk::AbstractResponse, traits::AbstractNameService};
+use cosmwasm_std::{DepsMut, Env, Reply, StdError, SubMsgResponse, SubMsgResult, Uint128};
+use cw_asset::Asset;
+use osmosis_std::types::osmosis::concentratedliquidity::v1beta1::MsgWithdrawPositionResponse;
+
+use crate::{
+    contract::{App, AppResult},
+    error::AppError,
+    helpers::forward_to_recipient,
+    state::{CONFIG, TEMP_WITHDRAW_RECIPIENT},
+};
+
+pub fn withdraw_to_recipient_reply(deps: DepsMut, env: Env, app: App, reply: Reply) -> AppResult {
+    let SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) = reply.result else {
+        return Err(AppError::Std(StdError::generic_err(
+            "Failed to withdraw to recipient",
+        )));
+    };
+
+    // Parse the msg exec response from the reply
+    let parsed = cw_utils::parse_execute_response_data(&b)?;
+
+    // Parse the position response from the message
+    let response: MsgWithdrawPositionResponse = parsed.data.unwrap_or_default().try_into()?;
+
+    let config = CONFIG.load(deps.storage)?;
+    let mut payload = TEMP_WITHDRAW_RECIPIENT.load(deps.storage)?;
+    payload.pending_withdraws -= 1;
+    if payload.pending_withdraws > 0 {
+        TEMP_WITHDRAW_RECIPIENT.save(deps.storage, &payload)?;
+    } else {
+        TEMP_WITHDRAW_RECIPIENT.remove(deps.storage);
+    }
+    let recipient = payload.recipient;
+    let ans = app.name_service(deps.as_ref());
+
+    let withdrawn_funds = vec![
+        Asset::new(
+            ans.query(&config.pool_config.asset0)?,
+            response.amount0.parse::<Uint128>()?,
+        ),
+        Asset::new(
+            ans.query(&config.pool_config.asset1)?,
+            response.amount1.parse::<Uint128>()?,
+        ),
+    ];
+    let forward_msgs =
+        forward_to_recipient(deps.as_ref(), &env, &app, withdrawn_funds, &recipient)?;
+
+    Ok(app
+        .response("withdraw_to_recipient_reply")
+        .add_attribute("recipient", recipient)
+        .add_messages(forward_msgs))
+}
@@ -0,0 +1,69 @@
+use abstract_app::sdk::AbstractResponse;
+use cosmwasm_std::{to_json_binary, Coin, Coins, CosmosMsg, DepsMut, Env, Reply, WasmMsg};
+
+use crate::{
+    contract::{App, AppResult},
+    helpers::get_user,
+    msg::{AppExecuteMsg, ExecuteMsg},
+    state::{add_compounded, add_principal, PendingZap, ZapTarget, TEMP_ZAP},
+};
+
+use super::migrate_pool::create_migrated_position;
+
+pub fn zap_reply(deps: DepsMut, env: Env, app: App, _reply: Reply) -> AppResult {
+    let PendingZap {
+        funds,
+        balances_before,
+        target,
+    } = TEMP_ZAP.load(deps.storage)?;
+    TEMP_ZAP.remove(deps.storage);
+
+    // Amounts received from the swaps
+    let user = get_user(deps.as_ref(), &app)?;
+    let mut received = vec![];
+    for before in balances_before {
+        let balance = deps.querier.query_balance(&user, before.denom)?;
+        received.push(Coin {
+            amount: balance.amount - before.amount,
+            denom: balance.denom,
+        });
+    }
+    let mut zapped_funds = Coins::try_from(funds)?;
+    for coin in received.iter().cloned() {
+        zapped_funds.add(coin)?;
+    }
+
+    match target {
+        ZapTarget::Deposit {
+            max_spread,
+            belief_price0,
+            belief_price1,
+            token_min_amount0,
+            token_min_amount1,
+            principal,
+        } => {
+            if principal {
+                add_principal(deps.storage, &zapped_funds.to_vec())?;
+            } else {
+                add_compounded(deps.storage, &received)?;
+            }
+            // Funds are pool assets now, the contract deposits them without accounting them again
+            let deposit_msg = CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.to_string(),
+                msg: to_json_binary(&ExecuteMsg::Module(AppExecuteMsg::Deposit {
+                    funds: zapped_funds.into(),
+                    max_spread,
+                    belief_price0,
+                    belief_price1,
+                    token_min_amount0,
+                    token_min_amount1,
+                }))?,
+                funds: vec![],
+            });
+            Ok(app.response("zap_reply").add_message(deposit_msg))
+        }
+        ZapTarget::MigratedPosition { position } => {
+            create_migrated_position(deps, &env, &app, zapped_funds.into(), position)
+        }
+    }
+}
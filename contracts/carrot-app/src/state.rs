@@ -1,98 +1,286 @@
 use abstract_app::sdk::{feature_objects::AnsHost, Resolve};
-use abstract_app::{objects::DexAssetPairing, std::objects::AssetEntry};
+use abstract_app::{
+    objects::{AnsAsset, DexAssetPairing},
+    std::objects::AssetEntry,
+};
 use cosmwasm_schema::cw_serde;
 use cosmwasm_std::{
-    ensure, Deps, Env, MessageInfo, QuerierWrapper, StdResult, Storage, Timestamp, Uint128, Uint64,
+    ensure, Addr, Coin, Coins, Decimal, Deps, DepsMut, Env, MessageInfo, Order, QuerierWrapper,
+    StdError, StdResult, Storage, Timestamp, Uint128, Uint64,
 };
-use cw_storage_plus::Item;
-use osmosis_std::types::osmosis::concentratedliquidity::v1beta1::{
-    ConcentratedliquidityQuerier, FullPositionBreakdown,
+use cw_storage_plus::{Bound, Item, Map};
+use osmosis_std::{
+    try_proto_to_cosmwasm_coins,
+    types::osmosis::concentratedliquidity::v1beta1::{
+        ConcentratedliquidityQuerier, FullPositionBreakdown,
+    },
 };
 
-use crate::msg::SwapToAsset;
+use crate::msg::{PositionRange, RebalanceStatus, SwapToAsset};
 use crate::{contract::AppResult, error::AppError, msg::CompoundStatus};
 
-const POSITION: Item<Position> = Item::new("position2");
-const LAST_COMPOUND: Item<Timestamp> = Item::new("last_compound");
+const POSITIONS: Map<&str, Position> = Map::new("positions");
+pub(crate) const LAST_COMPOUND: Item<Timestamp> = Item::new("last_compound");
+const OUT_OF_RANGE_SINCE: Map<&str, Timestamp> = Map::new("out_of_range_since");
 pub const CONFIG: Item<Config> = Item::new("config2");
+/// Deposited funds still in the positions, per pool asset.
+/// Withdraws reduce it by their share of the value of the positions
+const PRINCIPAL: Item<Vec<Coin>> = Item::new("principal");
+/// Rewards compounded into the positions since the instantiation, per pool asset
+const COMPOUNDED: Item<Vec<Coin>> = Item::new("compounded");
+/// Last autocompounds by id, only the last [`COMPOUND_HISTORY_SIZE`] are kept
+const COMPOUND_HISTORY: Map<u64, CompoundEvent> = Map::new("compound_history");
+/// Id of the next autocompound
+const NEXT_COMPOUND_ID: Item<u64> = Item::new("next_compound_id");
+
+/// Number of autocompounds kept in the history, the oldest one gets dropped past that
+pub const COMPOUND_HISTORY_SIZE: u64 = 100;
+const DEFAULT_HISTORY_LIMIT: u32 = 10;
+const MAX_HISTORY_LIMIT: u32 = 50;
+
+/// Max spread of the autocompound swaps when none provided
+pub const DEFAULT_AUTOCOMPOUND_MAX_SPREAD: Decimal = Decimal::percent(5);
+/// Name of the position used when none provided
+pub const DEFAULT_POSITION: &str = "default";
+/// Weight of the position used when none provided
+pub const DEFAULT_WEIGHT: u64 = 1;
 
 #[cw_serde]
 struct Position {
     pub position_id: u64,
+    /// Share of the deposits going into this position, relative to the other positions
+    pub weight: u64,
+    /// Funds of the position the last time the app changed it,
+    /// values the position once it got withdrawn outside of the app
+    #[serde(default)]
+    pub funds: Vec<Coin>,
 }
 
 /// Type for handling position created by the carrot app and compound status
 #[derive(Clone)]
 pub struct CarrotPosition {
+    pub name: String,
     pub id: u64,
+    pub weight: u64,
     pub position: FullPositionBreakdown,
 }
 
 impl CarrotPosition {
     /// Private method
-    /// Load position id if it's stored in the state
-    fn may_load_id(storage: &dyn Storage) -> StdResult<Option<u64>> {
-        let maybe_position = POSITION.may_load(storage)?;
+    /// Load ids and weights of all the positions stored in the state
+    fn load_ids(storage: &dyn Storage) -> StdResult<Vec<(String, Position)>> {
+        POSITIONS
+            .range(storage, None, None, Order::Ascending)
+            .collect()
+    }
 
-        Ok(maybe_position.map(|position| position.position_id))
+    /// Private method
+    /// Query osmosis for the stored position, returns `None` if it's not found
+    fn from_stored(deps: Deps, name: String, stored: Position) -> Option<Self> {
+        may_load_osmosis_position(&deps.querier, stored.position_id).map(|position| Self {
+            name,
+            id: stored.position_id,
+            weight: stored.weight,
+            position,
+        })
     }
 
-    /// Load position, returns `Ok(None)` if no valid position found
-    pub fn may_load(deps: Deps) -> StdResult<Option<Self>> {
-        if let Some(id) = Self::may_load_id(deps.storage)? {
-            if let Some(position) = may_load_osmosis_position(&deps.querier, id) {
-                return Ok(Some(Self { id, position }));
-            }
-        }
-        Ok(None)
+    /// Load position by name, returns `Ok(None)` if no valid position found
+    pub fn may_load(deps: Deps, name: &str) -> StdResult<Option<Self>> {
+        let maybe_position = POSITIONS
+            .may_load(deps.storage, name)?
+            .and_then(|stored| Self::from_stored(deps, name.to_owned(), stored));
+        Ok(maybe_position)
+    }
+
+    /// Load position by name, errors if no valid position found
+    pub fn load(deps: Deps, name: &str) -> Result<Self, AppError> {
+        Self::may_load(deps, name)?.ok_or(AppError::NoPosition {})
+    }
+
+    /// Load all valid positions, positions that can't be found on osmosis are skipped
+    pub fn load_all(deps: Deps) -> StdResult<Vec<Self>> {
+        let positions = Self::load_ids(deps.storage)?
+            .into_iter()
+            .filter_map(|(name, stored)| Self::from_stored(deps, name, stored))
+            .collect();
+        Ok(positions)
     }
 
-    /// Load position, errors if no valid position found
-    pub fn load(deps: Deps) -> Result<Self, AppError> {
-        Self::may_load(deps)?.ok_or(AppError::NoPosition {})
+    /// Names, weights and last known funds of the stored positions that can't be found on osmosis anymore
+    pub fn load_stale(deps: Deps) -> StdResult<Vec<(String, u64, Vec<Coin>)>> {
+        let stale = Self::load_ids(deps.storage)?
+            .into_iter()
+            .filter(|(_, stored)| {
+                may_load_osmosis_position(&deps.querier, stored.position_id).is_none()
+            })
+            .map(|(name, stored)| (name, stored.weight, stored.funds))
+            .collect();
+        Ok(stale)
     }
 
-    /// Save position
+    /// Ids of all the stored positions, including the ones that can't be found on osmosis
+    pub fn load_stored_ids(storage: &dyn Storage) -> StdResult<Vec<u64>> {
+        let ids = Self::load_ids(storage)?
+            .into_iter()
+            .map(|(_, stored)| stored.position_id)
+            .collect();
+        Ok(ids)
+    }
+
+    /// Remove the position from the state
+    pub fn remove(storage: &mut dyn Storage, name: &str) {
+        POSITIONS.remove(storage, name);
+        OUT_OF_RANGE_SINCE.remove(storage, name);
+    }
+
+    /// Save position, along with its current funds
     pub fn save_position(
-        storage: &mut dyn Storage,
+        deps: DepsMut,
         compound_timestamp: &Timestamp,
+        name: &str,
         position_id: u64,
+        weight: u64,
     ) -> StdResult<()> {
-        POSITION.save(storage, &Position { position_id })?;
-        LAST_COMPOUND.save(storage, compound_timestamp)?;
+        let funds = match may_load_osmosis_position(&deps.querier, position_id) {
+            Some(position) => position_funds(&position)?,
+            None => vec![],
+        };
+        POSITIONS.save(
+            deps.storage,
+            name,
+            &Position {
+                position_id,
+                weight,
+                funds,
+            },
+        )?;
+        LAST_COMPOUND.save(deps.storage, compound_timestamp)?;
+        OUT_OF_RANGE_SINCE.remove(deps.storage, name);
         Ok(())
     }
 
-    /// Get the status of compound
-    pub fn compound_status(
-        deps: Deps,
+    /// Update the last known funds of the position, when they change without the position being saved
+    pub fn save_funds(storage: &mut dyn Storage, name: &str, funds: Vec<Coin>) -> StdResult<()> {
+        POSITIONS.update(storage, name, |stored| {
+            let mut stored = stored.ok_or_else(|| StdError::not_found("position"))?;
+            stored.funds = funds;
+            Ok(stored)
+        })?;
+        Ok(())
+    }
+
+    /// Funds of the position in the pool assets
+    pub fn funds(&self) -> StdResult<Vec<Coin>> {
+        position_funds(&self.position)
+    }
+
+    /// Whether the current tick of the pool is inside the range of the position
+    pub fn is_in_range(&self, current_tick: i64) -> bool {
+        let position = self.position.position.as_ref().unwrap();
+        position.lower_tick <= current_tick && current_tick < position.upper_tick
+    }
+
+    /// Keep track of the time the position went out of range, used for permissionless rebalance.
+    /// Has to be called whenever the position is seen in range, otherwise the next time it goes out of range
+    /// the grace period counts from the previous time.
+    /// Returns since when the position is out of range, `None` if it's in range
+    pub fn track_range(
+        &self,
+        storage: &mut dyn Storage,
         env: &Env,
-        cooldown_seconds: u64,
-    ) -> AppResult<(CompoundStatus, Option<Self>)> {
-        let status = match Self::may_load_id(deps.storage)? {
-            Some(id) => {
-                // If saved position but can't query - return position id
-                let Some(position) = may_load_osmosis_position(&deps.querier, id) else {
-                    return Ok((CompoundStatus::PositionNotAvailable(id), None));
-                };
-                let ready_on = LAST_COMPOUND
-                    .load(deps.storage)?
-                    .plus_seconds(cooldown_seconds);
+        current_tick: i64,
+    ) -> StdResult<Option<Timestamp>> {
+        if self.is_in_range(current_tick) {
+            OUT_OF_RANGE_SINCE.remove(storage, &self.name);
+            return Ok(None);
+        }
+        let out_of_range_since = OUT_OF_RANGE_SINCE
+            .may_load(storage, &self.name)?
+            .unwrap_or(env.block.time);
+        OUT_OF_RANGE_SINCE.save(storage, &self.name, &out_of_range_since)?;
+        Ok(Some(out_of_range_since))
+    }
+
+    /// Restart the grace period from now, the position went back in range without anyone noticing
+    pub fn restart_grace_period(&self, storage: &mut dyn Storage, env: &Env) -> StdResult<()> {
+        OUT_OF_RANGE_SINCE.save(storage, &self.name, &env.block.time)
+    }
+
+    /// Forget since when the position is out of range, done once it gets rebalanced
+    pub fn clear_out_of_range(&self, storage: &mut dyn Storage) {
+        OUT_OF_RANGE_SINCE.remove(storage, &self.name);
+    }
+
+    /// Get the status of permissionless rebalance
+    pub fn rebalance_status(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        current_tick: i64,
+        grace_period_seconds: u64,
+    ) -> StdResult<RebalanceStatus> {
+        if self.is_in_range(current_tick) {
+            return Ok(RebalanceStatus::InRange {});
+        }
+        let status = match OUT_OF_RANGE_SINCE.may_load(storage, &self.name)? {
+            Some(out_of_range_since) => {
+                let ready_on = out_of_range_since.plus_seconds(grace_period_seconds);
                 if env.block.time >= ready_on {
-                    (CompoundStatus::Ready {}, Some(Self { id, position }))
+                    RebalanceStatus::Ready {}
                 } else {
-                    (
-                        CompoundStatus::Cooldown(
-                            (ready_on.seconds() - env.block.time.seconds()).into(),
-                        ),
-                        Some(Self { id, position }),
+                    RebalanceStatus::GracePeriod(
+                        (ready_on.seconds() - env.block.time.seconds()).into(),
                     )
                 }
             }
-            None => (CompoundStatus::NoPosition {}, None),
+            None => RebalanceStatus::OutOfRange {},
         };
         Ok(status)
     }
+
+    /// Get the status of compound, all positions get compounded at once
+    pub fn compound_status(
+        deps: Deps,
+        env: &Env,
+        cooldown_seconds: u64,
+    ) -> AppResult<(CompoundStatus, Vec<Self>)> {
+        let stored_positions = Self::load_ids(deps.storage)?;
+        let Some((_, first_stored)) = stored_positions.first() else {
+            return Ok((CompoundStatus::NoPosition {}, vec![]));
+        };
+        let first_id = first_stored.position_id;
+
+        let positions: Vec<Self> = stored_positions
+            .into_iter()
+            .filter_map(|(name, stored)| Self::from_stored(deps, name, stored))
+            .collect();
+        // If saved positions but can't query any - return position id
+        if positions.is_empty() {
+            return Ok((CompoundStatus::PositionNotAvailable(first_id), vec![]));
+        }
+
+        let ready_on = LAST_COMPOUND
+            .load(deps.storage)?
+            .plus_seconds(cooldown_seconds);
+        let status = if env.block.time >= ready_on {
+            CompoundStatus::Ready {}
+        } else {
+            CompoundStatus::Cooldown((ready_on.seconds() - env.block.time.seconds()).into())
+        };
+        Ok((status, positions))
+    }
+}
+
+// Helper to get the pool assets of an osmosis position
+fn position_funds(position: &FullPositionBreakdown) -> StdResult<Vec<Coin>> {
+    try_proto_to_cosmwasm_coins(
+        position
+            .asset0
+            .clone()
+            .into_iter()
+            .chain(position.asset1.clone()),
+    )
 }
 
 // Helper to load osmosis position from id, returns `None` if position by id not found
@@ -107,13 +295,406 @@ fn may_load_osmosis_position(
 }
 
 // Temp state
-pub const TEMP_WITHDRAW_TO_ASSET: Item<SwapToAsset> = Item::new("wta");
+pub const TEMP_WITHDRAW_TO_ASSET: Item<WithdrawToAsset> = Item::new("wta");
+pub const TEMP_WITHDRAW_RECIPIENT: Item<WithdrawToRecipient> = Item::new("withdraw_recipient");
+pub const TEMP_REBALANCE: Item<RebalanceParams> = Item::new("rebalance");
+pub const TEMP_MIGRATE_POOL: Item<MigratePoolParams> = Item::new("migrate_pool");
+pub const TEMP_ZAP: Item<PendingZap> = Item::new("zap");
+/// Positions waiting for the create or add to position reply, in the order of submessages
+const TEMP_PENDING_POSITIONS: Item<Vec<PendingPosition>> = Item::new("pending_positions");
+
+/// Swap of withdrawn or harvested funds into one asset in progress,
+/// shared by the withdraw replies of all the positions
+#[cw_serde]
+pub struct WithdrawToAsset {
+    pub swap_to: SwapToAsset,
+    /// Number of withdraw replies still to come
+    pub pending_withdraws: u64,
+    /// Balance of the user in `to_asset` before the withdraw, to check the received amount
+    pub balance_before: Uint128,
+    /// Address the received amount gets forwarded to
+    pub recipient: Option<Addr>,
+}
+
+/// Position that gets saved once the reply with its id arrives
+#[cw_serde]
+pub struct PendingPosition {
+    pub name: String,
+    pub weight: u64,
+    /// Minimum amounts sent with the liquidity provision, reported when it fails
+    pub token_min_amount0: Uint128,
+    pub token_min_amount1: Uint128,
+}
+
+/// Error message of the concentrated liquidity module when the minimum amounts are not met
+const SLIPPAGE_BOUND_ERROR: &str = "slippage bound";
+
+impl PendingPosition {
+    /// Error of the failed liquidity provision.
+    /// Slippage failures are surfaced with the minimum amounts the provision was sent with,
+    /// other failures keep the original reason
+    pub fn provision_error(&self, reason: String) -> AppError {
+        if reason.contains(SLIPPAGE_BOUND_ERROR) {
+            AppError::LiquiditySlippage {
+                token_min_amount0: self.token_min_amount0,
+                token_min_amount1: self.token_min_amount1,
+                reason,
+            }
+        } else {
+            AppError::Std(StdError::generic_err(reason))
+        }
+    }
+}
+
+/// Queue position, it will get saved by the next create or add to position reply
+pub fn push_pending_position(storage: &mut dyn Storage, pending: PendingPosition) -> StdResult<()> {
+    let mut queue = TEMP_PENDING_POSITIONS
+        .may_load(storage)?
+        .unwrap_or_default();
+    queue.push(pending);
+    TEMP_PENDING_POSITIONS.save(storage, &queue)
+}
+
+/// Adds the funds deposited by the user to the principal
+pub fn add_principal(storage: &mut dyn Storage, funds: &[Coin]) -> StdResult<()> {
+    add_to_total(storage, PRINCIPAL, funds)
+}
+
+/// Removes the withdrawn share of the principal and of the compounded rewards,
+/// they are both part of the withdrawn funds
+pub fn reduce_principal(storage: &mut dyn Storage, withdrawn_share: Decimal) -> StdResult<()> {
+    reduce_total(storage, PRINCIPAL, withdrawn_share)?;
+    reduce_total(storage, COMPOUNDED, withdrawn_share)
+}
+
+/// Sets the principal of instances that didn't track it yet, tracked principal is kept
+pub fn seed_principal(storage: &mut dyn Storage, funds: &[Coin]) -> StdResult<()> {
+    if PRINCIPAL.may_load(storage)?.is_none() {
+        add_principal(storage, funds)?;
+    }
+    Ok(())
+}
+
+pub fn load_principal(storage: &dyn Storage) -> StdResult<Vec<Coin>> {
+    Ok(PRINCIPAL.may_load(storage)?.unwrap_or_default())
+}
+
+/// Adds the rewards deposited back into the positions to the compounded total
+pub fn add_compounded(storage: &mut dyn Storage, rewards: &[Coin]) -> StdResult<()> {
+    add_to_total(storage, COMPOUNDED, rewards)
+}
+
+pub fn load_compounded(storage: &dyn Storage) -> StdResult<Vec<Coin>> {
+    Ok(COMPOUNDED.may_load(storage)?.unwrap_or_default())
+}
+
+fn reduce_total(
+    storage: &mut dyn Storage,
+    item: Item<Vec<Coin>>,
+    withdrawn_share: Decimal,
+) -> StdResult<()> {
+    let total: Vec<Coin> = item
+        .may_load(storage)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|coin| Coin {
+            amount: coin.amount - coin.amount.mul_floor(withdrawn_share),
+            denom: coin.denom,
+        })
+        .filter(|coin| !coin.amount.is_zero())
+        .collect();
+    item.save(storage, &total)
+}
+
+fn add_to_total(storage: &mut dyn Storage, item: Item<Vec<Coin>>, funds: &[Coin]) -> StdResult<()> {
+    let mut total = Coins::try_from(item.may_load(storage)?.unwrap_or_default())?;
+    for coin in funds {
+        total.add(coin.clone())?;
+    }
+    item.save(storage, &total.into())
+}
+
+/// Autocompound as recorded in the compound history
+#[cw_serde]
+pub struct CompoundEvent {
+    /// Sequential id of the autocompound
+    pub id: u64,
+    pub timestamp: Timestamp,
+    /// Ids of the compounded positions, before the compound
+    pub position_ids: Vec<u64>,
+    pub spread_rewards: Vec<Coin>,
+    pub incentives: Vec<Coin>,
+    pub executor: Addr,
+    /// Reward paid to the executor, none when the admin compounded or the cooldown was not over
+    pub executor_reward: Option<AnsAsset>,
+}
+
+/// Records the autocompound in the history, assigning its id and dropping the oldest one when full
+pub fn push_compound_event(storage: &mut dyn Storage, mut event: CompoundEvent) -> StdResult<()> {
+    let id = NEXT_COMPOUND_ID.may_load(storage)?.unwrap_or_default();
+    event.id = id;
+    COMPOUND_HISTORY.save(storage, id, &event)?;
+    if id >= COMPOUND_HISTORY_SIZE {
+        COMPOUND_HISTORY.remove(storage, id - COMPOUND_HISTORY_SIZE);
+    }
+    NEXT_COMPOUND_ID.save(storage, &(id + 1))
+}
+
+/// Loads the autocompounds with an id above `start_after`, oldest first
+pub fn load_compound_history(
+    storage: &dyn Storage,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Vec<CompoundEvent>> {
+    let limit = limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .min(MAX_HISTORY_LIMIT) as usize;
+    COMPOUND_HISTORY
+        .range(
+            storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, event)| event))
+        .collect()
+}
+
+/// Take the position the current reply belongs to
+pub fn pop_pending_position(storage: &mut dyn Storage) -> StdResult<PendingPosition> {
+    let mut queue = TEMP_PENDING_POSITIONS.load(storage)?;
+    let pending = queue.remove(0);
+    if queue.is_empty() {
+        TEMP_PENDING_POSITIONS.remove(storage);
+    } else {
+        TEMP_PENDING_POSITIONS.save(storage, &queue)?;
+    }
+    Ok(pending)
+}
+
+/// Forward of the withdrawn funds to a recipient in progress, when they are not swapped
+#[cw_serde]
+pub struct WithdrawToRecipient {
+    /// Address the withdrawn funds get forwarded to
+    pub recipient: Addr,
+    /// Number of withdraw replies still to come
+    pub pending_withdraws: u64,
+}
+
+/// Parameters of the position that gets created once the out of range position is withdrawn
+#[cw_serde]
+pub struct RebalanceParams {
+    pub name: String,
+    pub weight: u64,
+    pub lower_tick: i64,
+    pub upper_tick: i64,
+    pub max_spread: Option<Decimal>,
+    pub belief_price0: Option<Decimal>,
+    pub belief_price1: Option<Decimal>,
+}
+
+/// Move of the app to a new pool in progress, waiting for the withdraw of the positions
+#[cw_serde]
+pub struct MigratePoolParams {
+    /// Pool config of the new pool
+    pub pool_config: PoolConfig,
+    pub position: MigratedPosition,
+    /// Balances of the user in the assets of the current pool before the withdraw
+    pub balances_before: Vec<Coin>,
+}
+
+/// Position opened in the pool the app migrates to
+#[cw_serde]
+pub struct MigratedPosition {
+    pub range: PositionRange,
+    pub max_spread: Option<Decimal>,
+    pub token_min_amount0: Option<Uint128>,
+    pub token_min_amount1: Option<Uint128>,
+}
+
+/// Funds waiting for the swaps of their non-pool assets into the pool assets
+#[cw_serde]
+pub struct PendingZap {
+    /// Funds that are pool assets already
+    pub funds: Vec<Coin>,
+    /// Balances of the user in the pool assets before the swaps
+    pub balances_before: Vec<Coin>,
+    /// What the funds are used for once swapped
+    pub target: ZapTarget,
+}
+
+#[cw_serde]
+pub enum ZapTarget {
+    /// Deposit into the positions, with the parameters of the original deposit
+    Deposit {
+        max_spread: Option<Decimal>,
+        belief_price0: Option<Decimal>,
+        belief_price1: Option<Decimal>,
+        token_min_amount0: Option<Uint128>,
+        token_min_amount1: Option<Uint128>,
+        /// Whether the funds were deposited by the user, compounded rewards otherwise
+        principal: bool,
+    },
+    /// Position of the pool the app migrated to
+    MigratedPosition { position: MigratedPosition },
+}
 
 #[cw_serde]
 pub struct Config {
     pub pool_config: PoolConfig,
     pub autocompound_cooldown_seconds: Uint64,
     pub autocompound_rewards_config: AutocompoundRewardsConfig,
+    /// Seconds the position has to be out of range before anyone can rebalance it.
+    /// Only the admin can rebalance when omitted
+    pub rebalance_grace_period_seconds: Option<Uint64>,
+    /// Guard against price manipulation, swaps are rejected when the pool price deviates from its TWAP.
+    /// Disabled when omitted
+    pub twap_config: Option<TwapConfig>,
+    /// Incentive tokens that get swapped into the pool assets on autocompound.
+    /// Other incentive tokens, that are not pool assets, are kept in the wallet
+    #[serde(default)]
+    pub incentives_swap_allowlist: Vec<AssetEntry>,
+    /// What the autocompound does with the rewards
+    #[serde(default)]
+    pub autocompound_mode: AutocompoundMode,
+    /// Share of the rewards that the autocompound deposits back into the positions,
+    /// the rest goes to the payout. Only applies to the compound mode
+    #[serde(default = "Decimal::one")]
+    pub compound_ratio: Decimal,
+    /// Destination of the rewards that are not compounded
+    pub payout: Option<PayoutConfig>,
+    /// Fee taken from the rewards on autocompound, before they get compounded
+    pub performance_fee: Option<PerformanceFee>,
+    /// Max spread of the swaps of the autocompound, the deposit of the rewards and the payout swaps
+    #[serde(default = "default_autocompound_max_spread")]
+    pub autocompound_max_spread: Decimal,
+    /// Deposits, autocompounds and position creations are rejected while paused
+    #[serde(default)]
+    pub paused: bool,
+}
+
+fn default_autocompound_max_spread() -> Decimal {
+    DEFAULT_AUTOCOMPOUND_MAX_SPREAD
+}
+
+impl Config {
+    /// Checks the permissionless rebalance settings
+    pub fn check_rebalance(&self) -> AppResult<()> {
+        if let Some(grace_period) = self.rebalance_grace_period_seconds {
+            ensure!(
+                !grace_period.is_zero(),
+                AppError::RebalanceConfigError(
+                    "rebalance_grace_period_seconds has to be bigger than 0".to_owned()
+                )
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks the compound ratio and the payout it requires
+    pub fn check_payout(&self, deps: Deps, ans_host: &AnsHost) -> AppResult<()> {
+        ensure!(
+            self.compound_ratio <= Decimal::one(),
+            AppError::PayoutConfigError("compound_ratio has to be between 0 and 1".to_owned())
+        );
+        match &self.payout {
+            Some(payout) => {
+                deps.api.addr_validate(&payout.address)?;
+                if let Some(swap_to) = &payout.swap_to {
+                    swap_to.resolve(&deps.querier, ans_host)?;
+                }
+            }
+            None => ensure!(
+                self.compound_ratio == Decimal::one(),
+                AppError::PayoutConfigError(
+                    "payout is required when compound_ratio is below 1".to_owned()
+                )
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Where the rewards that are not compounded get sent
+#[cw_serde]
+pub struct PayoutConfig {
+    /// Address receiving the payout
+    pub address: String,
+    /// Rewards get swapped into this asset before being sent when provided.
+    /// Rewards without a route into this asset are sent as they are
+    pub swap_to: Option<AssetEntry>,
+    /// Max spread of the payout swaps, the autocompound max spread when omitted
+    pub max_spread: Option<Decimal>,
+}
+
+/// What the autocompound does with the rewards
+#[cw_serde]
+#[derive(Default)]
+pub enum AutocompoundMode {
+    /// Deposit the rewards back into the positions
+    #[default]
+    Compound {},
+    /// Keep the rewards in the wallet, optionally swapped into one asset
+    Harvest { swap_to: Option<SwapToAsset> },
+}
+
+impl AutocompoundMode {
+    pub fn check(&self, deps: Deps, ans_host: &AnsHost) -> AppResult<()> {
+        if let Self::Harvest {
+            swap_to: Some(swap_to),
+        } = self
+        {
+            swap_to.to_asset.resolve(&deps.querier, ans_host)?;
+            if let Some(recipient) = &swap_to.recipient {
+                deps.api.addr_validate(recipient)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Share of the compounded rewards charged by the operator of the app
+#[cw_serde]
+pub struct PerformanceFee {
+    /// Share of the rewards taken as fee, 0.1 is 10%
+    pub fee: Decimal,
+    /// Address receiving the fee
+    pub recipient: String,
+}
+
+impl PerformanceFee {
+    pub fn check(&self, deps: Deps) -> AppResult<()> {
+        ensure!(
+            self.fee < Decimal::one(),
+            AppError::PerformanceFeeError("fee has to be below 1".to_owned())
+        );
+        deps.api.addr_validate(&self.recipient)?;
+        Ok(())
+    }
+}
+
+/// Configuration of the TWAP guard of the swaps
+#[cw_serde]
+pub struct TwapConfig {
+    /// Seconds of the arithmetic TWAP window, ending at the current block
+    pub window_seconds: Uint64,
+    /// Biggest allowed deviation of the swap price from the TWAP, 0.05 is 5%
+    pub max_deviation: Decimal,
+}
+
+impl TwapConfig {
+    pub fn check(&self) -> AppResult<()> {
+        ensure!(
+            !self.window_seconds.is_zero(),
+            AppError::TwapConfigError("window_seconds has to be bigger than zero".to_owned())
+        );
+        ensure!(
+            !self.max_deviation.is_zero() && self.max_deviation < Decimal::one(),
+            AppError::TwapConfigError("max_deviation has to be between 0 and 1".to_owned())
+        );
+        Ok(())
+    }
 }
 
 /// Configuration on how rewards should be distributed
@@ -162,6 +743,27 @@ pub struct PoolConfig {
     pub asset1: AssetEntry,
 }
 
+impl PoolConfig {
+    /// Pool asset the `asset` gets swapped into, the first pool asset it has a pairing with
+    pub fn swap_target(
+        &self,
+        deps: Deps,
+        dex_name: &str,
+        ans_host: &AnsHost,
+        asset: &AssetEntry,
+    ) -> AppResult<AssetEntry> {
+        [&self.asset0, &self.asset1]
+            .into_iter()
+            .find(|pool_asset| {
+                DexAssetPairing::new(asset.clone(), (*pool_asset).clone(), dex_name)
+                    .resolve(&deps.querier, ans_host)
+                    .is_ok()
+            })
+            .cloned()
+            .ok_or(AppError::NoSwapPossibility {})
+    }
+}
+
 pub fn assert_contract(info: &MessageInfo, env: &Env) -> AppResult<()> {
     if info.sender == env.contract.address {
         Ok(())
@@ -2,15 +2,18 @@ mod common;
 
 use crate::common::incentives::Incentives;
 use crate::common::{
-    create_position, setup_test_tube, DEX_NAME, GAS_DENOM, LOTS, REWARD_DENOM, USDC, USDC_DENOM,
-    USDT, USDT_DENOM,
+    create_position, generate_spread_rewards, setup_test_tube, update_config, ConfigUpdate,
+    DEX_NAME, GAS_DENOM, LOTS, REWARD_ASSET, REWARD_DENOM, USDC, USDC_DENOM, USDT, USDT_DENOM,
 };
 use abstract_app::abstract_interface::{Abstract, AbstractAccount};
+use abstract_app::objects::AssetEntry;
+use carrot_app::error::AppError;
 use carrot_app::msg::{
-    AppExecuteMsgFns, AppQueryMsgFns, AssetsBalanceResponse, CompoundStatus, CompoundStatusResponse,
+    AppExecuteMsgFns, AppQueryMsgFns, AssetsBalanceResponse, CompoundStatus,
+    CompoundStatusResponse, OptionalUpdate,
 };
-use carrot_app::state::AutocompoundRewardsConfig;
-use cosmwasm_std::{coin, coins, Uint128, Uint64};
+use carrot_app::state::{AutocompoundRewardsConfig, PayoutConfig, PerformanceFee};
+use cosmwasm_std::{coin, coins, Decimal, Uint128, Uint64};
 use cw_asset::AssetBase;
 use cw_orch::{anyhow, prelude::*};
 use cw_orch_osmosis_test_tube::osmosis_test_tube::{
@@ -70,12 +73,7 @@ fn check_autocompound() -> anyhow::Result<()> {
         chain.wait_seconds(lockable_durations.lockable_durations[0].seconds as u64)?;
     }
     // Create position
-    create_position(
-        &carrot_app,
-        coins(100_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
 
     // Do some swaps
     let dex: abstract_dex_adapter::interface::DexAdapter<_> = carrot_app.module()?;
@@ -154,6 +152,9 @@ fn check_autocompound() -> anyhow::Result<()> {
     let status = carrot_app.compound_status()?;
     assert!(status.spread_rewards.is_empty());
     assert!(status.incentives.is_empty());
+    // Compounded rewards are accounted
+    let performance = carrot_app.performance()?;
+    assert!(!performance.compounded.is_empty());
 
     Ok(())
 }
@@ -170,12 +171,7 @@ fn stranger_autocompound() -> anyhow::Result<()> {
     let stranger = chain.init_account(coins(LOTS, GAS_DENOM))?;
 
     // Create position
-    create_position(
-        &carrot_app,
-        coins(100_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
 
     // Add incentive
     {
@@ -292,18 +288,302 @@ fn update_autocompound_config() -> anyhow::Result<()> {
         config.autocompound_rewards_config.reward,
         Uint128::new(1000)
     );
-    carrot_app.update_config(
-        Some(Uint64::new(1)),
-        Some(AutocompoundRewardsConfig {
-            gas_asset: config.autocompound_rewards_config.gas_asset,
-            swap_asset: config.autocompound_rewards_config.swap_asset,
-            reward: Uint128::zero(),
-            min_gas_balance: config.autocompound_rewards_config.min_gas_balance,
-            max_gas_balance: config.autocompound_rewards_config.max_gas_balance,
-        }),
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            autocompound_cooldown_seconds: Some(Uint64::new(1)),
+            autocompound_rewards_config: Some(AutocompoundRewardsConfig {
+                gas_asset: config.autocompound_rewards_config.gas_asset,
+                swap_asset: config.autocompound_rewards_config.swap_asset,
+                reward: Uint128::zero(),
+                min_gas_balance: config.autocompound_rewards_config.min_gas_balance,
+                max_gas_balance: config.autocompound_rewards_config.max_gas_balance,
+            }),
+            ..Default::default()
+        },
     )?;
     let config = carrot_app.config()?;
     assert_eq!(config.autocompound_cooldown_seconds, Uint64::new(1));
     assert_eq!(config.autocompound_rewards_config.reward, Uint128::zero());
+    assert_eq!(config.autocompound_max_spread, Decimal::percent(5));
+
+    let max_spread_err = update_config(
+        &carrot_app,
+        ConfigUpdate {
+            autocompound_max_spread: Some(Decimal::one()),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(max_spread_err
+        .to_string()
+        .contains("Autocompound max spread has to be below 1"));
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            autocompound_max_spread: Some(Decimal::percent(1)),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(
+        carrot_app.config()?.autocompound_max_spread,
+        Decimal::percent(1)
+    );
+    Ok(())
+}
+
+#[test]
+fn update_incentives_swap_allowlist() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    // Incentive without a pairing into the pool assets
+    let no_pairing_err = update_config(
+        &carrot_app,
+        ConfigUpdate {
+            incentives_swap_allowlist: Some(vec![
+                AssetEntry::new(USDC),
+                AssetEntry::new("unknown"),
+            ]),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(no_pairing_err
+        .to_string()
+        .contains(&AppError::NoSwapPossibility {}.to_string()));
+
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            incentives_swap_allowlist: Some(vec![AssetEntry::new(REWARD_ASSET)]),
+            ..Default::default()
+        },
+    )?;
+    let config = carrot_app.config()?;
+    assert_eq!(
+        config.incentives_swap_allowlist,
+        vec![AssetEntry::new(REWARD_ASSET)]
+    );
+    Ok(())
+}
+
+#[test]
+fn autocompound_with_payout() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+
+    // Payout required below a full compound
+    let payout_err = update_config(
+        &carrot_app,
+        ConfigUpdate {
+            compound_ratio: Some(Decimal::percent(70)),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(payout_err
+        .to_string()
+        .contains("payout is required when compound_ratio is below 1"));
+
+    let payee = chain.init_account(coins(LOTS, GAS_DENOM))?;
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            compound_ratio: Some(Decimal::percent(70)),
+            payout: Some(OptionalUpdate::Set(PayoutConfig {
+                address: payee.address(),
+                swap_to: Some(AssetEntry::new(USDC)),
+                max_spread: None,
+            })),
+            ..Default::default()
+        },
+    )?;
+
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+
+    generate_spread_rewards(&carrot_app)?;
+
+    let balance_before_autocompound: AssetsBalanceResponse = carrot_app.balance()?;
+    chain.wait_seconds(300)?;
+    carrot_app.autocompound()?;
+
+    // Part of the rewards compounded, the rest paid out in USDC
+    let balance_after_autocompound: AssetsBalanceResponse = carrot_app.balance()?;
+    assert!(balance_after_autocompound.liquidity > balance_before_autocompound.liquidity);
+    let payee_balance = chain.bank_querier().balance(payee.address(), None)?;
+    assert!(payee_balance
+        .iter()
+        .any(|c| c.denom == USDC_DENOM && !c.amount.is_zero()));
+    assert!(!payee_balance.iter().any(|c| c.denom == USDT_DENOM));
+
+    // Payout can only be cleared along with a full compound
+    let clear_err = update_config(
+        &carrot_app,
+        ConfigUpdate {
+            payout: Some(OptionalUpdate::Clear {}),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(clear_err
+        .to_string()
+        .contains("payout is required when compound_ratio is below 1"));
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            compound_ratio: Some(Decimal::one()),
+            payout: Some(OptionalUpdate::Clear {}),
+            ..Default::default()
+        },
+    )?;
+    assert!(carrot_app.config()?.payout.is_none());
+    Ok(())
+}
+
+#[test]
+fn stranger_autocompound_everything_paid_out() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+    let stranger = chain.init_account(coins(LOTS, GAS_DENOM))?;
+    let payee = chain.init_account(coins(LOTS, GAS_DENOM))?;
+
+    // Nothing gets compounded, so no deposit saves the positions
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            compound_ratio: Some(Decimal::zero()),
+            payout: Some(OptionalUpdate::Set(PayoutConfig {
+                address: payee.address(),
+                swap_to: None,
+                max_spread: None,
+            })),
+            ..Default::default()
+        },
+    )?;
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+    generate_spread_rewards(&carrot_app)?;
+
+    let balance_before_autocompound: AssetsBalanceResponse = carrot_app.balance()?;
+    chain.wait_seconds(300)?;
+    carrot_app.call_as(&stranger).autocompound()?;
+    let balance_after_autocompound: AssetsBalanceResponse = carrot_app.balance()?;
+    assert_eq!(
+        balance_after_autocompound.liquidity,
+        balance_before_autocompound.liquidity
+    );
+
+    // Cooldown started anyway
+    let cooldown_err = carrot_app.call_as(&stranger).autocompound().unwrap_err();
+    assert!(cooldown_err
+        .to_string()
+        .contains("Autocompound is on cooldown"));
+    let stranger_reward_balance = chain.query_balance(stranger.address().as_str(), REWARD_DENOM)?;
+    assert_eq!(stranger_reward_balance, Uint128::new(1000));
+    Ok(())
+}
+
+#[test]
+fn autocompound_performance_fee() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+
+    let fee_recipient = chain.init_account(coins(LOTS, GAS_DENOM))?;
+    let fee_err = update_config(
+        &carrot_app,
+        ConfigUpdate {
+            performance_fee: Some(OptionalUpdate::Set(PerformanceFee {
+                fee: Decimal::one(),
+                recipient: fee_recipient.address(),
+            })),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(fee_err.to_string().contains("Performance fee error"));
+
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            performance_fee: Some(OptionalUpdate::Set(PerformanceFee {
+                fee: Decimal::percent(10),
+                recipient: fee_recipient.address(),
+            })),
+            ..Default::default()
+        },
+    )?;
+
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+    generate_spread_rewards(&carrot_app)?;
+    let status = carrot_app.compound_status()?;
+
+    chain.wait_seconds(300)?;
+    let response = carrot_app.autocompound()?;
+
+    // Fee recipient got 10% of the rewards
+    for reward in status.spread_rewards {
+        let expected_fee = reward.amount.mul_floor(Decimal::percent(10));
+        let received = chain
+            .bank_querier()
+            .balance(fee_recipient.address(), Some(reward.denom))?
+            .pop()
+            .map(|c| c.amount)
+            .unwrap_or_default();
+        assert_eq!(received, expected_fee);
+    }
+    assert!(response
+        .event_attr_values("wasm-abstract", "performance_fee")
+        .iter()
+        .any(|fee| !fee.is_empty()));
+
+    // No fee once cleared
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            performance_fee: Some(OptionalUpdate::Clear {}),
+            ..Default::default()
+        },
+    )?;
+    assert!(carrot_app.config()?.performance_fee.is_none());
+    let fee_balances_before = chain
+        .bank_querier()
+        .balance(fee_recipient.address(), None)?;
+    generate_spread_rewards(&carrot_app)?;
+    chain.wait_seconds(300)?;
+    carrot_app.autocompound()?;
+    let fee_balances_after = chain
+        .bank_querier()
+        .balance(fee_recipient.address(), None)?;
+    assert_eq!(fee_balances_before, fee_balances_after);
+    Ok(())
+}
+
+#[test]
+fn compound_history() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let chain = carrot_app.environment().clone();
+
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+    assert!(carrot_app.compound_history(None, None)?.events.is_empty());
+
+    for _ in 0..2 {
+        generate_spread_rewards(&carrot_app)?;
+        chain.wait_seconds(300)?;
+        carrot_app.autocompound()?;
+    }
+
+    let history = carrot_app.compound_history(None, None)?;
+    assert_eq!(history.events.len(), 2);
+    let first = &history.events[0];
+    assert_eq!(first.id, 0);
+    assert_eq!(first.executor, chain.sender_addr());
+    assert!(!first.spread_rewards.is_empty());
+    // Admin doesn't get the executor reward
+    assert!(first.executor_reward.is_none());
+    assert!(history.events[1].timestamp > first.timestamp);
+
+    // Paginated
+    let page = carrot_app.compound_history(Some(0), Some(1))?;
+    assert_eq!(page.events.len(), 1);
+    assert_eq!(page.events[0].id, 1);
     Ok(())
 }
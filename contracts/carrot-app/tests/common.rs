@@ -1,5 +1,6 @@
 use std::iter;
 
+use abstract_app::abstract_interface::{Abstract, AbstractAccount};
 use abstract_app::objects::module::ModuleInfo;
 use abstract_app::std::{
     manager::{self, ModuleInstallConfig},
@@ -8,8 +9,12 @@ use abstract_app::std::{
 use abstract_client::{AbstractClient, Application, Environment, Namespace};
 use abstract_dex_adapter::DEX_ADAPTER_ID;
 use carrot_app::contract::APP_ID;
-use carrot_app::msg::{AppInstantiateMsg, CreatePositionMessage};
-use carrot_app::state::AutocompoundRewardsConfig;
+use carrot_app::msg::{
+    AppInstantiateMsg, AppQueryMsgFns, CreatePositionMessage, OptionalUpdate, PositionRange,
+};
+use carrot_app::state::{
+    AutocompoundMode, AutocompoundRewardsConfig, PayoutConfig, PerformanceFee, TwapConfig,
+};
 use cosmwasm_std::{coin, coins, to_json_binary, to_json_vec, Decimal, Uint128, Uint64};
 use cw_asset::AssetInfoUnchecked;
 use cw_orch::anyhow;
@@ -128,6 +133,14 @@ pub fn deploy<Chain: CwEnv + Stargate>(
             min_gas_balance: Uint128::new(2000),
             max_gas_balance: Uint128::new(10000),
         },
+        rebalance_grace_period_seconds: Some(Uint64::new(300)),
+        twap_config: None,
+        incentives_swap_allowlist: None,
+        autocompound_mode: None,
+        compound_ratio: None,
+        payout: None,
+        performance_fee: None,
+        autocompound_max_spread: None,
         create_position,
     };
     // If we create position on instantiate - give auth
@@ -201,19 +214,21 @@ pub fn deploy<Chain: CwEnv + Stargate>(
 pub fn create_position<Chain: CwEnv>(
     app: &Application<Chain, carrot_app::AppInterface<Chain>>,
     funds: Vec<Coin>,
-    asset0: Coin,
-    asset1: Coin,
 ) -> anyhow::Result<Chain::Response> {
     app.execute(
         &carrot_app::msg::AppExecuteMsg::CreatePosition(CreatePositionMessage {
-            lower_tick: INITIAL_LOWER_TICK,
-            upper_tick: INITIAL_UPPER_TICK,
+            name: None,
+            weight: None,
+            range: PositionRange::Ticks {
+                lower_tick: INITIAL_LOWER_TICK,
+                upper_tick: INITIAL_UPPER_TICK,
+            },
             funds,
-            asset0,
-            asset1,
             max_spread: None,
             belief_price0: None,
             belief_price1: None,
+            token_min_amount0: None,
+            token_min_amount1: None,
         })
         .into(),
         None,
@@ -221,6 +236,93 @@ pub fn create_position<Chain: CwEnv>(
     .map_err(Into::into)
 }
 
+/// Fields of `UpdateConfig`, the ones left to `None` don't get updated
+#[derive(Default)]
+pub struct ConfigUpdate {
+    pub autocompound_cooldown_seconds: Option<Uint64>,
+    pub autocompound_rewards_config: Option<AutocompoundRewardsConfig>,
+    pub rebalance_grace_period_seconds: Option<OptionalUpdate<Uint64>>,
+    pub twap_config: Option<OptionalUpdate<TwapConfig>>,
+    pub incentives_swap_allowlist: Option<Vec<AssetEntry>>,
+    pub autocompound_mode: Option<AutocompoundMode>,
+    pub compound_ratio: Option<Decimal>,
+    pub payout: Option<OptionalUpdate<PayoutConfig>>,
+    pub performance_fee: Option<OptionalUpdate<PerformanceFee>>,
+    pub autocompound_max_spread: Option<Decimal>,
+}
+
+pub fn update_config<Chain: CwEnv>(
+    app: &Application<Chain, carrot_app::AppInterface<Chain>>,
+    update: ConfigUpdate,
+) -> anyhow::Result<Chain::Response> {
+    let ConfigUpdate {
+        autocompound_cooldown_seconds,
+        autocompound_rewards_config,
+        rebalance_grace_period_seconds,
+        twap_config,
+        incentives_swap_allowlist,
+        autocompound_mode,
+        compound_ratio,
+        payout,
+        performance_fee,
+        autocompound_max_spread,
+    } = update;
+    app.execute(
+        &carrot_app::msg::AppExecuteMsg::UpdateConfig {
+            autocompound_cooldown_seconds,
+            autocompound_rewards_config,
+            rebalance_grace_period_seconds,
+            twap_config,
+            incentives_swap_allowlist,
+            autocompound_mode,
+            compound_ratio,
+            payout,
+            performance_fee,
+            autocompound_max_spread,
+        }
+        .into(),
+        None,
+    )
+    .map_err(Into::into)
+}
+
+/// Swaps back and forth in the pool so the positions earn spread rewards
+pub fn generate_spread_rewards(
+    carrot_app: &carrot_app::AppInterface<OsmosisTestTube>,
+) -> anyhow::Result<()> {
+    let chain = carrot_app.environment().clone();
+    let dex: abstract_dex_adapter::interface::DexAdapter<_> = carrot_app.module()?;
+    let abs = Abstract::load_from(chain.clone())?;
+    let account_id = carrot_app.account().id()?;
+    let account = AbstractAccount::new(&abs, account_id);
+    chain.bank_send(
+        account.proxy.addr_str()?,
+        vec![
+            coin(200_000, USDC_DENOM.to_owned()),
+            coin(200_000, USDT_DENOM.to_owned()),
+        ],
+    )?;
+    for _ in 0..10 {
+        dex.ans_swap(
+            (USDC, 50_000),
+            USDT,
+            DEX_NAME.to_string(),
+            &account,
+            &abs.ans_host,
+        )?;
+        dex.ans_swap(
+            (USDT, 50_000),
+            USDC,
+            DEX_NAME.to_string(),
+            &account,
+            &abs.ans_host,
+        )?;
+    }
+    let status = carrot_app.compound_status()?;
+    assert!(!status.spread_rewards.is_empty());
+    Ok(())
+}
+
 pub fn create_pool(mut chain: OsmosisTestTube) -> anyhow::Result<(u64, u64)> {
     chain.add_balance(chain.sender_addr(), coins(LOTS, USDC_DENOM))?;
     chain.add_balance(chain.sender_addr(), coins(LOTS, USDT_DENOM))?;
@@ -321,14 +423,18 @@ pub fn setup_test_tube(
     let (pool_id, gas_pool_id) = create_pool(chain.clone())?;
 
     let create_position_msg = create_position.then(|| CreatePositionMessage {
-        lower_tick: INITIAL_LOWER_TICK,
-        upper_tick: INITIAL_UPPER_TICK,
+        name: None,
+        weight: None,
+        range: PositionRange::Ticks {
+            lower_tick: INITIAL_LOWER_TICK,
+            upper_tick: INITIAL_UPPER_TICK,
+        },
         funds: coins(100_000, USDT_DENOM),
-        asset0: coin(1_000_000, USDT_DENOM),
-        asset1: coin(1_000_000, USDC_DENOM),
         max_spread: None,
         belief_price0: None,
         belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
     });
     let carrot_app = deploy(chain.clone(), pool_id, gas_pool_id, create_position_msg)?;
 
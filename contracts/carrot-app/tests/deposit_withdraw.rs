@@ -2,20 +2,27 @@ mod common;
 
 use std::str::FromStr;
 
-use crate::common::{create_position, setup_test_tube, USDC, USDC_DENOM, USDT, USDT_DENOM};
+use crate::common::{
+    create_position, setup_test_tube, INITIAL_LOWER_TICK, INITIAL_UPPER_TICK, USDC, USDC_DENOM,
+    USDT, USDT_DENOM,
+};
 use abstract_app::objects::AssetEntry;
 use abstract_interface::{Abstract, AbstractAccount};
+use carrot_app::error::AppError;
 use carrot_app::msg::{
     AppExecuteMsg, AppExecuteMsgFns, AppQueryMsgFns, AssetsBalanceResponse, CompoundStatus,
-    CreatePositionMessage, PositionResponse, SwapToAsset,
+    CreatePositionMessage, PositionRange, PositionResponse, SwapToAsset, WithdrawAmount,
 };
-use common::{DEX_NAME, GAS_DENOM};
-use cosmwasm_std::{coin, coins, Decimal, Uint128, Uint256};
+use common::{DEX_NAME, GAS_DENOM, LOTS, REWARD_ASSET, REWARD_DENOM, TICK_SPACING};
+use cosmwasm_std::{coin, coins, Addr, Decimal, Decimal256, Uint128, Uint256};
 use cw_orch::anyhow;
 use cw_orch::prelude::*;
 use cw_orch_osmosis_test_tube::osmosis_test_tube::osmosis_std::types::osmosis::concentratedliquidity::v1beta1::PositionByIdRequest;
 use cw_orch_osmosis_test_tube::osmosis_test_tube::{
-    osmosis_std::types::osmosis::concentratedliquidity::v1beta1::MsgWithdrawPosition,
+    osmosis_std::types::{
+        cosmos::base::v1beta1,
+        osmosis::concentratedliquidity::v1beta1::{MsgCreatePosition, MsgWithdrawPosition},
+    },
     ConcentratedLiquidity, Module,
 };
 
@@ -27,12 +34,7 @@ fn deposit_lands() -> anyhow::Result<()> {
     // Either missed position range or fees
     let max_difference = Uint128::new(deposit_amount).mul_floor(Decimal::percent(3));
     // Create position
-    create_position(
-        &carrot_app,
-        coins(deposit_amount, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(deposit_amount, USDT_DENOM.to_owned()))?;
     // Check almost everything landed
     let balance: AssetsBalanceResponse = carrot_app.balance()?;
     let sum = balance
@@ -47,6 +49,8 @@ fn deposit_lands() -> anyhow::Result<()> {
         None,
         None,
         None,
+        None,
+        None,
     )?;
     // Check almost everything landed
     let balance: AssetsBalanceResponse = carrot_app.balance()?;
@@ -62,6 +66,8 @@ fn deposit_lands() -> anyhow::Result<()> {
         None,
         None,
         None,
+        None,
+        None,
     )?;
     // Check almost everything landed
     let balance: AssetsBalanceResponse = carrot_app.balance()?;
@@ -73,6 +79,60 @@ fn deposit_lands() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn zap_deposit() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+
+    let deposit_amount = 5_000;
+    // Fees of the zap swap on top of the position swap
+    let max_difference = Uint128::new(deposit_amount).mul_floor(Decimal::percent(6));
+    create_position(&carrot_app, coins(deposit_amount, USDT_DENOM.to_owned()))?;
+    let balance_before: AssetsBalanceResponse = carrot_app.balance()?;
+
+    // Reward token is worth half of USDC
+    chain.add_balance(chain.sender_addr(), coins(deposit_amount * 2, REWARD_DENOM))?;
+    carrot_app.deposit(
+        coins(deposit_amount * 2, REWARD_DENOM),
+        None,
+        None,
+        None,
+        None,
+        None,
+    )?;
+
+    // Check almost everything landed
+    let balance: AssetsBalanceResponse = carrot_app.balance()?;
+    let deposited = balance
+        .balances
+        .iter()
+        .zip(balance_before.balances.iter())
+        .fold(Uint128::zero(), |acc, (after, before)| {
+            acc + after.amount - before.amount
+        });
+    assert!(deposited.u128() > deposit_amount - max_difference.u128());
+
+    // Principal counts the amounts received from the zap swap, on top of the created position
+    let principal: u128 = carrot_app
+        .performance()?
+        .principal
+        .iter()
+        .map(|coin| coin.amount.u128())
+        .sum();
+    assert!(principal - deposit_amount >= deposited.u128());
+    assert!(principal - deposit_amount <= deposit_amount);
+
+    // Nothing left in the reward token
+    let reward_balance = chain
+        .bank_querier()
+        .balance(chain.sender_addr(), Some(REWARD_DENOM.to_owned()))?
+        .pop()
+        .map(|coin| coin.amount)
+        .unwrap_or_default();
+    assert!(reward_balance.is_zero());
+    Ok(())
+}
+
 #[test]
 fn withdraw_position() -> anyhow::Result<()> {
     let (_, carrot_app) = setup_test_tube(false)?;
@@ -80,12 +140,7 @@ fn withdraw_position() -> anyhow::Result<()> {
     let chain = carrot_app.environment().clone();
 
     // Create position
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     let balance: AssetsBalanceResponse = carrot_app.balance()?;
     let balance_usdc_before_withdraw = chain
@@ -102,7 +157,12 @@ fn withdraw_position() -> anyhow::Result<()> {
     // Withdraw half of liquidity
     let liquidity_amount: Uint256 = balance.liquidity.parse().unwrap();
     let half_of_liquidity = liquidity_amount / Uint256::from_u128(2);
-    carrot_app.withdraw(Some(half_of_liquidity), None)?;
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Liquidity(half_of_liquidity)),
+        None,
+        None,
+        None,
+    )?;
 
     let balance_usdc_after_half_withdraw = chain
         .bank_querier()
@@ -119,7 +179,7 @@ fn withdraw_position() -> anyhow::Result<()> {
     assert!(balance_usdt_after_half_withdraw.amount > balance_usdt_before_withdraw.amount);
 
     // Withdraw rest of liquidity
-    carrot_app.withdraw(None, None)?;
+    carrot_app.withdraw(None, None, None, None)?;
     let balance_usdc_after_full_withdraw = chain
         .bank_querier()
         .balance(chain.sender_addr(), Some(USDT_DENOM.to_owned()))?
@@ -136,17 +196,90 @@ fn withdraw_position() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn withdraw_percentage_and_value() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
+    let liquidity = || -> anyhow::Result<Uint256> {
+        let balance: AssetsBalanceResponse = carrot_app.balance()?;
+        Ok(balance.liquidity.parse()?)
+    };
+    let initial_liquidity = liquidity()?;
+
+    let err = carrot_app
+        .withdraw(
+            Some(WithdrawAmount::Percentage { bps: 0 }),
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("Withdraw percentage"));
+
+    // Withdraw a quarter
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Percentage { bps: 2_500 }),
+        None,
+        None,
+        None,
+    )?;
+    let expected_liquidity = initial_liquidity - initial_liquidity.multiply_ratio(1u128, 4u128);
+    assert!(liquidity()?.abs_diff(expected_liquidity) <= Uint256::one());
+
+    let err = carrot_app
+        .withdraw(
+            Some(WithdrawAmount::Value {
+                asset: AssetEntry::new(USDT),
+                amount: Uint128::zero(),
+            }),
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains(&AppError::ZeroWithdrawValue {}.to_string()));
+
+    // Position is worth around 7_500 USDT now
+    let err = carrot_app
+        .withdraw(
+            Some(WithdrawAmount::Value {
+                asset: AssetEntry::new(USDT),
+                amount: Uint128::new(10_000),
+            }),
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("exceeds the position value"));
+
+    // Withdraw around a third of what's left
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Value {
+            asset: AssetEntry::new(USDT),
+            amount: Uint128::new(2_500),
+        }),
+        None,
+        None,
+        None,
+    )?;
+    let liquidity_left = liquidity()?;
+    let expected_liquidity = expected_liquidity.multiply_ratio(2u128, 3u128);
+    assert!(
+        liquidity_left.abs_diff(expected_liquidity) <= expected_liquidity / Uint256::from_u128(50)
+    );
+    Ok(())
+}
+
 #[test]
 fn deposit_both_assets() -> anyhow::Result<()> {
     let (_, carrot_app) = setup_test_tube(false)?;
 
     // Create position
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     carrot_app.deposit(
         vec![
@@ -156,6 +289,8 @@ fn deposit_both_assets() -> anyhow::Result<()> {
         None,
         None,
         None,
+        None,
+        None,
     )?;
 
     Ok(())
@@ -166,7 +301,7 @@ fn create_position_on_instantiation() -> anyhow::Result<()> {
     let (_, carrot_app) = setup_test_tube(true)?;
 
     let position: PositionResponse = carrot_app.position()?;
-    assert!(position.position_id.is_some());
+    assert!(!position.positions.is_empty());
     Ok(())
 }
 
@@ -176,7 +311,7 @@ fn withdraw_after_user_withdraw_liquidity_manually() -> anyhow::Result<()> {
     let chain = carrot_app.environment().clone();
 
     let position: PositionResponse = carrot_app.position()?;
-    let position_id = position.position_id.unwrap();
+    let position_id = position.positions[0].position_id;
 
     let test_tube = chain.app.borrow();
     let cl = ConcentratedLiquidity::new(&*test_tube);
@@ -195,7 +330,7 @@ fn withdraw_after_user_withdraw_liquidity_manually() -> anyhow::Result<()> {
     )?;
 
     // Ensure it errors
-    carrot_app.withdraw(None, None).unwrap_err();
+    carrot_app.withdraw(None, None, None, None).unwrap_err();
 
     // Ensure we get correct compound response
     let status_response = carrot_app.compound_status()?;
@@ -214,6 +349,150 @@ fn withdraw_after_user_withdraw_liquidity_manually() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn reconcile_after_user_withdraw_liquidity_manually() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(true)?;
+    let chain = carrot_app.environment().clone();
+
+    let withdraw_manually = |position_id: u64| -> anyhow::Result<()> {
+        let test_tube = chain.app.borrow();
+        let cl = ConcentratedLiquidity::new(&*test_tube);
+        let position = cl
+            .query_position_by_id(&PositionByIdRequest { position_id })?
+            .position
+            .unwrap()
+            .position
+            .unwrap();
+        cl.withdraw_position(
+            MsgWithdrawPosition {
+                position_id,
+                sender: chain.sender_addr().to_string(),
+                liquidity_amount: position.liquidity,
+            },
+            &chain.sender,
+        )?;
+        Ok(())
+    };
+
+    let position_id = carrot_app.position()?.positions[0].position_id;
+    withdraw_manually(position_id)?;
+
+    // The position of the user created with the pool gets adopted
+    carrot_app.reconcile()?;
+    let position: PositionResponse = carrot_app.position()?;
+    assert_eq!(position.positions.len(), 1);
+    let adopted_id = position.positions[0].position_id;
+    assert_ne!(adopted_id, position_id);
+    assert_eq!(position.positions[0].name, "default");
+    let status_response = carrot_app.compound_status()?;
+    assert_ne!(
+        status_response.status,
+        CompoundStatus::PositionNotAvailable(position_id)
+    );
+
+    // Principal follows the funds of the adopted position
+    assert!(!carrot_app.performance()?.principal.is_empty());
+
+    // Nothing left to adopt, the stale position gets removed
+    withdraw_manually(adopted_id)?;
+    carrot_app.reconcile()?;
+    assert!(carrot_app.position()?.positions.is_empty());
+    assert!(carrot_app.performance()?.principal.is_empty());
+    let status_response = carrot_app.compound_status()?;
+    assert_eq!(status_response.status, CompoundStatus::NoPosition {});
+    Ok(())
+}
+
+#[test]
+fn import_position() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let chain = carrot_app.environment().clone();
+    let pool_id = carrot_app.config()?.pool_config.pool_id;
+
+    // Position created by the user outside of the app
+    let position_id = {
+        let test_tube = chain.app.borrow();
+        let cl = ConcentratedLiquidity::new(&*test_tube);
+        cl.create_position(
+            MsgCreatePosition {
+                pool_id,
+                sender: chain.sender_addr().to_string(),
+                lower_tick: INITIAL_LOWER_TICK,
+                upper_tick: INITIAL_UPPER_TICK,
+                tokens_provided: vec![
+                    v1beta1::Coin {
+                        denom: USDT_DENOM.to_owned(),
+                        amount: "100000".to_owned(),
+                    },
+                    v1beta1::Coin {
+                        denom: USDC_DENOM.to_owned(),
+                        amount: "100000".to_owned(),
+                    },
+                ],
+                token_min_amount0: "0".to_string(),
+                token_min_amount1: "0".to_string(),
+            },
+            &chain.sender,
+        )?
+        .data
+        .position_id
+    };
+
+    // Unknown position
+    let not_found_err = carrot_app.import_position(position_id + 1).unwrap_err();
+    assert!(not_found_err.to_string().contains("can't be imported"));
+
+    carrot_app.import_position(position_id)?;
+    let position: PositionResponse = carrot_app.position()?;
+    assert_eq!(position.positions.len(), 1);
+    assert_eq!(position.positions[0].position_id, position_id);
+    assert!(!carrot_app.performance()?.principal.is_empty());
+
+    // Default position is taken now
+    let exists_err = carrot_app.import_position(position_id).unwrap_err();
+    assert!(exists_err.to_string().contains("Position already exists"));
+
+    // Imported position is usable by the app
+    carrot_app.deposit(
+        vec![coin(5_000, USDT_DENOM.to_owned())],
+        None,
+        None,
+        None,
+        None,
+        None,
+    )?;
+    Ok(())
+}
+
+#[test]
+fn import_tracked_position() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    // Position tracked under another name, the default name is free
+    carrot_app.create_position(CreatePositionMessage {
+        name: Some("other".to_owned()),
+        weight: None,
+        range: PositionRange::Ticks {
+            lower_tick: INITIAL_LOWER_TICK,
+            upper_tick: INITIAL_UPPER_TICK,
+        },
+        funds: coins(100_000, USDT_DENOM),
+        max_spread: None,
+        belief_price0: None,
+        belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
+    })?;
+    let position_id = carrot_app.position()?.positions[0].position_id;
+
+    let tracked_err = carrot_app.import_position(position_id).unwrap_err();
+    assert!(tracked_err
+        .to_string()
+        .contains("position is already tracked"));
+    assert_eq!(carrot_app.position()?.positions.len(), 1);
+    Ok(())
+}
+
 #[test]
 fn deposit_slippage() -> anyhow::Result<()> {
     let (_, carrot_app) = setup_test_tube(false)?;
@@ -221,12 +500,7 @@ fn deposit_slippage() -> anyhow::Result<()> {
     let deposit_amount = 5_000;
     let max_difference = Uint128::new(deposit_amount).mul_floor(Decimal::percent(3));
     // Create position
-    create_position(
-        &carrot_app,
-        coins(deposit_amount, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(deposit_amount, USDT_DENOM.to_owned()))?;
 
     // Do the deposit of asset0 with incorrect belief_price1
     let e = carrot_app
@@ -235,6 +509,8 @@ fn deposit_slippage() -> anyhow::Result<()> {
             None,
             Some(Decimal::zero()),
             None,
+            None,
+            None,
         )
         .unwrap_err();
     assert!(e.to_string().contains("exceeds max spread limit"));
@@ -246,6 +522,8 @@ fn deposit_slippage() -> anyhow::Result<()> {
             Some(Decimal::zero()),
             None,
             None,
+            None,
+            None,
         )
         .unwrap_err();
     assert!(e.to_string().contains("exceeds max spread limit"));
@@ -256,6 +534,8 @@ fn deposit_slippage() -> anyhow::Result<()> {
         None,
         Some(Decimal::one()),
         Some(Decimal::percent(10)),
+        None,
+        None,
     )?;
     // Do the deposits of asset1 with correct belief_price
     carrot_app.deposit(
@@ -263,6 +543,8 @@ fn deposit_slippage() -> anyhow::Result<()> {
         Some(Decimal::one()),
         None,
         Some(Decimal::percent(10)),
+        None,
+        None,
     )?;
 
     // Check almost everything landed
@@ -272,6 +554,21 @@ fn deposit_slippage() -> anyhow::Result<()> {
         .iter()
         .fold(Uint128::zero(), |acc, e| acc + e.amount);
     assert!(sum.u128() > (deposit_amount - max_difference.u128()) * 3);
+
+    // Minimum amounts above the deposited funds can't be met
+    let e = carrot_app
+        .deposit(
+            vec![coin(deposit_amount, USDT_DENOM.to_owned())],
+            None,
+            None,
+            None,
+            Some(Uint128::new(deposit_amount * 2)),
+            None,
+        )
+        .unwrap_err();
+    assert!(e
+        .to_string()
+        .contains("Liquidity provision doesn't meet the minimum amounts"));
     Ok(())
 }
 
@@ -281,12 +578,7 @@ fn partial_withdraw_position_autoclaims() -> anyhow::Result<()> {
 
     let chain = carrot_app.environment().clone();
     // Create position
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     // Do some swaps
     let dex: abstract_dex_adapter::interface::DexAdapter<_> = carrot_app.module()?;
@@ -325,7 +617,12 @@ fn partial_withdraw_position_autoclaims() -> anyhow::Result<()> {
     let balance: AssetsBalanceResponse = carrot_app.balance()?;
     let liquidity_amount: Uint256 = balance.liquidity.parse().unwrap();
     let half_of_liquidity = liquidity_amount / Uint256::from_u128(2);
-    carrot_app.withdraw(Some(half_of_liquidity), None)?;
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Liquidity(half_of_liquidity)),
+        None,
+        None,
+        None,
+    )?;
 
     // Check rewards claimed
     let status = carrot_app.compound_status()?;
@@ -341,12 +638,7 @@ fn manual_partial_withdraw_position_doesnt_autoclaim() -> anyhow::Result<()> {
     let chain = carrot_app.environment().clone();
 
     // Create position
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     // Do some swaps
     let dex: abstract_dex_adapter::interface::DexAdapter<_> = carrot_app.module()?;
@@ -386,7 +678,7 @@ fn manual_partial_withdraw_position_doesnt_autoclaim() -> anyhow::Result<()> {
     let cl = ConcentratedLiquidity::new(&*test_tube);
 
     let position: PositionResponse = carrot_app.position()?;
-    let position_id = position.position_id.unwrap();
+    let position_id = position.positions[0].position_id;
 
     let balance: AssetsBalanceResponse = carrot_app.balance()?;
     let liquidity_amount: Uint128 = balance.liquidity.parse().unwrap();
@@ -417,14 +709,18 @@ fn shifted_position_create_deposit() -> anyhow::Result<()> {
 
     // Create one-way shifted position
     carrot_app.create_position(CreatePositionMessage {
-        lower_tick: -37000,
-        upper_tick: 1000,
+        name: None,
+        weight: None,
+        range: PositionRange::Ticks {
+            lower_tick: -37000,
+            upper_tick: 1000,
+        },
         funds: coins(deposit_amount, USDT_DENOM),
-        asset0: coin(205_000, USDT_DENOM),
-        asset1: coin(753_000, USDC_DENOM),
         max_spread: None,
         belief_price0: None,
         belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
     })?;
 
     let balance = carrot_app.balance()?;
@@ -435,7 +731,14 @@ fn shifted_position_create_deposit() -> anyhow::Result<()> {
     assert!(sum.u128() > (deposit_amount - max_difference.u128()));
 
     // Deposit asset0
-    carrot_app.deposit(coins(deposit_amount, USDT_DENOM), None, None, None)?;
+    carrot_app.deposit(
+        coins(deposit_amount, USDT_DENOM),
+        None,
+        None,
+        None,
+        None,
+        None,
+    )?;
     let balance = carrot_app.balance()?;
     let sum = balance
         .balances
@@ -444,7 +747,14 @@ fn shifted_position_create_deposit() -> anyhow::Result<()> {
     assert!(sum.u128() > (deposit_amount - max_difference.u128()) * 2);
 
     // Deposit asset1
-    carrot_app.deposit(coins(deposit_amount, USDT_DENOM), None, None, None)?;
+    carrot_app.deposit(
+        coins(deposit_amount, USDT_DENOM),
+        None,
+        None,
+        None,
+        None,
+        None,
+    )?;
     let balance = carrot_app.balance()?;
     let sum = balance
         .balances
@@ -454,6 +764,83 @@ fn shifted_position_create_deposit() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn create_position_from_price_range() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let chain = carrot_app.environment().clone();
+
+    let deposit_amount = 10_000;
+    let max_difference = Uint128::new(deposit_amount).mul_floor(Decimal::percent(3));
+
+    // Absolute prices, ratio computed by the contract
+    carrot_app.create_position(CreatePositionMessage {
+        name: Some("prices".to_owned()),
+        weight: None,
+        range: PositionRange::Prices {
+            lower_price: Decimal256::percent(90),
+            upper_price: Decimal256::percent(110),
+        },
+        funds: coins(deposit_amount, USDT_DENOM),
+        max_spread: None,
+        belief_price0: None,
+        belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
+    })?;
+
+    // ±5% around the spot price
+    carrot_app.create_position(CreatePositionMessage {
+        name: Some("spot".to_owned()),
+        weight: None,
+        range: PositionRange::AroundSpot {
+            percentage: Decimal::percent(5),
+        },
+        funds: coins(deposit_amount, USDT_DENOM),
+        max_spread: None,
+        belief_price0: None,
+        belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
+    })?;
+
+    let balance = carrot_app.balance()?;
+    for position_balance in balance.positions {
+        let sum = position_balance
+            .balances
+            .iter()
+            .fold(Uint128::zero(), |acc, e| acc + e.amount);
+        assert!(sum.u128() > (deposit_amount - max_difference.u128()));
+    }
+
+    let test_tube = chain.app.borrow();
+    let cl = ConcentratedLiquidity::new(&*test_tube);
+    let position_ticks = |name: &str| -> anyhow::Result<(i64, i64)> {
+        let positions: PositionResponse = carrot_app.position()?;
+        let position_id = positions
+            .positions
+            .into_iter()
+            .find(|position| position.name == name)
+            .unwrap()
+            .position_id;
+        let position = cl
+            .query_position_by_id(&PositionByIdRequest { position_id })?
+            .position
+            .unwrap()
+            .position
+            .unwrap();
+        Ok((position.lower_tick, position.upper_tick))
+    };
+
+    // 0.9 and 1.1 are exact ticks aligned to the tick spacing
+    assert_eq!(position_ticks("prices")?, (-1_000_000, 100_000));
+
+    let (lower_tick, upper_tick) = position_ticks("spot")?;
+    assert_eq!(lower_tick % TICK_SPACING as i64, 0);
+    assert_eq!(upper_tick % TICK_SPACING as i64, 0);
+    assert!(lower_tick < 0 && 0 < upper_tick);
+    Ok(())
+}
+
 #[test]
 fn error_on_provided_funds() -> anyhow::Result<()> {
     let (_, carrot_app) = setup_test_tube(false)?;
@@ -461,14 +848,18 @@ fn error_on_provided_funds() -> anyhow::Result<()> {
     carrot_app
         .execute(
             &AppExecuteMsg::CreatePosition(CreatePositionMessage {
-                lower_tick: -37000,
-                upper_tick: 1000,
+                name: None,
+                weight: None,
+                range: PositionRange::Ticks {
+                    lower_tick: -37000,
+                    upper_tick: 1000,
+                },
                 funds: coins(10_000, USDT),
-                asset0: coin(205_000, USDT),
-                asset1: coin(753_000, USDC),
                 max_spread: None,
                 belief_price0: None,
                 belief_price1: None,
+                token_min_amount0: None,
+                token_min_amount1: None,
             })
             .into(),
             Some(&[coin(10, GAS_DENOM)]),
@@ -484,12 +875,7 @@ fn withdraw_to_asset() -> anyhow::Result<()> {
     let chain = carrot_app.environment().clone();
     let initial_amount = 100_000;
     // Create position
-    create_position(
-        &carrot_app,
-        coins(initial_amount, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(initial_amount, USDT_DENOM.to_owned()))?;
     let liquidity = carrot_app.balance()?.liquidity;
     let liquidity = Uint256::from_str(&liquidity)?;
     let withdraw_liquidity_amount = liquidity / Uint256::from_u128(3);
@@ -506,11 +892,15 @@ fn withdraw_to_asset() -> anyhow::Result<()> {
             .balance(chain.sender_addr(), Some(USDC_DENOM.to_owned()))?;
 
         carrot_app.withdraw(
-            Some(withdraw_liquidity_amount),
+            Some(WithdrawAmount::Liquidity(withdraw_liquidity_amount)),
             Some(carrot_app::msg::SwapToAsset {
                 to_asset: AssetEntry::new(USDC),
                 max_spread: None,
+                min_receive: None,
+                recipient: None,
             }),
+            None,
+            None,
         )?;
 
         let asset0_balance_after = chain
@@ -537,11 +927,15 @@ fn withdraw_to_asset() -> anyhow::Result<()> {
             .balance(chain.sender_addr(), Some(USDC_DENOM.to_owned()))?;
 
         carrot_app.withdraw(
-            Some(withdraw_liquidity_amount),
+            Some(WithdrawAmount::Liquidity(withdraw_liquidity_amount)),
             Some(SwapToAsset {
                 to_asset: AssetEntry::new(USDT),
                 max_spread: None,
+                min_receive: None,
+                recipient: None,
             }),
+            None,
+            None,
         )?;
 
         let asset0_balance_after = chain
@@ -559,3 +953,99 @@ fn withdraw_to_asset() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+#[test]
+fn withdraw_to_asset_multi_hop() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    let chain = carrot_app.environment().clone();
+    let initial_amount = 10_000;
+    create_position(&carrot_app, coins(initial_amount, USDT_DENOM.to_owned()))?;
+
+    // Reward token is worth half of USDC, USDT gets routed through USDC
+    let expected_amount = Uint128::new(initial_amount * 2);
+    let swap_to = |min_receive: Uint128| SwapToAsset {
+        to_asset: AssetEntry::new(REWARD_ASSET),
+        max_spread: None,
+        min_receive: Some(min_receive),
+        recipient: None,
+    };
+
+    let err = carrot_app
+        .withdraw(
+            None,
+            Some(swap_to(expected_amount * Uint128::new(2))),
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("less than the minimum"));
+
+    let reward_balance_before = chain
+        .bank_querier()
+        .balance(chain.sender_addr(), Some(REWARD_DENOM.to_owned()))?
+        .pop()
+        .map(|coin| coin.amount)
+        .unwrap_or_default();
+
+    let min_receive = expected_amount.mul_floor(Decimal::percent(85));
+    carrot_app.withdraw(None, Some(swap_to(min_receive)), None, None)?;
+
+    let reward_balance_after = chain
+        .bank_querier()
+        .balance(chain.sender_addr(), Some(REWARD_DENOM.to_owned()))?
+        .pop()
+        .map(|coin| coin.amount)
+        .unwrap_or_default();
+    assert!(reward_balance_after - reward_balance_before >= min_receive);
+
+    Ok(())
+}
+
+#[test]
+fn withdraw_to_recipient() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    let mut chain = carrot_app.environment().clone();
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
+    let treasury = chain.init_account(coins(LOTS, GAS_DENOM))?;
+    let payee = chain.init_account(coins(LOTS, GAS_DENOM))?;
+
+    let balance_of = |address: &Addr, denom: &str| -> anyhow::Result<Uint128> {
+        Ok(chain
+            .bank_querier()
+            .balance(address, Some(denom.to_owned()))?
+            .pop()
+            .map(|coin| coin.amount)
+            .unwrap_or_default())
+    };
+
+    // Half of the withdrawn funds go to the treasury as they are
+    let simulation =
+        carrot_app.simulate_withdraw(Some(WithdrawAmount::Percentage { bps: 5_000 }), None)?;
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Percentage { bps: 5_000 }),
+        None,
+        None,
+        Some(treasury.address()),
+    )?;
+    for coin in simulation.withdrawn {
+        let received = balance_of(&Addr::unchecked(treasury.address()), &coin.denom)?;
+        assert!(received.abs_diff(coin.amount) <= Uint128::one());
+    }
+
+    // The rest gets swapped to USDC for the payee
+    carrot_app.withdraw(
+        None,
+        Some(SwapToAsset {
+            to_asset: AssetEntry::new(USDC),
+            max_spread: None,
+            min_receive: None,
+            recipient: Some(payee.address()),
+        }),
+        None,
+        Some(treasury.address()),
+    )?;
+    assert!(!balance_of(&Addr::unchecked(payee.address()), USDC_DENOM)?.is_zero());
+    Ok(())
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use crate::common::{
+    create_position, generate_spread_rewards, setup_test_tube, update_config, ConfigUpdate,
+    GAS_DENOM, LOTS, REWARD_DENOM, USDC, USDC_DENOM, USDT_DENOM,
+};
+use abstract_app::objects::AssetEntry;
+use carrot_app::msg::{
+    AppExecuteMsgFns, AppQueryMsgFns, AssetsBalanceResponse, CompoundStatus, SwapToAsset,
+};
+use carrot_app::state::AutocompoundMode;
+use cosmwasm_std::{coins, Addr, Uint128};
+use cw_orch::{anyhow, prelude::*};
+use cw_orch_osmosis_test_tube::osmosis_test_tube::Account;
+
+#[test]
+fn harvest_rewards() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+    generate_spread_rewards(&carrot_app)?;
+
+    let balance_before: AssetsBalanceResponse = carrot_app.balance()?;
+    carrot_app.harvest(None)?;
+
+    // Rewards collected without being compounded
+    let balance_after: AssetsBalanceResponse = carrot_app.balance()?;
+    assert_eq!(balance_after.liquidity, balance_before.liquidity);
+    let status = carrot_app.compound_status()?;
+    assert!(status.spread_rewards.is_empty());
+    Ok(())
+}
+
+#[test]
+fn harvest_rewards_to_asset() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+    generate_spread_rewards(&carrot_app)?;
+
+    // Unreachable minimum
+    let min_receive_err = carrot_app
+        .harvest(Some(SwapToAsset {
+            to_asset: AssetEntry::new(USDC),
+            max_spread: None,
+            min_receive: Some(Uint128::new(1_000_000_000)),
+            recipient: None,
+        }))
+        .unwrap_err();
+    assert!(min_receive_err.to_string().contains("minimum"));
+
+    let recipient = Addr::unchecked(chain.init_account(coins(LOTS, GAS_DENOM))?.address());
+    carrot_app.harvest(Some(SwapToAsset {
+        to_asset: AssetEntry::new(USDC),
+        max_spread: None,
+        min_receive: Some(Uint128::one()),
+        recipient: Some(recipient.to_string()),
+    }))?;
+
+    // All of the rewards got swapped into USDC and forwarded
+    let recipient_balance = chain.bank_querier().balance(&recipient, None)?;
+    assert!(recipient_balance
+        .iter()
+        .any(|c| c.denom == USDC_DENOM && !c.amount.is_zero()));
+    assert!(!recipient_balance.iter().any(|c| c.denom == USDT_DENOM));
+    Ok(())
+}
+
+#[test]
+fn autocompound_in_harvest_mode() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+    generate_spread_rewards(&carrot_app)?;
+
+    // Harvest mode forwarding rewards to the recipient
+    let recipient = Addr::unchecked(chain.init_account(coins(LOTS, GAS_DENOM))?.address());
+    let mode = AutocompoundMode::Harvest {
+        swap_to: Some(SwapToAsset {
+            to_asset: AssetEntry::new(USDC),
+            max_spread: None,
+            min_receive: None,
+            recipient: Some(recipient.to_string()),
+        }),
+    };
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            autocompound_mode: Some(mode.clone()),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(carrot_app.config()?.autocompound_mode, mode);
+
+    let balance_before: AssetsBalanceResponse = carrot_app.balance()?;
+    chain.wait_seconds(300)?;
+    carrot_app.autocompound()?;
+
+    // Liquidity unchanged and rewards received
+    let balance_after: AssetsBalanceResponse = carrot_app.balance()?;
+    assert_eq!(balance_after.liquidity, balance_before.liquidity);
+    let recipient_usdc = chain
+        .bank_querier()
+        .balance(&recipient, Some(USDC_DENOM.to_owned()))?
+        .pop()
+        .unwrap();
+    assert!(!recipient_usdc.amount.is_zero());
+    Ok(())
+}
+
+#[test]
+fn stranger_autocompound_in_harvest_mode() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+    let stranger = chain.init_account(coins(LOTS, GAS_DENOM))?;
+
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+    generate_spread_rewards(&carrot_app)?;
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            autocompound_mode: Some(AutocompoundMode::Harvest { swap_to: None }),
+            ..Default::default()
+        },
+    )?;
+
+    chain.wait_seconds(300)?;
+    carrot_app.call_as(&stranger).autocompound()?;
+    let stranger_reward_balance = chain.query_balance(stranger.address().as_str(), REWARD_DENOM)?;
+    assert_eq!(stranger_reward_balance, Uint128::new(1000));
+
+    // Harvest starts the cooldown as well, no second executor reward
+    let status = carrot_app.compound_status()?;
+    assert!(matches!(status.status, CompoundStatus::Cooldown(_)));
+    let cooldown_err = carrot_app.call_as(&stranger).autocompound().unwrap_err();
+    assert!(cooldown_err
+        .to_string()
+        .contains("Autocompound is on cooldown"));
+    let stranger_reward_balance = chain.query_balance(stranger.address().as_str(), REWARD_DENOM)?;
+    assert_eq!(stranger_reward_balance, Uint128::new(1000));
+    Ok(())
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use crate::common::{
+    setup_test_tube, INITIAL_LOWER_TICK, INITIAL_UPPER_TICK, TICK_SPACING, USDC_DENOM, USDT_DENOM,
+};
+use carrot_app::error::AppError;
+use carrot_app::msg::{AppExecuteMsgFns, AppQueryMsgFns, AssetsBalanceResponse, PositionRange};
+use cosmwasm_std::{Decimal, Uint128};
+use cw_orch::{anyhow, prelude::*};
+use cw_orch_osmosis_test_tube::osmosis_test_tube::{
+    osmosis_std::types::{
+        cosmos::base::v1beta1,
+        osmosis::concentratedliquidity::v1beta1::{
+            CreateConcentratedLiquidityPoolsProposal, MsgCreatePosition, Pool, PoolRecord,
+            PoolsRequest, PositionByIdRequest,
+        },
+    },
+    ConcentratedLiquidity, GovWithAppAccess, Module,
+};
+use cw_orch_osmosis_test_tube::OsmosisTestTube;
+use prost::Message;
+
+/// Creates another usdt-usdc pool with a different spread factor and provides liquidity to it
+fn create_second_pool(chain: &OsmosisTestTube) -> anyhow::Result<u64> {
+    GovWithAppAccess::new(&chain.app.borrow())
+        .propose_and_execute(
+            CreateConcentratedLiquidityPoolsProposal::TYPE_URL.to_string(),
+            CreateConcentratedLiquidityPoolsProposal {
+                title: "Create lower fee usdt:usdc pool".to_string(),
+                description: "Create lower fee usdt:usdc pool, to migrate the app to".to_string(),
+                pool_records: vec![PoolRecord {
+                    denom0: USDT_DENOM.to_owned(),
+                    denom1: USDC_DENOM.to_owned(),
+                    tick_spacing: TICK_SPACING,
+                    spread_factor: Decimal::permille(5).atomics().to_string(),
+                }],
+            },
+            chain.sender_addr().to_string(),
+            &chain.sender,
+        )
+        .unwrap();
+    let test_tube = chain.app.borrow();
+    let cl = ConcentratedLiquidity::new(&*test_tube);
+
+    let pools = cl.query_pools(&PoolsRequest { pagination: None })?;
+    let pool = Pool::decode(pools.pools.last().unwrap().value.as_slice())?;
+    cl.create_position(
+        MsgCreatePosition {
+            pool_id: pool.id,
+            sender: chain.sender_addr().to_string(),
+            lower_tick: INITIAL_LOWER_TICK,
+            upper_tick: INITIAL_UPPER_TICK,
+            tokens_provided: vec![
+                v1beta1::Coin {
+                    denom: USDC_DENOM.to_owned(),
+                    amount: "10000000".to_owned(),
+                },
+                v1beta1::Coin {
+                    denom: USDT_DENOM.to_owned(),
+                    amount: "10000000".to_owned(),
+                },
+            ],
+            token_min_amount0: "0".to_string(),
+            token_min_amount1: "0".to_string(),
+        },
+        &chain.sender,
+    )?;
+    Ok(pool.id)
+}
+
+#[test]
+fn migrate_pool() -> anyhow::Result<()> {
+    let (pool_id, carrot_app) = setup_test_tube(true)?;
+    let chain = carrot_app.environment().clone();
+    let new_pool_id = create_second_pool(&chain)?;
+    assert_ne!(new_pool_id, pool_id);
+
+    let range = PositionRange::Ticks {
+        lower_tick: INITIAL_LOWER_TICK,
+        upper_tick: INITIAL_UPPER_TICK,
+    };
+    // Can't migrate to the pool in use
+    let same_pool_err = carrot_app
+        .migrate_pool(pool_id, range.clone(), None, None, None)
+        .unwrap_err();
+    assert!(same_pool_err
+        .to_string()
+        .contains(&AppError::SamePool { pool_id }.to_string()));
+
+    // Minimum amounts the new position can't take revert the migration
+    let slippage_err = carrot_app
+        .migrate_pool(
+            new_pool_id,
+            range.clone(),
+            None,
+            Some(Uint128::new(u128::MAX)),
+            None,
+        )
+        .unwrap_err();
+    assert!(slippage_err
+        .to_string()
+        .contains("Liquidity provision doesn't meet the minimum amounts"));
+    assert_eq!(carrot_app.config()?.pool_config.pool_id, pool_id);
+
+    let old_position_id = carrot_app.position()?.positions[0].position_id;
+    let balance_before: AssetsBalanceResponse = carrot_app.balance()?;
+    carrot_app.migrate_pool(new_pool_id, range, Some(Decimal::percent(5)), None, None)?;
+
+    // App uses the new pool
+    assert_eq!(carrot_app.config()?.pool_config.pool_id, new_pool_id);
+    let positions = carrot_app.position()?.positions;
+    assert_eq!(positions.len(), 1);
+    assert_ne!(positions[0].position_id, old_position_id);
+    let test_tube = chain.app.borrow();
+    let cl = ConcentratedLiquidity::new(&*test_tube);
+    let position = cl
+        .query_position_by_id(&PositionByIdRequest {
+            position_id: positions[0].position_id,
+        })?
+        .position
+        .unwrap()
+        .position
+        .unwrap();
+    assert_eq!(position.pool_id, new_pool_id);
+    // Old position is gone
+    cl.query_position_by_id(&PositionByIdRequest {
+        position_id: old_position_id,
+    })
+    .unwrap_err();
+
+    // Funds moved along, minus the swap fees
+    let balance_after: AssetsBalanceResponse = carrot_app.balance()?;
+    let sum = |balance: &AssetsBalanceResponse| {
+        balance
+            .balances
+            .iter()
+            .map(|coin| coin.amount.u128())
+            .sum::<u128>()
+    };
+    assert!(sum(&balance_after) > sum(&balance_before) * 97 / 100);
+    Ok(())
+}
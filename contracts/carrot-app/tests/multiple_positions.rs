@@ -0,0 +1,218 @@
+mod common;
+
+use crate::common::{
+    create_position, setup_test_tube, INITIAL_LOWER_TICK, INITIAL_UPPER_TICK, USDT_DENOM,
+};
+use carrot_app::error::AppError;
+use carrot_app::msg::{
+    AppExecuteMsgFns, AppQueryMsgFns, AssetsBalanceResponse, CreatePositionMessage, PositionRange,
+    PositionResponse, WithdrawAmount,
+};
+use cosmwasm_std::{coin, coins, Decimal, Uint128, Uint256};
+use cw_orch::{anyhow, prelude::*};
+use cw_orch_osmosis_test_tube::osmosis_test_tube::{
+    osmosis_std::types::osmosis::concentratedliquidity::v1beta1::{
+        MsgWithdrawPosition, PositionByIdRequest,
+    },
+    ConcentratedLiquidity, Module,
+};
+
+const CORE_POSITION: &str = "core";
+
+fn position_sum(balance: &AssetsBalanceResponse, name: &str) -> Uint128 {
+    balance
+        .positions
+        .iter()
+        .find(|position| position.name == name)
+        .unwrap()
+        .balances
+        .iter()
+        .fold(Uint128::zero(), |acc, e| acc + e.amount)
+}
+
+#[test]
+fn deposit_and_withdraw_across_positions() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    // Wide default position
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
+
+    // Tight core position with bigger weight
+    carrot_app.create_position(CreatePositionMessage {
+        name: Some(CORE_POSITION.to_owned()),
+        weight: Some(3),
+        range: PositionRange::Ticks {
+            lower_tick: -1000,
+            upper_tick: 1000,
+        },
+        funds: coins(10_000, USDT_DENOM),
+        max_spread: None,
+        belief_price0: None,
+        belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
+    })?;
+
+    // Position names are unique
+    let exists_err = carrot_app
+        .create_position(CreatePositionMessage {
+            name: Some(CORE_POSITION.to_owned()),
+            weight: None,
+            range: PositionRange::Ticks {
+                lower_tick: INITIAL_LOWER_TICK,
+                upper_tick: INITIAL_UPPER_TICK,
+            },
+            funds: coins(10_000, USDT_DENOM),
+            max_spread: None,
+            belief_price0: None,
+            belief_price1: None,
+            token_min_amount0: None,
+            token_min_amount1: None,
+        })
+        .unwrap_err();
+    assert!(exists_err
+        .to_string()
+        .contains(&AppError::PositionExists {}.to_string()));
+
+    let position: PositionResponse = carrot_app.position()?;
+    assert_eq!(position.positions.len(), 2);
+    let core = position
+        .positions
+        .iter()
+        .find(|position| position.name == CORE_POSITION)
+        .unwrap();
+    assert_eq!(core.weight, 3);
+
+    // Deposit gets split between positions
+    let balance_before: AssetsBalanceResponse = carrot_app.balance()?;
+    carrot_app.deposit(
+        vec![coin(40_000, USDT_DENOM.to_owned())],
+        None,
+        None,
+        None,
+        None,
+        None,
+    )?;
+    let balance_after: AssetsBalanceResponse = carrot_app.balance()?;
+
+    let core_deposited =
+        position_sum(&balance_after, CORE_POSITION) - position_sum(&balance_before, CORE_POSITION);
+    let default_deposited =
+        position_sum(&balance_after, "default") - position_sum(&balance_before, "default");
+    assert!(core_deposited > default_deposited * Uint128::new(2));
+    assert!(!default_deposited.is_zero());
+
+    // Total balance is the sum of all positions
+    let total = balance_after
+        .balances
+        .iter()
+        .fold(Uint128::zero(), |acc, e| acc + e.amount);
+    assert_eq!(
+        total,
+        position_sum(&balance_after, CORE_POSITION) + position_sum(&balance_after, "default")
+    );
+
+    // Liquidity amount requires position name
+    let liquidity: Uint256 = balance_after.liquidity.parse()?;
+    let name_err = carrot_app
+        .withdraw(
+            Some(WithdrawAmount::Liquidity(liquidity / Uint256::from_u128(2))),
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert!(name_err
+        .to_string()
+        .contains(&AppError::PositionNameRequired {}.to_string()));
+
+    // Withdraw only the core position
+    carrot_app.withdraw(None, None, Some(CORE_POSITION.to_owned()), None)?;
+    let position: PositionResponse = carrot_app.position()?;
+    assert_eq!(position.positions.len(), 1);
+    assert_eq!(position.positions[0].name, "default");
+
+    // Withdraw the rest
+    carrot_app.withdraw(None, None, None, None)?;
+    let position: PositionResponse = carrot_app.position()?;
+    assert!(position.positions.is_empty());
+    Ok(())
+}
+
+#[test]
+fn reconcile_reduces_principal_by_stale_value() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let chain = carrot_app.environment().clone();
+
+    // Same weights, the core position only holds a tenth of the funds
+    create_position(&carrot_app, coins(90_000, USDT_DENOM.to_owned()))?;
+    carrot_app.create_position(CreatePositionMessage {
+        name: Some(CORE_POSITION.to_owned()),
+        weight: None,
+        range: PositionRange::Ticks {
+            lower_tick: INITIAL_LOWER_TICK,
+            upper_tick: INITIAL_UPPER_TICK,
+        },
+        funds: coins(10_000, USDT_DENOM),
+        max_spread: None,
+        belief_price0: None,
+        belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
+    })?;
+    let principal_before = carrot_app.performance()?.principal;
+
+    // Core position gets withdrawn outside of the app
+    let core_id = carrot_app
+        .position()?
+        .positions
+        .into_iter()
+        .find(|position| position.name == CORE_POSITION)
+        .unwrap()
+        .position_id;
+    {
+        let test_tube = chain.app.borrow();
+        let cl = ConcentratedLiquidity::new(&*test_tube);
+        let position = cl
+            .query_position_by_id(&PositionByIdRequest {
+                position_id: core_id,
+            })?
+            .position
+            .unwrap()
+            .position
+            .unwrap();
+        cl.withdraw_position(
+            MsgWithdrawPosition {
+                position_id: core_id,
+                sender: chain.sender_addr().to_string(),
+                liquidity_amount: position.liquidity,
+            },
+            &chain.sender,
+        )?;
+    }
+    carrot_app.reconcile()?;
+
+    // The position of the user created with the pool got adopted in its place
+    let balance: AssetsBalanceResponse = carrot_app.balance()?;
+    let adopted = &balance
+        .positions
+        .iter()
+        .find(|position| position.name == CORE_POSITION)
+        .unwrap()
+        .balances;
+    let principal_after = carrot_app.performance()?.principal;
+    for coin in principal_before {
+        let amount_of = |funds: &[Coin]| {
+            funds
+                .iter()
+                .find(|c| c.denom == coin.denom)
+                .map(|c| c.amount)
+                .unwrap_or_default()
+        };
+        let kept = amount_of(&principal_after) - amount_of(adopted);
+        // A tenth of the value left the app, weights would have removed half of the principal
+        assert!(kept >= coin.amount.mul_floor(Decimal::percent(85)));
+        assert!(kept <= coin.amount.mul_floor(Decimal::percent(95)));
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use crate::common::{
+    create_position, setup_test_tube, GAS_DENOM, INITIAL_LOWER_TICK, INITIAL_UPPER_TICK, LOTS,
+    USDC_DENOM, USDT_DENOM,
+};
+use carrot_app::error::AppError;
+use carrot_app::msg::{AppExecuteMsgFns, AppQueryMsgFns, CompoundStatus, PositionRange};
+use cosmwasm_std::{coins, Coin};
+use cw_orch::{anyhow, prelude::*};
+
+#[test]
+fn pause_rejects_deposits() -> anyhow::Result<()> {
+    let (pool_id, carrot_app) = setup_test_tube(true)?;
+    let mut chain = carrot_app.environment().clone();
+    let stranger = chain.init_account(coins(LOTS, GAS_DENOM))?;
+    let paused_err = AppError::Paused {}.to_string();
+
+    carrot_app.pause()?;
+    assert!(carrot_app.config()?.paused);
+
+    let deposit_err = carrot_app
+        .deposit(coins(5_000, USDT_DENOM), None, None, None, None, None)
+        .unwrap_err();
+    assert!(deposit_err.to_string().contains(&paused_err));
+    let autocompound_err = carrot_app.autocompound().unwrap_err();
+    assert!(autocompound_err.to_string().contains(&paused_err));
+    let create_position_err = create_position(&carrot_app, coins(5_000, USDT_DENOM)).unwrap_err();
+    assert!(create_position_err.to_string().contains(&paused_err));
+
+    let rebalance_err = carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)
+        .unwrap_err();
+    assert!(rebalance_err.to_string().contains(&paused_err));
+    let migrate_pool_err = carrot_app
+        .migrate_pool(
+            pool_id + 1,
+            PositionRange::Ticks {
+                lower_tick: INITIAL_LOWER_TICK,
+                upper_tick: INITIAL_UPPER_TICK,
+            },
+            None,
+            None,
+            None,
+        )
+        .unwrap_err();
+    assert!(migrate_pool_err.to_string().contains(&paused_err));
+    let import_err = carrot_app.import_position(1).unwrap_err();
+    assert!(import_err.to_string().contains(&paused_err));
+
+    // Withdraws still work
+    carrot_app.withdraw(None, None, None, None)?;
+
+    carrot_app.unpause()?;
+    assert!(!carrot_app.config()?.paused);
+    create_position(&carrot_app, coins(5_000, USDT_DENOM))?;
+    Ok(())
+}
+
+#[test]
+fn emergency_exit() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(true)?;
+    let chain = carrot_app.environment().clone();
+    let user = chain.sender_addr();
+
+    let balance_before = chain.bank_querier().balance(&user, None)?;
+    carrot_app.emergency_exit()?;
+
+    // Funds of the position landed in the wallet
+    let balance_after = chain.bank_querier().balance(&user, None)?;
+    for denom in [USDT_DENOM, USDC_DENOM] {
+        let amount = |balance: &Vec<Coin>| {
+            balance
+                .iter()
+                .find(|coin| coin.denom == denom)
+                .unwrap()
+                .amount
+        };
+        assert!(amount(&balance_after) > amount(&balance_before));
+    }
+
+    // Positions forgotten and app paused
+    assert!(carrot_app.position()?.positions.is_empty());
+    assert_eq!(
+        carrot_app.compound_status()?.status,
+        CompoundStatus::NoPosition {}
+    );
+    assert!(carrot_app.config()?.paused);
+    assert!(carrot_app.performance()?.principal.is_empty());
+
+    // Nothing left to exit
+    let no_position_err = carrot_app.emergency_exit().unwrap_err();
+    assert!(no_position_err
+        .to_string()
+        .contains(&AppError::NoPosition {}.to_string()));
+    Ok(())
+}
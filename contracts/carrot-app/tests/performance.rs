@@ -0,0 +1,133 @@
+mod common;
+
+use crate::common::{
+    create_position, generate_spread_rewards, setup_test_tube, REWARD_ASSET, USDC, USDT, USDT_DENOM,
+};
+use abstract_app::objects::AssetEntry;
+use carrot_app::msg::{AppExecuteMsgFns, AppQueryMsgFns, PerformanceResponse, WithdrawAmount};
+use cosmwasm_std::{coins, Decimal, Int256, Uint128};
+use cw_orch::{anyhow, prelude::*};
+
+#[test]
+fn performance_tracks_principal() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    let deposit_amount = 10_000;
+    create_position(&carrot_app, coins(deposit_amount, USDT_DENOM.to_owned()))?;
+
+    let performance: PerformanceResponse = carrot_app.performance()?;
+    assert_eq!(performance.principal, coins(deposit_amount, USDT_DENOM));
+    assert!(performance.compounded.is_empty());
+    // Only swap fees and dust got lost
+    let max_loss = Uint128::new(deposit_amount).mul_floor(Decimal::percent(3));
+    assert!(
+        performance
+            .principal_value
+            .abs_diff(performance.balance_value)
+            <= max_loss
+    );
+    assert_eq!(
+        performance.net_yield,
+        Int256::from(performance.balance_value.u128())
+            - Int256::from(performance.principal_value.u128())
+    );
+
+    // Deposits add to the principal
+    carrot_app.deposit(
+        coins(deposit_amount, USDT_DENOM),
+        None,
+        None,
+        None,
+        None,
+        None,
+    )?;
+    let performance: PerformanceResponse = carrot_app.performance()?;
+    assert_eq!(performance.principal, coins(deposit_amount * 2, USDT_DENOM));
+
+    // Withdrawing half of the positions removes half of the principal
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Percentage { bps: 5_000 }),
+        None,
+        None,
+        None,
+    )?;
+    let performance: PerformanceResponse = carrot_app.performance()?;
+    let principal = performance.principal[0].amount;
+    assert!(principal.abs_diff(Uint128::new(deposit_amount)) <= Uint128::new(2));
+
+    // Withdrawing everything clears it
+    carrot_app.withdraw(None, None, None, None)?;
+    let performance: PerformanceResponse = carrot_app.performance()?;
+    assert!(performance.principal.is_empty());
+    Ok(())
+}
+
+#[test]
+fn hodl_comparison() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
+
+    // Defaults to the second pool asset
+    let comparison = carrot_app.hodl_comparison(None)?;
+    assert_eq!(comparison.quote_asset, AssetEntry::new(USDC));
+    let performance: PerformanceResponse = carrot_app.performance()?;
+    assert_eq!(comparison.hodl_value, performance.principal_value);
+    assert_eq!(comparison.position_value, performance.balance_value);
+    assert!(comparison.fees_earned.is_zero());
+    assert_eq!(
+        comparison.lp_vs_hodl,
+        Int256::from(comparison.position_value.u128()) - Int256::from(comparison.hodl_value.u128())
+    );
+
+    // Valued in the first pool asset
+    let comparison_usdt = carrot_app.hodl_comparison(Some(AssetEntry::new(USDT)))?;
+    assert_eq!(comparison_usdt.quote_asset, AssetEntry::new(USDT));
+    assert!(!comparison_usdt.hodl_value.is_zero());
+
+    // Quote asset has to be a pool asset
+    let err = carrot_app
+        .hodl_comparison(Some(AssetEntry::new(REWARD_ASSET)))
+        .unwrap_err();
+    assert!(err.to_string().contains("is not an asset of the pool"));
+    Ok(())
+}
+
+#[test]
+fn hodl_comparison_after_partial_withdraw() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let chain = carrot_app.environment().clone();
+
+    create_position(&carrot_app, coins(100_000, USDT_DENOM.to_owned()))?;
+    generate_spread_rewards(&carrot_app)?;
+    chain.wait_seconds(300)?;
+    carrot_app.autocompound()?;
+    let compounded = carrot_app.performance()?.compounded;
+    assert!(!compounded.is_empty());
+
+    // Compounded rewards are withdrawn along with the principal
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Percentage { bps: 5_000 }),
+        None,
+        None,
+        None,
+    )?;
+    let performance: PerformanceResponse = carrot_app.performance()?;
+    for (before, after) in compounded.iter().zip(performance.compounded.iter()) {
+        assert_eq!(before.denom, after.denom);
+        assert!(after.amount.abs_diff(before.amount / Uint128::new(2)) <= Uint128::one());
+    }
+
+    // Positions still compare to holding the remaining principal,
+    // losses are only swap fees and dust
+    let comparison = carrot_app.hodl_comparison(None)?;
+    let max_loss = comparison.hodl_value.mul_floor(Decimal::percent(3));
+    assert!(comparison.impermanent_loss.unsigned_abs() <= max_loss.into());
+
+    // Nothing left after a full withdraw
+    carrot_app.withdraw(None, None, None, None)?;
+    let performance: PerformanceResponse = carrot_app.performance()?;
+    assert!(performance.principal.is_empty());
+    assert!(performance.compounded.is_empty());
+    Ok(())
+}
@@ -0,0 +1,395 @@
+mod common;
+
+use crate::common::{
+    setup_test_tube, update_config, ConfigUpdate, DEX_NAME, GAS_DENOM, LOTS, REWARD_DENOM, USDC,
+    USDC_DENOM, USDT, USDT_DENOM,
+};
+use abstract_app::abstract_interface::{Abstract, AbstractAccount};
+use abstract_client::Application;
+use carrot_app::error::AppError;
+use carrot_app::msg::{
+    AppExecuteMsgFns, AppQueryMsgFns, CreatePositionMessage, OptionalUpdate, PositionRange,
+    RebalanceStatus,
+};
+use carrot_app::AppInterface;
+use cosmwasm_std::{coin, coins, Uint128, Uint64};
+use cw_orch::{anyhow, prelude::*};
+use cw_orch_osmosis_test_tube::osmosis_test_tube::{
+    osmosis_std::types::osmosis::concentratedliquidity::v1beta1::{
+        Pool, PoolsRequest, PositionByIdRequest,
+    },
+    ConcentratedLiquidity, Module,
+};
+use prost::Message;
+
+const NARROW_LOWER_TICK: i64 = -1000;
+const NARROW_UPPER_TICK: i64 = 1000;
+
+fn create_narrow_position<Chain: CwEnv>(
+    carrot_app: &Application<Chain, AppInterface<Chain>>,
+) -> anyhow::Result<()> {
+    carrot_app.create_position(CreatePositionMessage {
+        name: None,
+        weight: None,
+        range: PositionRange::Ticks {
+            lower_tick: NARROW_LOWER_TICK,
+            upper_tick: NARROW_UPPER_TICK,
+        },
+        funds: coins(100_000, USDT_DENOM),
+        max_spread: None,
+        belief_price0: None,
+        belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
+    })?;
+    Ok(())
+}
+
+// Swap big enough amount to push the price above the range of the narrow position
+fn push_price_out_of_range<Chain: CwEnv>(
+    carrot_app: &Application<Chain, AppInterface<Chain>>,
+) -> anyhow::Result<()> {
+    let chain = carrot_app.environment().clone();
+    let dex: abstract_dex_adapter::interface::DexAdapter<_> = carrot_app.module()?;
+    let abs = Abstract::load_from(chain.clone())?;
+    let account = AbstractAccount::new(&abs, carrot_app.account().id()?);
+    chain.bank_send(
+        account.proxy.addr_str()?,
+        vec![coin(3_000_000, USDC_DENOM.to_owned())],
+    )?;
+    dex.ans_swap(
+        (USDC, 3_000_000),
+        USDT,
+        DEX_NAME.to_string(),
+        &account,
+        &abs.ans_host,
+    )?;
+    Ok(())
+}
+
+// Swaps `offer_asset` into `ask_asset` in small steps, until the current tick of the pool satisfies `done`
+fn swap_until<Chain: CwEnv>(
+    carrot_app: &Application<Chain, AppInterface<Chain>>,
+    (offer_asset, offer_denom): (&str, &str),
+    ask_asset: &str,
+    done: impl Fn(i64) -> bool,
+) -> anyhow::Result<()> {
+    let chain = carrot_app.environment().clone();
+    let dex: abstract_dex_adapter::interface::DexAdapter<_> = carrot_app.module()?;
+    let abs = Abstract::load_from(chain.clone())?;
+    let account = AbstractAccount::new(&abs, carrot_app.account().id()?);
+    for _ in 0..50 {
+        if done(carrot_app.position_details()?.current_tick) {
+            return Ok(());
+        }
+        chain.bank_send(
+            account.proxy.addr_str()?,
+            vec![coin(20_000, offer_denom.to_owned())],
+        )?;
+        dex.ans_swap(
+            (offer_asset, 20_000),
+            ask_asset,
+            DEX_NAME.to_string(),
+            &account,
+            &abs.ans_host,
+        )?;
+    }
+    anyhow::bail!("Pool tick didn't reach the target")
+}
+
+#[test]
+fn rebalance_out_of_range_position() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let chain = carrot_app.environment().clone();
+
+    // Create narrow position
+    create_narrow_position(&carrot_app)?;
+    let position_id = carrot_app.position()?.positions[0].position_id;
+
+    // Position is in range, nothing to rebalance
+    let in_range_err = carrot_app.rebalance(None, None, None, None).unwrap_err();
+    assert!(in_range_err
+        .to_string()
+        .contains(&AppError::PositionInRange {}.to_string()));
+
+    push_price_out_of_range(&carrot_app)?;
+
+    carrot_app.rebalance(None, None, None, None)?;
+
+    // New position got created around the current tick
+    let new_position_id = carrot_app.position()?.positions[0].position_id;
+    assert_ne!(position_id, new_position_id);
+
+    let test_tube = chain.app.borrow();
+    let cl = ConcentratedLiquidity::new(&*test_tube);
+    let pools = cl.query_pools(&PoolsRequest { pagination: None })?;
+    let pool = Pool::decode(pools.pools[0].value.as_slice())?;
+    let new_position = cl
+        .query_position_by_id(&PositionByIdRequest {
+            position_id: new_position_id,
+        })?
+        .position
+        .unwrap()
+        .position
+        .unwrap();
+    assert!(new_position.lower_tick <= pool.current_tick);
+    assert!(pool.current_tick < new_position.upper_tick);
+    assert_eq!(
+        new_position.upper_tick - new_position.lower_tick,
+        NARROW_UPPER_TICK - NARROW_LOWER_TICK
+    );
+    Ok(())
+}
+
+#[test]
+fn stranger_rebalance_after_grace_period() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+    let stranger = chain.init_account(coins(LOTS, GAS_DENOM))?;
+
+    create_narrow_position(&carrot_app)?;
+    let position_id = carrot_app.position()?.positions[0].position_id;
+
+    let status = carrot_app.rebalance_status()?;
+    assert_eq!(status.positions[0].status, RebalanceStatus::InRange {});
+    assert!(status.permissionless);
+
+    push_price_out_of_range(&carrot_app)?;
+    let status = carrot_app.rebalance_status()?;
+    assert_eq!(status.positions[0].status, RebalanceStatus::OutOfRange {});
+
+    // First call only starts the grace period
+    carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)?;
+    assert_eq!(carrot_app.position()?.positions[0].position_id, position_id);
+    let RebalanceStatus::GracePeriod(seconds_left) =
+        carrot_app.rebalance_status()?.positions[0].status.clone()
+    else {
+        panic!("Position should be in the grace period")
+    };
+    assert!(seconds_left <= Uint64::new(300));
+
+    // Stranger can't rebalance during grace period
+    let grace_period_err = carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)
+        .unwrap_err();
+    assert!(grace_period_err
+        .to_string()
+        .contains("can be rebalanced by anyone in"));
+
+    chain.wait_seconds(300)?;
+    let status = carrot_app.rebalance_status()?;
+    assert_eq!(status.positions[0].status, RebalanceStatus::Ready {});
+
+    carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)?;
+    let new_position_id = carrot_app.position()?.positions[0].position_id;
+    assert_ne!(position_id, new_position_id);
+    let status = carrot_app.rebalance_status()?;
+    assert_eq!(status.positions[0].status, RebalanceStatus::InRange {});
+
+    // Check stranger gets rewarded
+    let stranger_reward_balance = chain.query_balance(stranger.address().as_str(), REWARD_DENOM)?;
+    assert_eq!(stranger_reward_balance, Uint128::new(1000));
+    Ok(())
+}
+
+#[test]
+fn stranger_rebalance_disabled_without_grace_period() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+    let stranger = chain.init_account(coins(LOTS, GAS_DENOM))?;
+
+    create_narrow_position(&carrot_app)?;
+    push_price_out_of_range(&carrot_app)?;
+
+    // Only the admin can rebalance once the grace period is cleared
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            rebalance_grace_period_seconds: Some(OptionalUpdate::Clear {}),
+            ..Default::default()
+        },
+    )?;
+    assert!(carrot_app
+        .config()?
+        .rebalance_grace_period_seconds
+        .is_none());
+    assert!(!carrot_app.rebalance_status()?.permissionless);
+    let unauthorized_err = carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)
+        .unwrap_err();
+    assert!(unauthorized_err
+        .to_string()
+        .contains(&AppError::Unauthorized {}.to_string()));
+
+    carrot_app.rebalance(None, None, None, None)?;
+    Ok(())
+}
+
+#[test]
+fn grace_period_restarts_after_back_in_range() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+    let stranger = chain.init_account(coins(LOTS, GAS_DENOM))?;
+
+    create_narrow_position(&carrot_app)?;
+
+    // Out of range, grace period started
+    swap_until(&carrot_app, (USDC, USDC_DENOM), USDT, |tick| {
+        tick >= NARROW_UPPER_TICK
+    })?;
+    carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)?;
+    assert!(matches!(
+        carrot_app.rebalance_status()?.positions[0].status,
+        RebalanceStatus::GracePeriod(_)
+    ));
+
+    // Back in range and seen by a deposit
+    swap_until(&carrot_app, (USDT, USDT_DENOM), USDC, |tick| {
+        tick < NARROW_UPPER_TICK
+    })?;
+    assert_eq!(
+        carrot_app.rebalance_status()?.positions[0].status,
+        RebalanceStatus::InRange {}
+    );
+    carrot_app.deposit(coins(1_000, USDT_DENOM), None, None, None, None, None)?;
+    // Deposit replaces the position
+    let position_id = carrot_app.position()?.positions[0].position_id;
+
+    // Out of range again long after, the grace period starts over
+    chain.wait_seconds(600)?;
+    swap_until(&carrot_app, (USDC, USDC_DENOM), USDT, |tick| {
+        tick >= NARROW_UPPER_TICK
+    })?;
+    assert_eq!(
+        carrot_app.rebalance_status()?.positions[0].status,
+        RebalanceStatus::OutOfRange {}
+    );
+    carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)?;
+    assert_eq!(carrot_app.position()?.positions[0].position_id, position_id);
+    assert!(matches!(
+        carrot_app.rebalance_status()?.positions[0].status,
+        RebalanceStatus::GracePeriod(_)
+    ));
+    Ok(())
+}
+
+#[test]
+fn grace_period_restarts_when_back_in_range_unnoticed() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+    let stranger = chain.init_account(coins(LOTS, GAS_DENOM))?;
+
+    create_narrow_position(&carrot_app)?;
+    let position_id = carrot_app.position()?.positions[0].position_id;
+
+    // Out of range, grace period started
+    swap_until(&carrot_app, (USDC, USDC_DENOM), USDT, |tick| {
+        tick >= NARROW_UPPER_TICK
+    })?;
+    carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)?;
+
+    // Back in range without the app seeing it, for the whole grace period
+    swap_until(&carrot_app, (USDT, USDT_DENOM), USDC, |tick| {
+        tick < NARROW_UPPER_TICK
+    })?;
+    chain.wait_seconds(300)?;
+
+    // Out of range again, the old grace period doesn't count
+    swap_until(&carrot_app, (USDC, USDC_DENOM), USDT, |tick| {
+        tick >= NARROW_UPPER_TICK
+    })?;
+    carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)?;
+    assert_eq!(carrot_app.position()?.positions[0].position_id, position_id);
+    assert!(matches!(
+        carrot_app.rebalance_status()?.positions[0].status,
+        RebalanceStatus::GracePeriod(_)
+    ));
+    let grace_period_err = carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)
+        .unwrap_err();
+    assert!(grace_period_err
+        .to_string()
+        .contains("can be rebalanced by anyone in"));
+
+    // Rebalanced once out of range for the new grace period
+    chain.wait_seconds(300)?;
+    carrot_app
+        .call_as(&stranger)
+        .rebalance(None, None, None, None)?;
+    assert_ne!(carrot_app.position()?.positions[0].position_id, position_id);
+    assert_eq!(
+        carrot_app.rebalance_status()?.positions[0].status,
+        RebalanceStatus::InRange {}
+    );
+    Ok(())
+}
+
+#[test]
+fn zero_grace_period_rejected() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    let err = update_config(
+        &carrot_app,
+        ConfigUpdate {
+            rebalance_grace_period_seconds: Some(OptionalUpdate::Set(Uint64::zero())),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains(
+        &AppError::RebalanceConfigError(
+            "rebalance_grace_period_seconds has to be bigger than 0".to_owned()
+        )
+        .to_string()
+    ));
+    Ok(())
+}
+
+#[test]
+fn position_details() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let chain = carrot_app.environment().clone();
+
+    create_narrow_position(&carrot_app)?;
+    chain.wait_seconds(10)?;
+
+    let details = carrot_app.position_details()?;
+    assert_eq!(details.positions.len(), 1);
+    let position = &details.positions[0];
+    assert_eq!(
+        (position.lower_tick, position.upper_tick),
+        (NARROW_LOWER_TICK, NARROW_UPPER_TICK)
+    );
+    assert!(position.lower_price < details.current_price);
+    assert!(details.current_price < position.upper_price);
+    assert!(position.in_range);
+    assert!(!position.liquidity.is_zero());
+    assert_eq!(
+        position.position_id,
+        carrot_app.position()?.positions[0].position_id
+    );
+    assert!(details.seconds_since_last_compound.unwrap() >= 10);
+
+    // Out of range once the price moved above the range
+    push_price_out_of_range(&carrot_app)?;
+    let details = carrot_app.position_details()?;
+    assert!(details.current_tick >= NARROW_UPPER_TICK);
+    assert!(!details.positions[0].in_range);
+    assert!(!details.positions[0].claimable_spread_rewards.is_empty());
+    Ok(())
+}
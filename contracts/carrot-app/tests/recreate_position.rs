@@ -9,11 +9,11 @@ use abstract_client::{AbstractClient, Environment as _};
 use carrot_app::error::AppError;
 use carrot_app::msg::{
     AppExecuteMsgFns, AppInstantiateMsg, AppQueryMsgFns, AssetsBalanceResponse,
-    CreatePositionMessage, PositionResponse,
+    CreatePositionMessage, PositionRange, PositionResponse, WithdrawAmount,
 };
 use carrot_app::state::AutocompoundRewardsConfig;
 use common::REWARD_ASSET;
-use cosmwasm_std::{coin, coins, Uint128, Uint256, Uint64};
+use cosmwasm_std::{coins, Uint128, Uint256, Uint64};
 use cw_orch::{anyhow, prelude::*};
 use cw_orch_osmosis_test_tube::osmosis_test_tube::osmosis_std::types::osmosis::concentratedliquidity::v1beta1::PositionByIdRequest;
 use cw_orch_osmosis_test_tube::{
@@ -29,21 +29,11 @@ fn create_multiple_positions() -> anyhow::Result<()> {
     let (_, carrot_app) = setup_test_tube(false)?;
 
     // Create position
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     // Create position second time, it should fail
-    let position_err = create_position(
-        &carrot_app,
-        coins(5_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )
-    .unwrap_err();
+    let position_err =
+        create_position(&carrot_app, coins(5_000, USDT_DENOM.to_owned())).unwrap_err();
 
     assert!(position_err
         .to_string()
@@ -56,27 +46,22 @@ fn create_multiple_positions_after_withdraw() -> anyhow::Result<()> {
     let (_, carrot_app) = setup_test_tube(false)?;
 
     // Create position
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     // Withdraw half of liquidity
     let balance: AssetsBalanceResponse = carrot_app.balance()?;
     let liquidity_amount: Uint256 = balance.liquidity.parse().unwrap();
     let half_of_liquidity = liquidity_amount / Uint256::from_u128(2);
-    carrot_app.withdraw(Some(half_of_liquidity), None)?;
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Liquidity(half_of_liquidity)),
+        None,
+        None,
+        None,
+    )?;
 
     // Create position second time, it should fail
-    let position_err = create_position(
-        &carrot_app,
-        coins(5_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )
-    .unwrap_err();
+    let position_err =
+        create_position(&carrot_app, coins(5_000, USDT_DENOM.to_owned())).unwrap_err();
 
     assert!(position_err
         .to_string()
@@ -85,15 +70,15 @@ fn create_multiple_positions_after_withdraw() -> anyhow::Result<()> {
     // Withdraw whole liquidity
     let balance: AssetsBalanceResponse = carrot_app.balance()?;
     let liquidity_amount: Uint256 = balance.liquidity.parse().unwrap();
-    carrot_app.withdraw(Some(liquidity_amount), None)?;
+    carrot_app.withdraw(
+        Some(WithdrawAmount::Liquidity(liquidity_amount)),
+        None,
+        None,
+        None,
+    )?;
 
     // Create position second time, it should fail
-    create_position(
-        &carrot_app,
-        coins(5_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(5_000, USDT_DENOM.to_owned()))?;
 
     Ok(())
 }
@@ -103,23 +88,13 @@ fn create_multiple_positions_after_withdraw_all() -> anyhow::Result<()> {
     let (_, carrot_app) = setup_test_tube(false)?;
 
     // Create position
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     // Withdraw whole liquidity
-    carrot_app.withdraw(None, None)?;
+    carrot_app.withdraw(None, None, None, None)?;
 
     // Create position second time, it should succeed
-    create_position(
-        &carrot_app,
-        coins(5_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(5_000, USDT_DENOM.to_owned()))?;
     Ok(())
 }
 
@@ -134,7 +109,7 @@ fn create_position_after_user_withdraw_liquidity_manually() -> anyhow::Result<()
     let cl = ConcentratedLiquidity::new(&*test_tube);
     let position_breakdown = cl
         .query_position_by_id(&PositionByIdRequest {
-            position_id: position.position_id.unwrap(),
+            position_id: position.positions[0].position_id,
         })?
         .position
         .unwrap();
@@ -150,15 +125,10 @@ fn create_position_after_user_withdraw_liquidity_manually() -> anyhow::Result<()
     )?;
 
     // Create position, ignoring it was manually withdrawn
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     let position = carrot_app.position()?;
-    assert!(position.position_id.is_some());
+    assert!(!position.positions.is_empty());
     Ok(())
 }
 
@@ -181,6 +151,14 @@ fn install_on_sub_account() -> anyhow::Result<()> {
             min_gas_balance: Uint128::new(2000),
             max_gas_balance: Uint128::new(10000),
         },
+        rebalance_grace_period_seconds: None,
+        twap_config: None,
+        incentives_swap_allowlist: None,
+        autocompound_mode: None,
+        compound_ratio: None,
+        payout: None,
+        performance_fee: None,
+        autocompound_max_spread: None,
         create_position: None,
     };
 
@@ -198,15 +176,10 @@ fn install_on_sub_account() -> anyhow::Result<()> {
     let carrot_app = account.application::<carrot_app::AppInterface<_>>()?;
 
     give_authorizations(&client, carrot_app.addr_str()?)?;
-    create_position(
-        &carrot_app,
-        coins(10_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDT_DENOM.to_owned()),
-        coin(1_000_000, USDC_DENOM.to_owned()),
-    )?;
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
 
     let position: PositionResponse = carrot_app.position()?;
-    assert!(position.position_id.is_some());
+    assert!(!position.positions.is_empty());
     Ok(())
 }
 
@@ -234,15 +207,27 @@ fn install_on_sub_account_create_position_on_install() -> anyhow::Result<()> {
             min_gas_balance: Uint128::new(1_000_000),
             max_gas_balance: Uint128::new(3_000_000),
         },
+        rebalance_grace_period_seconds: None,
+        twap_config: None,
+        incentives_swap_allowlist: None,
+        autocompound_mode: None,
+        compound_ratio: None,
+        payout: None,
+        performance_fee: None,
+        autocompound_max_spread: None,
         create_position: Some(CreatePositionMessage {
-            lower_tick: INITIAL_LOWER_TICK,
-            upper_tick: INITIAL_UPPER_TICK,
+            name: None,
+            weight: None,
+            range: PositionRange::Ticks {
+                lower_tick: INITIAL_LOWER_TICK,
+                upper_tick: INITIAL_UPPER_TICK,
+            },
             funds: coins(100_000, USDC_DENOM),
-            asset0: coin(1_000_672_899, USDT_DENOM),
-            asset1: coin(10_000_000_000, USDC_DENOM),
             max_spread: None,
             belief_price0: None,
             belief_price1: None,
+            token_min_amount0: None,
+            token_min_amount1: None,
         }),
     };
 
@@ -260,6 +245,6 @@ fn install_on_sub_account_create_position_on_install() -> anyhow::Result<()> {
     let carrot_app = account.application::<carrot_app::AppInterface<_>>()?;
 
     let position: PositionResponse = carrot_app.position()?;
-    assert!(position.position_id.is_some());
+    assert!(!position.positions.is_empty());
     Ok(())
 }
@@ -0,0 +1,116 @@
+mod common;
+
+use crate::common::{
+    create_position, setup_test_tube, INITIAL_LOWER_TICK, INITIAL_UPPER_TICK, REWARD_DENOM, USDT,
+    USDT_DENOM,
+};
+use abstract_app::objects::AssetEntry;
+use carrot_app::msg::{
+    AppExecuteMsgFns, AppQueryMsgFns, AssetsBalanceResponse, CreatePositionMessage, PositionRange,
+    WithdrawAmount,
+};
+use cosmwasm_std::{coins, Decimal, Uint128, Uint256};
+use cw_orch::{anyhow, prelude::*};
+
+#[test]
+fn simulate_create_position() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    let deposit_amount = 10_000;
+    let simulation = carrot_app.simulate_create_position(CreatePositionMessage {
+        name: None,
+        weight: None,
+        range: PositionRange::Ticks {
+            lower_tick: INITIAL_LOWER_TICK,
+            upper_tick: INITIAL_UPPER_TICK,
+        },
+        funds: coins(deposit_amount, USDT_DENOM),
+        max_spread: None,
+        belief_price0: None,
+        belief_price1: None,
+        token_min_amount0: None,
+        token_min_amount1: None,
+    })?;
+    assert_eq!(
+        (simulation.lower_tick, simulation.upper_tick),
+        (INITIAL_LOWER_TICK, INITIAL_UPPER_TICK)
+    );
+    // Part of the USDT gets swapped into USDC
+    let swap = simulation.position.swap.unwrap();
+    assert_eq!(swap.offer_asset, AssetEntry::new(USDT));
+    assert!(swap.amount < Uint128::new(deposit_amount));
+
+    create_position(&carrot_app, coins(deposit_amount, USDT_DENOM))?;
+
+    // Simulated liquidity is within 1% of the position
+    let balance: AssetsBalanceResponse = carrot_app.balance()?;
+    let liquidity: Uint256 = balance.liquidity.parse()?;
+    let simulated_liquidity: Uint256 = simulation.position.liquidity.parse()?;
+    assert!(liquidity.abs_diff(simulated_liquidity) <= liquidity / Uint256::from_u128(100));
+    Ok(())
+}
+
+#[test]
+fn simulate_deposit() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    let deposit_amount = 10_000;
+    create_position(&carrot_app, coins(deposit_amount, USDT_DENOM))?;
+    let balance_before: AssetsBalanceResponse = carrot_app.balance()?;
+
+    // Zap from the reward token
+    let simulation =
+        carrot_app.simulate_deposit(coins(deposit_amount, REWARD_DENOM), None, None, None)?;
+    assert!(!simulation.zap_swaps.is_empty());
+    assert_eq!(simulation.positions.len(), 1);
+
+    let mut chain = carrot_app.environment().clone();
+    chain.add_balance(chain.sender_addr(), coins(deposit_amount, REWARD_DENOM))?;
+    carrot_app.deposit(
+        coins(deposit_amount, REWARD_DENOM),
+        None,
+        None,
+        None,
+        None,
+        None,
+    )?;
+
+    // Simulated liquidity is within 1% of the deposited liquidity
+    let balance: AssetsBalanceResponse = carrot_app.balance()?;
+    let deposited_liquidity =
+        balance.liquidity.parse::<Uint256>()? - balance_before.liquidity.parse::<Uint256>()?;
+    let simulated_liquidity: Uint256 = simulation.positions[0].liquidity.parse()?;
+    assert!(
+        deposited_liquidity.abs_diff(simulated_liquidity)
+            <= deposited_liquidity / Uint256::from_u128(100)
+    );
+    Ok(())
+}
+
+#[test]
+fn simulate_withdraw() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+
+    create_position(&carrot_app, coins(10_000, USDT_DENOM))?;
+    let balance: AssetsBalanceResponse = carrot_app.balance()?;
+
+    // Full withdraw returns the whole balance
+    let simulation = carrot_app.simulate_withdraw(None, None)?;
+    assert_eq!(simulation.withdrawn, balance.balances);
+    assert_eq!(
+        simulation.positions[0].liquidity_amount,
+        simulation.positions[0].total_liquidity
+    );
+
+    // Half withdraw returns half of the balance
+    let liquidity: Uint256 = balance.liquidity.parse()?;
+    let simulation = carrot_app.simulate_withdraw(
+        Some(WithdrawAmount::Liquidity(liquidity / Uint256::from_u128(2))),
+        None,
+    )?;
+    for (withdrawn, balance) in simulation.withdrawn.iter().zip(balance.balances.iter()) {
+        let half = balance.amount.mul_floor(Decimal::percent(50));
+        assert!(withdrawn.amount.abs_diff(half) <= Uint128::one());
+    }
+    Ok(())
+}
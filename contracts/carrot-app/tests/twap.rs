@@ -0,0 +1,138 @@
+mod common;
+
+use crate::common::{
+    create_position, setup_test_tube, update_config, ConfigUpdate, DEX_NAME, REWARD_ASSET,
+    REWARD_DENOM, USDC, USDT, USDT_DENOM,
+};
+use abstract_app::abstract_interface::{Abstract, AbstractAccount};
+use carrot_app::msg::{AppExecuteMsgFns, AppQueryMsgFns, OptionalUpdate};
+use carrot_app::state::TwapConfig;
+use cosmwasm_std::{coin, coins, Decimal, Uint64};
+use cw_orch::{anyhow, prelude::*};
+
+const TWAP_WINDOW_SECONDS: u64 = 300;
+
+#[test]
+fn deposit_rejected_after_price_manipulation() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let chain = carrot_app.environment().clone();
+
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
+
+    // Invalid deviation
+    let config_err = update_config(
+        &carrot_app,
+        ConfigUpdate {
+            twap_config: Some(OptionalUpdate::Set(TwapConfig {
+                window_seconds: Uint64::new(TWAP_WINDOW_SECONDS),
+                max_deviation: Decimal::zero(),
+            })),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(config_err.to_string().contains("TWAP configuration error"));
+
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            twap_config: Some(OptionalUpdate::Set(TwapConfig {
+                window_seconds: Uint64::new(TWAP_WINDOW_SECONDS),
+                max_deviation: Decimal::percent(10),
+            })),
+            ..Default::default()
+        },
+    )?;
+    let config = carrot_app.config()?;
+    assert_eq!(
+        config.twap_config.unwrap().max_deviation,
+        Decimal::percent(10)
+    );
+
+    // Let the pool build up its price history
+    chain.wait_seconds(TWAP_WINDOW_SECONDS * 2)?;
+
+    // Price is in line with the TWAP
+    carrot_app.deposit(coins(10_000, USDT_DENOM), None, None, None, None, None)?;
+
+    // Move the price far from its average
+    let dex: abstract_dex_adapter::interface::DexAdapter<_> = carrot_app.module()?;
+    let abs = Abstract::load_from(chain.clone())?;
+    let account = AbstractAccount::new(&abs, carrot_app.account().id()?);
+    chain.bank_send(
+        account.proxy.addr_str()?,
+        vec![coin(5_000_000, USDT_DENOM.to_owned())],
+    )?;
+    dex.ans_swap(
+        (USDT, 5_000_000),
+        USDC,
+        DEX_NAME.to_string(),
+        &account,
+        &abs.ans_host,
+    )?;
+
+    let deposit_err = carrot_app
+        .deposit(coins(10_000, USDT_DENOM), None, None, None, None, None)
+        .unwrap_err();
+    assert!(deposit_err
+        .to_string()
+        .contains("deviates too much from its TWAP"));
+
+    // Deposits go through again without the guard
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            twap_config: Some(OptionalUpdate::Clear {}),
+            ..Default::default()
+        },
+    )?;
+    assert!(carrot_app.config()?.twap_config.is_none());
+    carrot_app.deposit(coins(10_000, USDT_DENOM), None, None, None, None, None)?;
+    Ok(())
+}
+
+#[test]
+fn zap_rejected_after_price_manipulation() -> anyhow::Result<()> {
+    let (_, carrot_app) = setup_test_tube(false)?;
+    let mut chain = carrot_app.environment().clone();
+
+    create_position(&carrot_app, coins(10_000, USDT_DENOM.to_owned()))?;
+    update_config(
+        &carrot_app,
+        ConfigUpdate {
+            twap_config: Some(OptionalUpdate::Set(TwapConfig {
+                window_seconds: Uint64::new(TWAP_WINDOW_SECONDS),
+                max_deviation: Decimal::percent(10),
+            })),
+            ..Default::default()
+        },
+    )?;
+
+    // Let the pools build up their price history
+    chain.wait_seconds(TWAP_WINDOW_SECONDS * 2)?;
+
+    // Price of the reward pool the zap swap goes through is in line with its TWAP
+    chain.add_balance(chain.sender_addr(), coins(20_000, REWARD_DENOM))?;
+    carrot_app.deposit(coins(10_000, REWARD_DENOM), None, None, None, None, None)?;
+
+    // Move the price of the reward pool far from its average
+    let dex: abstract_dex_adapter::interface::DexAdapter<_> = carrot_app.module()?;
+    let abs = Abstract::load_from(chain.clone())?;
+    let account = AbstractAccount::new(&abs, carrot_app.account().id()?);
+    chain.add_balance(account.proxy.addr_str()?, coins(500_000_000, REWARD_DENOM))?;
+    dex.ans_swap(
+        (REWARD_ASSET, 500_000_000),
+        USDC,
+        DEX_NAME.to_string(),
+        &account,
+        &abs.ans_host,
+    )?;
+
+    let deposit_err = carrot_app
+        .deposit(coins(10_000, REWARD_DENOM), None, None, None, None, None)
+        .unwrap_err();
+    assert!(deposit_err
+        .to_string()
+        .contains("deviates too much from its TWAP"));
+    Ok(())
+}